@@ -42,6 +42,47 @@ lazy_static! {
     };
 }
 
+impl SignData {
+    /// Build the signature data from the (r, s) signature, the public key of
+    /// the signer and the message hash reduced into the scalar field.
+    pub fn new(
+        signature: (secp256k1::Fq, secp256k1::Fq),
+        pk: Secp256k1Affine,
+        msg_hash: secp256k1::Fq,
+    ) -> Self {
+        Self {
+            signature,
+            pk,
+            msg_hash,
+        }
+    }
+
+    /// Verify the signature natively: with `u1 = msg_hash / s` and
+    /// `u2 = r / s`, the x coordinate of `u1 * G + u2 * pk` reduced into the
+    /// scalar field must equal `r`.
+    pub fn verify(&self) -> bool {
+        let (sig_r, sig_s) = self.signature;
+        if sig_r == secp256k1::Fq::zero() {
+            return false;
+        }
+        let s_inv = match Option::<secp256k1::Fq>::from(sig_s.invert()) {
+            Some(s_inv) => s_inv,
+            None => return false,
+        };
+        let u_1 = self.msg_hash * s_inv;
+        let u_2 = sig_r * s_inv;
+        let r_point = (Secp256k1Affine::generator() * u_1 + self.pk * u_2).to_affine();
+        let x = match Option::<Coordinates<_>>::from(r_point.coordinates()) {
+            Some(coords) => *coords.x(),
+            None => return false,
+        };
+
+        let mut x_bytes = [0u8; 64];
+        x_bytes[..32].copy_from_slice(&x.to_bytes());
+        secp256k1::Fq::from_bytes_wide(&x_bytes) == sig_r
+    }
+}
+
 impl Default for SignData {
     fn default() -> Self {
         // Hardcoded valid signature corresponding to a hardcoded private key and
@@ -0,0 +1,47 @@
+//! proving and verifying time of the prover circuit per k, run with
+//! `cargo bench --features bench`
+//!
+//! `StateTrieCircuit` is covered by `benches/state_trie.rs`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use halo2_proofs::{
+    halo2curves::bn256::{Bn256, Fr},
+    poly::kzg::commitment::ParamsKZG,
+};
+use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+use zkprover_mpt_circuits::{
+    verifier::{circuit_deploy::keygen, gen_proof, halo2_verify::verify},
+    zkprover_circuit::ZkProverCircuit,
+};
+
+const TX_NUM: usize = 2;
+const KS: [u32; 3] = [7, 9, 11];
+
+/// deterministic params of degree `k`, so runs are comparable
+fn params(k: u32) -> ParamsKZG<Bn256> {
+    ParamsKZG::setup(k, ChaCha8Rng::from_seed([k as u8; 32]))
+}
+
+fn bench_zkprover_circuit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zkprover_circuit");
+    group.sample_size(10);
+    for k in KS {
+        let params = params(k);
+        let circuit = ZkProverCircuit::<Fr, TX_NUM>::fixture_circuit();
+        let instances = circuit.instances();
+        let pk = keygen(&params, circuit.clone()).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("prove", k), &k, |b, _| {
+            b.iter(|| gen_proof(&params, &pk, circuit.clone(), instances.clone()).unwrap())
+        });
+
+        let proof = gen_proof(&params, &pk, circuit.clone(), instances.clone()).unwrap();
+        group.bench_with_input(BenchmarkId::new("verify", k), &k, |b, _| {
+            b.iter(|| verify(&params, pk.get_vk(), &instances, &proof).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_zkprover_circuit);
+criterion_main!(benches);
@@ -0,0 +1,110 @@
+//! time of adding a batch of ops into the state trie, where the hash traces
+//! of every op are completed; compare the runs of
+//! `cargo bench --features bench --bench state_trie` with and without the
+//! `parallel` feature
+//!
+//! and time of synthesizing a batch of ops in `StateTrieCircuit`, whose ops
+//! are assigned within the single "main" region, against the layout before
+//! where every block of the ops enters a region of its own
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
+    dev::MockProver,
+    halo2curves::bn256::Fr,
+    plonk::{Circuit, ConstraintSystem, Error},
+};
+use zkprover_mpt_circuits::{
+    operation::AccountOp,
+    state_trie::{StateTrie, StateTrieCircuit, StateTrieConfig},
+    test_utils::{rand_account_ops, rand_gen},
+};
+
+const OPS: usize = 256;
+const ASSIGNED_OPS: usize = 100;
+
+/// the ops with the traces of both accounts dropped, so adding them has to
+/// recalculate all of the traces
+fn untraced_ops() -> Vec<AccountOp<Fr>> {
+    let mut rng = rand_gen([7u8; 32]);
+    rand_account_ops::<Fr>(&mut rng, OPS)
+        .into_iter()
+        .map(|mut op| {
+            op.account_before.hash_traces.clear();
+            op.account_after.hash_traces.clear();
+            op
+        })
+        .collect()
+}
+
+/// a circuit assigning the ops of `trie` in the layout before, see
+/// `StateTrieConfig::synthesize_per_op`
+#[derive(Clone, Default)]
+struct PerOpCircuit {
+    trie: StateTrie<Fr>,
+    rows: usize,
+}
+
+impl Circuit<Fr> for PerOpCircuit {
+    type Config = StateTrieConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fr>) -> Self::Config {
+        let hash_tbl = [0; 5].map(|_| meta.advice_column());
+        let mpt_tbl = [0; 7].map(|_| meta.advice_column());
+        StateTrieConfig::configure_sub(meta, mpt_tbl, hash_tbl)
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fr>,
+    ) -> Result<(), Error> {
+        config.synthesize_per_op(&mut layouter, self.trie.get_ops().iter(), self.rows)
+    }
+}
+
+fn bench_add_ops(c: &mut Criterion) {
+    let ops = untraced_ops();
+    c.bench_function("state_trie_add_256_ops", |b| {
+        b.iter_batched(
+            || ops.clone(),
+            |ops| StateTrie::<Fr>::default().add_ops(ops),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_assign_ops(c: &mut Criterion) {
+    let mut trie = StateTrie::<Fr>::default();
+    trie.add_ops(rand_account_ops::<Fr>(
+        &mut rand_gen([11u8; 32]),
+        ASSIGNED_OPS,
+    ));
+    let rows = trie.required_rows();
+    // the layout before places the blocks after the layer region, which
+    // takes about twice the rows
+    let k = trie.min_k() + 1;
+    let per_op = PerOpCircuit {
+        trie: trie.clone(),
+        rows,
+    };
+    let single: StateTrieCircuit<Fr> = trie.circuits(rows, &[]);
+
+    let mut group = c.benchmark_group("state_trie_assign_100_ops");
+    group.sample_size(10);
+    group.bench_function("per_op_regions", |b| {
+        b.iter(|| MockProver::<Fr>::run(k, &per_op, vec![]).unwrap())
+    });
+    group.bench_function("single_region", |b| {
+        b.iter(|| MockProver::<Fr>::run(k, &single, vec![]).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_add_ops, bench_assign_ops);
+criterion_main!(benches);
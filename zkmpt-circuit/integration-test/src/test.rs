@@ -1,37 +1,21 @@
-use halo2_proofs::dev::MockProver;
-pub use halo2_proofs::halo2curves::bn256::Fr as Fp;
-use hash_circuit::Hashable;
-use serde::Deserialize;
-use std::fs::File;
-use std::io::Read;
-use zkprover_mpt_circuits::state_trie::StateTrie;
-use zkprover_mpt_circuits::{
-    operation::AccountOp,
-    serde::{BlockResult, MPTTransTrace},
-};
+use halo2_proofs::{dev::MockProver, halo2curves::bn256::Fr as Fp};
+use std::path::Path;
+use zkprover_mpt_circuits::utils::validate_trace_file;
 
+#[test]
 fn integration_test() {
-    let mut buffer = Vec::new();
-    let mut f = File::open("zkmpt-circuit/integration-test/trace.json").unwrap();
-    f.read_to_end(&mut buffer).unwrap();
-    // println!("buffer {buffer:?}");
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("integration-test/trace.json");
+    let state_trie = validate_trace_file(&path).unwrap();
 
-    let block_result = serde_json::from_slice::<BlockResult>(&buffer).unwrap();
-    let traces = block_result.mpt_trans_trace;
-    let start_mpt_root = block_result.start_mpt_root;
-    let end_mpt_root = block_result.end_mpt_root;
+    let ops = state_trie.get_ops();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(state_trie.touched_addresses().len(), 1);
+    assert_eq!(state_trie.final_root(), ops[0].account_root_after());
+    state_trie.verify_all().unwrap();
 
-    let ops: Vec<AccountOp<Fp>> = traces.iter().map(|tr| tr.try_into().unwrap()).collect();
-    println!("ops {ops:?}");
-
-    let mut state_trie: StateTrie<Fp> = Default::default();
-    state_trie.add_ops(ops);
-
-    let final_root = state_trie.final_root();
-    println!("final_root {final_root:?}");
-    println!("mpt_trans_trace {traces:?}");
-
-    let state_circuit = state_trie.circuits(7, &[]);
-
-    // let prover_state = MockProver::<Fp>::run(7, &state_circuit, vec![]);
+    let rows = state_trie.required_rows();
+    let k = state_trie.min_k();
+    let state_circuit = state_trie.circuits(rows, &[]);
+    let prover = MockProver::<Fp>::run(k, &state_circuit, vec![]).unwrap();
+    assert_eq!(prover.verify(), Ok(()));
 }
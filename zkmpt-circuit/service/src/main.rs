@@ -6,11 +6,9 @@ use serde_with::serde_as;
 use dotenv::dotenv;
 use std::time::Duration;
 use tokio::{task, time};
-use zkprover_mpt_circuits::{
-    manager::Manager,
-    // smt_rocksdb_store::default_store::DefaultStore,
-    ERC4337::bundler::BundlerRpcData,
-};
+#[cfg(feature = "rocksdb")]
+use zkprover_mpt_circuits::smt_rocksdb_store::RocksDbTrieStore;
+use zkprover_mpt_circuits::{manager::Manager, ERC4337::bundler::BundlerRpcData};
 
 /*
 cargo run --package zkprover-mpt-circuits --bin service 
@@ -24,7 +22,17 @@ pub async fn main() {
     println!("SETUP_PATH {:?}", setup_path);
     println!("DB_PATH {:?}", db_path);
 
-    let mut manager = Manager::new(setup_path.to_string(), rpc_url.to_string());
+    #[cfg(feature = "rocksdb")]
+    let mut manager = Manager::with_store(
+        setup_path.to_string(),
+        rpc_url.to_string(),
+        Box::new(RocksDbTrieStore::open(&db_path).expect("open rocksdb")),
+    )
+    .expect("setup manager");
+    #[cfg(not(feature = "rocksdb"))]
+    let mut manager =
+        Manager::new(setup_path.to_string(), rpc_url.to_string()).expect("setup manager");
+    println!("MPT_ROOT {:?}", manager.mpt_root());
 
     let forever = task::spawn(async move {
         let mut interval = time::interval(Duration::from_millis(3000));
@@ -32,7 +40,9 @@ pub async fn main() {
         loop {
             interval.tick().await;
             // pull_bundler_mission().await;
-            manager.execute_mission().await;
+            if let Err(e) = manager.execute_mission().await {
+                eprintln!("execute mission error: {}", e);
+            }
         }
     });
 
@@ -5,11 +5,15 @@
 use ethers::{
     abi,
     abi::ParamType,
-    core::types::{Address, Bytes, TxHash, U256, U64},
-    types::TransactionRequest,
+    core::types::{transaction::eip2930::AccessList, Address, Bytes, TxHash, H256, U256, U64},
+    types::{
+        transaction::eip2718::TypedTransaction, Eip1559TransactionRequest, TransactionRequest,
+    },
+    utils::hash_message,
 };
 
 use lazy_static::lazy_static;
+use std::borrow::Cow;
 
 use halo2_proofs::halo2curves::{
     secp256k1::{self, Secp256k1Affine},
@@ -71,10 +75,74 @@ pub struct BundlerRpcTxData {
 #[serde(rename_all(deserialize = "camelCase", serialize = "camelCase"))]
 pub struct BundlerRpcData {
     pub jsonrpc: String,
+    #[serde(default)]
     pub result: Option<BundlerRpcResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
     pub id: u64,
 }
 
+/// the error object in a failed JSON-RPC response
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl BundlerRpcData {
+    /// take the result of response, the error object is surfaced if the
+    /// bundler has failed, and None means there is no mission
+    pub fn into_result(self) -> Result<Option<BundlerRpcResult>, BundlerError> {
+        match self.error {
+            Some(err) => Err(BundlerError::Rpc(err)),
+            None => Ok(self.result),
+        }
+    }
+}
+
+impl BundlerRpcResult {
+    /// the batch hash folded from the ordered tx hashes:
+    /// h_0 = 0, h_i = keccak256(h_{i-1} || tx_hash_i)
+    pub fn compute_batch_hash(&self) -> TxHash {
+        self.tx_list.iter().fold(TxHash::zero(), |acc, tx| {
+            TxHash::from_slice(&Keccak256::digest(
+                [acc.as_bytes(), tx.hash.as_bytes()].concat(),
+            ))
+        })
+    }
+
+    /// check the claimed batch hash is consistent with the tx list
+    pub fn verify_batch_hash(&self) -> bool {
+        self.compute_batch_hash() == self.batch_hash
+    }
+
+    /// split the tx list into chunks of `tx_per_proof` txs, each one for a
+    /// circuit instance, the final chunk may be partial
+    pub fn chunks(&self, tx_per_proof: usize) -> impl Iterator<Item = &[BundlerRpcTxData]> {
+        self.tx_list.chunks(tx_per_proof)
+    }
+
+    /// like `chunks`, but the final partial chunk is padded with `padding` up
+    /// to `tx_per_proof`, so every chunk fit the compiled circuit
+    pub fn padded_chunks<'a>(
+        &'a self,
+        tx_per_proof: usize,
+        padding: &'a BundlerRpcTxData,
+    ) -> impl Iterator<Item = Cow<'a, [BundlerRpcTxData]>> {
+        self.chunks(tx_per_proof).map(move |chunk| {
+            if chunk.len() == tx_per_proof {
+                Cow::Borrowed(chunk)
+            } else {
+                let mut padded = chunk.to_vec();
+                padded.resize(tx_per_proof, padding.clone());
+                Cow::Owned(padded)
+            }
+        })
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all(deserialize = "camelCase", serialize = "camelCase"))]
 pub struct BundlerRpcResult {
@@ -87,7 +155,7 @@ pub struct BundlerRpcResult {
 pub type Word = U256;
 
 /// Definition of all of the constants related to an Ethereum transaction.
-#[derive(Debug, Default, Clone, Serialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     /// Sender address
     pub from: Address,
@@ -104,15 +172,18 @@ pub struct Transaction {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gas_price: Option<Word>,
     /// Gas fee cap
-    // pub gas_fee_cap: Option<Word>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_fee_cap: Option<Word>,
     /// Gas tip cap
-    // pub gas_tip_cap: Option<Word>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_tip_cap: Option<Word>,
     /// The compiled code of a contract OR the first 4 bytes of the hash of the
     /// invoked method signature and encoded parameters. For details see
     /// Ethereum Contract ABI
     pub input: Bytes,
     /// Access list
-    // pub access_list: AccessList,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_list: Option<AccessList>,
     pub chain_id: U64,
 
     /// "v" value of the transaction signature
@@ -121,6 +192,15 @@ pub struct Transaction {
     pub r: Word,
     /// "s" value of the transaction signature
     pub s: Word,
+
+    /// the hash of the user op the tx is converted from, which is what its
+    /// signature covers instead of the tx
+    #[serde(skip)]
+    pub user_op_hash: Option<H256>,
+    /// the user op is signed with `personal_sign`, i.e. over the EIP-191
+    /// prefixed op hash instead of the raw one
+    #[serde(skip)]
+    pub user_op_personal_sign: bool,
 }
 
 /// Helper function to convert a `CtOption` into an `Result`.  Similar to
@@ -140,21 +220,22 @@ pub fn pk_bytes_swap_endianness<T: Clone>(pk: &[T]) -> [T; 64] {
     pk_swap
 }
 
-/// Recover the public key from a secp256k1 signature and the message hash.
-pub fn recover_pk(
-    add: Address,
+fn recover_secp_pk(
     v: u8,
     r: &Word,
     s: &Word,
     msg_hash: &[u8; 32],
-) -> Result<Secp256k1Affine, libsecp256k1::Error> {
+) -> Result<libsecp256k1::PublicKey, libsecp256k1::Error> {
     let mut sig_bytes = [0u8; 64];
     sig_bytes[..32].copy_from_slice(&r.to_be_bytes());
     sig_bytes[32..].copy_from_slice(&s.to_be_bytes());
     let signature = libsecp256k1::Signature::parse_standard(&sig_bytes)?;
     let msg_hash = libsecp256k1::Message::parse_slice(msg_hash.as_slice())?;
     let recovery_id = libsecp256k1::RecoveryId::parse(v)?;
-    let pk = libsecp256k1::recover(&msg_hash, &signature, &recovery_id)?;
+    libsecp256k1::recover(&msg_hash, &signature, &recovery_id)
+}
+
+fn pk_to_address(pk: &libsecp256k1::PublicKey) -> Address {
     let pk_be = pk.serialize();
     debug_assert_eq!(pk_be[0], 0x04);
     let pk_hash: [u8; 32] = Keccak256::digest(&pk_be[1..])
@@ -162,13 +243,39 @@ pub fn recover_pk(
         .to_vec()
         .try_into()
         .expect("hash length isn't 32 bytes");
-    let address = Address::from_slice(&pk_hash[12..]);
+    Address::from_slice(&pk_hash[12..])
+}
+
+/// Recover the signer address from a secp256k1 signature and the message
+/// hash, for the case the sender is not known in advance.
+pub fn recover_address(
+    v: u8,
+    r: &Word,
+    s: &Word,
+    msg_hash: &[u8; 32],
+) -> Result<Address, libsecp256k1::Error> {
+    recover_secp_pk(v, r, s, msg_hash).map(|pk| pk_to_address(&pk))
+}
 
-    // debug_assert_eq!(address, add);
-    if !address.eq(&add) {
+/// Recover the public key from a secp256k1 signature and the message hash.
+/// The recovered address must equal `add`, use `recover_address` to discover
+/// an unknown signer.
+pub fn recover_pk(
+    add: Address,
+    v: u8,
+    r: &Word,
+    s: &Word,
+    msg_hash: &[u8; 32],
+) -> Result<Secp256k1Affine, libsecp256k1::Error> {
+    let pk = recover_secp_pk(v, r, s, msg_hash)?;
+    if pk_to_address(&pk) != add {
         return Err(libsecp256k1::Error::InvalidSignature);
     }
+    secp_affine(&pk)
+}
 
+fn secp_affine(pk: &libsecp256k1::PublicKey) -> Result<Secp256k1Affine, libsecp256k1::Error> {
+    let pk_be = pk.serialize();
     let pk_le = pk_bytes_swap_endianness(&pk_be[1..]);
     let x = ct_option_ok_or(
         secp256k1::Fp::from_bytes(pk_le[..32].try_into().unwrap()),
@@ -192,6 +299,36 @@ pub fn biguint_to_32bytes_le(v: BigUint) -> [u8; 32] {
 }
 
 impl Transaction {
+    /// the canonical filler for an unused tx slot, its zero signature marks
+    /// the slot as disabled for sign verification
+    pub fn padding(chain_id: u64) -> Self {
+        Self {
+            chain_id: chain_id.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_padding(&self) -> bool {
+        self.r.is_zero() && self.s.is_zero()
+    }
+
+    /// the legacy signing message, which is also the keccak input the sign
+    /// verify circuit binds the msg_hash to
+    pub(crate) fn sign_msg(&self) -> Vec<u8> {
+        // msg = rlp([nonce, gasPrice, gas, to, value, data, sig_v, r, s])
+        let req: TransactionRequest = self.into();
+        req.chain_id(self.chain_id.as_u64()).rlp().to_vec()
+    }
+
+    /// keccak256 of the legacy signing message
+    pub(crate) fn sign_hash(&self) -> [u8; 32] {
+        Keccak256::digest(self.sign_msg())
+            .as_slice()
+            .to_vec()
+            .try_into()
+            .expect("hash length isn't 32 bytes")
+    }
+
     pub(crate) fn verify_sig(&self) -> Result<(), ()> {
         todo!()
     }
@@ -247,32 +384,73 @@ impl Transaction {
     }
 
     pub(crate) fn sign_data(&self) -> Result<SignData, BundlerError> {
+        if let Some(op_hash) = self.user_op_hash {
+            return self.user_op_sign_data(op_hash.as_fixed_bytes());
+        }
         let chain_id = self.chain_id.as_u64();
+        let v = normalize_v(self.v, chain_id, false)?;
+        let msg_hash = self.sign_hash();
+        let pk = recover_pk(self.from, v, &self.r, &self.s, &msg_hash)?;
+        self.sign_data_with(&msg_hash, pk)
+    }
+
+    /// a user op is signed over its hash (EIP-191 prefixed with
+    /// `user_op_personal_sign`) by the owner of the sender wallet, who is not
+    /// known from the op, so the pk is recovered without checking it against
+    /// `from`
+    fn user_op_sign_data(&self, op_hash: &[u8; 32]) -> Result<SignData, BundlerError> {
+        let msg_hash = if self.user_op_personal_sign {
+            hash_message(op_hash).to_fixed_bytes()
+        } else {
+            *op_hash
+        };
+        let v = normalize_v(self.v, self.chain_id.as_u64(), true)?;
+        let pk = recover_secp_pk(v, &self.r, &self.s, &msg_hash)?;
+        self.sign_data_with(&msg_hash, secp_affine(&pk)?)
+    }
+
+    pub(crate) fn sign_1559_data(&self) -> Result<SignData, BundlerError> {
+        let chain_id = self.chain_id.as_u64();
+        // msg = 0x02 || rlp([chain_id, nonce, tip_cap, fee_cap, gas, to, value, data, access_list])
+        let req: Eip1559TransactionRequest = self.into();
+        let msg_hash = TypedTransaction::Eip1559(req).sighash().to_fixed_bytes();
+        let v = normalize_v(self.v, chain_id, true)?;
+        let pk = recover_pk(self.from, v, &self.r, &self.s, &msg_hash)?;
+        self.sign_data_with(&msg_hash, pk)
+    }
+
+    /// the (x, y) coordinates of the signer's public key, recovered from the
+    /// user op hash if the tx is converted from one, else from the 1559
+    /// signing message if the tx has a fee cap or the legacy one, and ready
+    /// for `ecc_chip.assign_point`
+    pub fn recover_pubkey_coords(&self) -> Result<(secp256k1::Fp, secp256k1::Fp), BundlerError> {
+        let sign_data = if self.gas_fee_cap.is_some() && self.user_op_hash.is_none() {
+            self.sign_1559_data()?
+        } else {
+            self.sign_data()?
+        };
+        let coords = ct_option_ok_or(
+            sign_data.pk.coordinates(),
+            libsecp256k1::Error::InvalidPublicKey,
+        )?;
+        Ok((*coords.x(), *coords.y()))
+    }
+
+    fn sign_data_with(
+        &self,
+        msg_hash: &[u8; 32],
+        pk: Secp256k1Affine,
+    ) -> Result<SignData, BundlerError> {
         let sig_r_le = self.r.to_le_bytes();
         let sig_s_le = self.s.to_le_bytes();
         let sig_r = ct_option_ok_or(
             secp256k1::Fq::from_repr(sig_r_le),
-            BundlerError::Signature(libsecp256k1::Error::InvalidSignature),
+            libsecp256k1::Error::InvalidSignature,
         )?;
         let sig_s = ct_option_ok_or(
             secp256k1::Fq::from_repr(sig_s_le),
-            BundlerError::Signature(libsecp256k1::Error::InvalidSignature),
+            libsecp256k1::Error::InvalidSignature,
         )?;
-        // msg = rlp([nonce, gasPrice, gas, to, value, data, sig_v, r, s])
-        let req: TransactionRequest = self.into();
-        let msg = req.chain_id(chain_id).rlp();
-        let msg_hash: [u8; 32] = Keccak256::digest(&msg)
-            .as_slice()
-            .to_vec()
-            .try_into()
-            .expect("hash length isn't 32 bytes");
-        let v = self
-            .v
-            .checked_sub(35 + chain_id * 2)
-            .ok_or(BundlerError::Signature(
-                libsecp256k1::Error::InvalidSignature,
-            ))? as u8;
-        let pk = recover_pk(self.from, v, &self.r, &self.s, &msg_hash).unwrap();
         // msg_hash = msg_hash % q
         let msg_hash = BigUint::from_bytes_be(msg_hash.as_slice());
         let msg_hash = msg_hash.mod_floor(&*SECP256K1_Q);
@@ -280,13 +458,37 @@ impl Transaction {
         let msg_hash = ct_option_ok_or(
             secp256k1::Fq::from_repr(msg_hash_le),
             libsecp256k1::Error::InvalidMessage,
-        )
-        .unwrap();
-        Ok(SignData {
-            signature: (sig_r, sig_s),
-            pk,
-            msg_hash,
-        })
+        )?;
+        Ok(SignData::new((sig_r, sig_s), pk, msg_hash))
+    }
+}
+
+/// Normalize the signature `v` into the secp256k1 recovery id. A legacy tx
+/// follows EIP-155 (`v = recid + 35 + chain_id * 2`), while a typed (EIP-1559)
+/// tx carries the recovery id directly, or 27/28 by some encoders.
+pub fn normalize_v(v: u64, chain_id: u64, is_1559: bool) -> Result<u8, BundlerError> {
+    let invalid = || BundlerError::Signature(libsecp256k1::Error::InvalidSignature);
+    if is_1559 {
+        return parity_recovery_id(v).ok_or_else(invalid);
+    }
+    let recovery_id = chain_id
+        .checked_mul(2)
+        .and_then(|id| id.checked_add(35))
+        .and_then(|base| v.checked_sub(base))
+        .ok_or_else(invalid)?;
+    if recovery_id > 1 {
+        return Err(invalid());
+    }
+    Ok(recovery_id as u8)
+}
+
+/// the recovery id of a `v` which is the parity itself (0 or 1), or 27/28 as
+/// used by `eth_sign` and some encoders
+pub fn parity_recovery_id(v: u64) -> Option<u8> {
+    match v {
+        0 | 1 => Some(v as u8),
+        27 | 28 => Some((v - 27) as u8),
+        _ => None,
     }
 }
 
@@ -301,34 +503,509 @@ impl<'d> TryFrom<&'d BundlerRpcTxData> for Transaction {
             gas_limit: value.gas.clone(),
             value: value.value.clone(),
             gas_price: value.gas_price.clone(),
-            // gas_fee_cap: value.max_fee_per_gas.clone(),
-            // gas_tip_cap: value.max_priority_fee_per_gas.clone(),
+            // the bundler only serves legacy txs
+            gas_fee_cap: None,
+            gas_tip_cap: None,
             input: value.input.clone(),
-            // access_list: value.access_list.clone(),
+            access_list: None,
             v: value.v.as_u64().clone(),
             r: value.r.clone(),
             s: value.s.clone(),
             chain_id: value.chain_id.clone(),
+            user_op_hash: None,
+            user_op_personal_sign: false,
         };
         Ok(tx)
     }
 }
 
+/// infer the chain id from an EIP-155 `v`, none for pre-EIP-155 signatures
+pub fn chain_id_from_v(v: u64) -> Option<u64> {
+    if v >= 35 {
+        Some((v - 35) / 2)
+    } else {
+        None
+    }
+}
+
+impl Transaction {
+    fn from_ethers_tx(tx: &ethers::types::Transaction, chain_id: U64) -> Result<Self, TraceError> {
+        if let Some(id) = tx.chain_id {
+            if id != U256::from(chain_id.as_u64()) {
+                return Err(TraceError::DataErr(format!(
+                    "chain id {} of tx {:?} unmatch the expected {}",
+                    id, tx.hash, chain_id
+                )));
+            }
+        }
+        Ok(Transaction {
+            from: tx.from,
+            to: tx.to,
+            nonce: tx.nonce,
+            gas_limit: tx.gas,
+            value: tx.value,
+            gas_price: tx.gas_price,
+            gas_fee_cap: tx.max_fee_per_gas,
+            gas_tip_cap: tx.max_priority_fee_per_gas,
+            input: tx.input.clone(),
+            access_list: tx.access_list.clone(),
+            chain_id,
+            v: tx.v.as_u64(),
+            r: tx.r,
+            s: tx.s,
+            user_op_hash: None,
+            user_op_personal_sign: false,
+        })
+    }
+}
+
+impl<'d> TryFrom<&'d ethers::types::Transaction> for Transaction {
+    type Error = TraceError;
+
+    /// the chain id is taken from the tx, or inferred from its EIP-155 `v`
+    fn try_from(tx: &'d ethers::types::Transaction) -> Result<Self, Self::Error> {
+        let chain_id = match tx.chain_id {
+            Some(id) if id > U256::from(u64::MAX) => {
+                return Err(TraceError::DataErr(format!("chain id {id} overflow")))
+            }
+            Some(id) => id.as_u64(),
+            None => chain_id_from_v(tx.v.as_u64()).ok_or_else(|| {
+                TraceError::DataErr(format!("can not infer chain id of tx {:?}", tx.hash))
+            })?,
+        };
+        Self::from_ethers_tx(tx, U64::from(chain_id))
+    }
+}
+
+impl<'d> TryFrom<(&'d ethers::types::Transaction, U64)> for Transaction {
+    type Error = TraceError;
+
+    /// the chain id is known from the context (e.g. the provider which gives
+    /// the tx and its receipt), it must agree with the one carried by tx
+    fn try_from(value: (&'d ethers::types::Transaction, U64)) -> Result<Self, Self::Error> {
+        let (tx, chain_id) = value;
+        Self::from_ethers_tx(tx, chain_id)
+    }
+}
+
+impl<'d> TryFrom<(&'d UserOperation, &'d Address, U64)> for Transaction {
+    type Error = TraceError;
+
+    /// convert a user op signed over the raw op hash, see the conversion
+    /// with `personal_sign`
+    fn try_from(value: (&'d UserOperation, &'d Address, U64)) -> Result<Self, Self::Error> {
+        let (op, entry_point, chain_id) = value;
+        Self::try_from((op, entry_point, chain_id, false))
+    }
+}
+
+impl<'d> TryFrom<(&'d UserOperation, &'d Address, U64, bool)> for Transaction {
+    type Error = TraceError;
+
+    /// a user op does not carry its entry point and chain id, nor whether the
+    /// wallet signs with `personal_sign`, they come from the context. the tx
+    /// calls the sender wallet with the op call data, the gas limit covers all
+    /// the gas the op may consume and the 65 bytes `r || s || v` signature is
+    /// kept with a `v` of parity form, along with the op hash it signs
+    fn try_from(value: (&'d UserOperation, &'d Address, U64, bool)) -> Result<Self, Self::Error> {
+        let (op, entry_point, chain_id, personal_sign) = value;
+        let (r, s, v) = op.signature_parts().map_err(|e| {
+            TraceError::DataErr(format!("invalid user op of sender {:?}: {e}", op.sender))
+        })?;
+        let op_hash = op.hash(entry_point, &U256::from(chain_id.as_u64()));
+        let gas_limit = op
+            .call_gas_limit
+            .checked_add(op.verification_gas_limit)
+            .and_then(|gas| gas.checked_add(op.pre_verification_gas))
+            .ok_or_else(|| {
+                TraceError::DataErr(format!("gas limit overflow of sender {:?}", op.sender))
+            })?;
+
+        Ok(Transaction {
+            from: op.sender,
+            to: Some(op.sender),
+            nonce: op.nonce,
+            gas_limit,
+            value: U256::zero(),
+            gas_price: None,
+            gas_fee_cap: Some(op.max_fee_per_gas),
+            gas_tip_cap: Some(op.max_priority_fee_per_gas),
+            input: op.call_data.clone(),
+            access_list: None,
+            chain_id,
+            v: v as u64,
+            r,
+            s,
+            user_op_hash: Some(op_hash.into()),
+            user_op_personal_sign: personal_sign,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use jsonrpsee::tracing::log::error;
-    use std::fs::File;
-    use std::io::Read;
 
-    use super::{BundlerRpcData, Transaction};
+    use super::{
+        normalize_v, recover_address, recover_pk, BundlerError, BundlerRpcData, BundlerRpcTxData,
+        Transaction,
+    };
+    use crate::ERC4337::user_op::UserOperation;
+    use ethers::types::{
+        transaction::eip2930::{AccessList, AccessListItem},
+        Address, TxHash, H256, U256, U64,
+    };
+    use ethers::utils::hash_message;
+    use halo2_proofs::halo2curves::{secp256k1::Secp256k1Affine, CurveAffine};
+    use snark_verifier::util::hash::{Digest, Keccak256};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_transaction_serde_round_trip() {
+        let tx = Transaction {
+            from: Address::repeat_byte(0x11),
+            to: Some(Address::repeat_byte(0x22)),
+            nonce: U256::from(3u64),
+            gas_limit: U256::from(21000u64),
+            value: U256::from(10u64).pow(U256::from(18u64)),
+            gas_price: Some(U256::from(1_000_000_000u64)),
+            input: vec![0xde, 0xad, 0xbe, 0xef].into(),
+            access_list: Some(AccessList(vec![AccessListItem {
+                address: Address::repeat_byte(0x33),
+                storage_keys: vec![TxHash::repeat_byte(0x44)],
+            }])),
+            chain_id: U64::from(0x4337u64),
+            v: 0x8691,
+            r: U256::from(5u64),
+            s: U256::MAX,
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&tx).unwrap();
+        let decoded: Transaction = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.chain_id, tx.chain_id);
+        assert_eq!(decoded.v, tx.v);
+        assert_eq!(decoded.gas_price, tx.gas_price);
+        assert_eq!(decoded.gas_fee_cap, None);
+        assert_eq!(decoded.gas_tip_cap, None);
+        assert_eq!(decoded.access_list, tx.access_list);
+        assert_eq!(decoded.s, U256::MAX);
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&tx).unwrap()
+        );
+
+        // the skipped optional fields are read back as None
+        let padding = Transaction::padding(0x4337);
+        let json = serde_json::to_string(&padding).unwrap();
+        assert!(!json.contains("access_list"));
+        let decoded: Transaction = serde_json::from_str(&json).unwrap();
+        assert!(decoded.to.is_none() && decoded.access_list.is_none());
+        assert!(decoded.is_padding());
+    }
+
+    #[test]
+    fn test_normalize_v() {
+        // EIP-155 with chain id 0x4337
+        assert_eq!(normalize_v(0x8691, 0x4337, false).unwrap(), 0);
+        assert_eq!(normalize_v(0x8692, 0x4337, false).unwrap(), 1);
+        assert!(normalize_v(0x8693, 0x4337, false).is_err());
+        assert!(normalize_v(0x8691, 5, false).is_err());
+        assert!(normalize_v(28, 5, false).is_err());
+        assert!(normalize_v(37, u64::MAX, false).is_err());
+
+        // typed tx
+        assert_eq!(normalize_v(1, 5, true).unwrap(), 1);
+        assert_eq!(normalize_v(27, 5, true).unwrap(), 0);
+        assert_eq!(normalize_v(28, 5, true).unwrap(), 1);
+        assert!(normalize_v(2, 5, true).is_err());
+        assert!(normalize_v(0x8691, 0x4337, true).is_err());
+    }
+
+    #[test]
+    fn test_recover_address() {
+        let sk = libsecp256k1::SecretKey::parse(&[7u8; 32]).unwrap();
+        let pk = libsecp256k1::PublicKey::from_secret_key(&sk).serialize();
+        let signer = Address::from_slice(&Keccak256::digest(&pk[1..])[12..]);
+
+        let msg_hash = [42u8; 32];
+        let (sig, rec_id) = libsecp256k1::sign(&libsecp256k1::Message::parse(&msg_hash), &sk);
+        let sig = sig.serialize();
+        let (r, s) = (
+            U256::from_big_endian(&sig[..32]),
+            U256::from_big_endian(&sig[32..]),
+        );
+        let v = rec_id.serialize();
+
+        assert_eq!(recover_address(v, &r, &s, &msg_hash).unwrap(), signer);
+        assert!(recover_pk(signer, v, &r, &s, &msg_hash).is_ok());
+        assert!(recover_pk(Address::zero(), v, &r, &s, &msg_hash).is_err());
+        assert!(recover_pk(Address::repeat_byte(1), v, &r, &s, &msg_hash).is_err());
+    }
+
+    #[test]
+    fn test_from_ethers_transaction() {
+        let legacy = ethers::types::Transaction {
+            v: U64::from(0x8691),
+            gas_price: Some(U256::from(1000)),
+            ..Default::default()
+        };
+        let tx = Transaction::try_from(&legacy).unwrap();
+        assert_eq!(tx.chain_id, U64::from(0x4337));
+        assert_eq!(tx.v, 0x8691);
+        assert_eq!(tx.gas_price, Some(U256::from(1000)));
+
+        let typed = ethers::types::Transaction {
+            v: U64::from(1),
+            chain_id: Some(U256::from(5)),
+            max_fee_per_gas: Some(U256::from(30)),
+            max_priority_fee_per_gas: Some(U256::from(2)),
+            access_list: Some(Default::default()),
+            ..Default::default()
+        };
+        let tx = Transaction::try_from(&typed).unwrap();
+        assert_eq!(tx.chain_id, U64::from(5));
+        assert_eq!(tx.gas_fee_cap, Some(U256::from(30)));
+        assert_eq!(tx.gas_tip_cap, Some(U256::from(2)));
+        assert!(tx.access_list.is_some());
+        assert!(Transaction::try_from((&typed, U64::from(1))).is_err());
+
+        // pre-EIP-155 signature needs the chain id from context
+        let unprotected = ethers::types::Transaction {
+            v: U64::from(27),
+            ..Default::default()
+        };
+        assert!(Transaction::try_from(&unprotected).is_err());
+        let tx = Transaction::try_from((&unprotected, U64::from(5))).unwrap();
+        assert_eq!(tx.chain_id, U64::from(5));
+    }
+
+    #[test]
+    fn test_from_user_op() {
+        let mut signature = vec![0x11; 32];
+        signature.extend(vec![0x22; 32]);
+        signature.push(28);
+        let op = UserOperation {
+            nonce: U256::from(7),
+            call_data: vec![0xde, 0xad].into(),
+            call_gas_limit: U256::from(50000),
+            max_fee_per_gas: U256::from(30),
+            signature: signature.into(),
+            ..UserOperation::random()
+        };
+        let entry_point = Address::repeat_byte(0x43);
+        let tx = Transaction::try_from((&op, &entry_point, U64::from(5))).unwrap();
+        assert_eq!(tx.from, op.sender);
+        assert_eq!(tx.to, Some(op.sender));
+        assert_eq!(tx.nonce, op.nonce);
+        assert_eq!(tx.input, op.call_data);
+        assert_eq!(tx.gas_limit, U256::from(50000 + 100000 + 21000));
+        assert_eq!(tx.gas_fee_cap, Some(op.max_fee_per_gas));
+        assert_eq!(tx.gas_tip_cap, Some(op.max_priority_fee_per_gas));
+        assert_eq!(tx.chain_id, U64::from(5));
+        assert_eq!(tx.v, 1);
+        assert_eq!(tx.r, U256::from_big_endian(&[0x11; 32]));
+        assert_eq!(tx.s, U256::from_big_endian(&[0x22; 32]));
+        assert_eq!(
+            tx.user_op_hash,
+            Some(op.hash(&entry_point, &U256::from(5)).into())
+        );
+
+        let short = UserOperation {
+            signature: vec![0; 64].into(),
+            ..op.clone()
+        };
+        assert!(Transaction::try_from((&short, &entry_point, U64::from(5))).is_err());
+        let mut bad_v = op.signature.to_vec();
+        bad_v[64] = 2;
+        let bad_v = UserOperation {
+            signature: bad_v.into(),
+            ..op
+        };
+        assert!(Transaction::try_from((&bad_v, &entry_point, U64::from(5))).is_err());
+    }
+
+    #[test]
+    fn test_user_op_sign_data() {
+        let sk = libsecp256k1::SecretKey::parse(&[7u8; 32]).unwrap();
+        let pk = libsecp256k1::PublicKey::from_secret_key(&sk).serialize();
+        let owner = Address::from_slice(&Keccak256::digest(&pk[1..])[12..]);
+        let entry_point = Address::repeat_byte(0x43);
+        let chain_id = U64::from(5);
+
+        let op = UserOperation::random();
+        let op_hash = H256::from(op.hash(&entry_point, &U256::from(5)));
+        let (sig, rec_id) =
+            libsecp256k1::sign(&libsecp256k1::Message::parse(op_hash.as_fixed_bytes()), &sk);
+        let op = UserOperation {
+            signature: [sig.serialize().as_slice(), &[rec_id.serialize() + 27]]
+                .concat()
+                .into(),
+            ..op
+        };
+
+        // the wallet owner signs the op hash, not the rlp of the tx
+        let tx = Transaction::try_from((&op, &entry_point, chain_id)).unwrap();
+        let (r, s, v) = op.signature_parts().unwrap();
+        let sign_data = tx.sign_data().unwrap();
+        assert_eq!(
+            sign_data.pk,
+            recover_pk(owner, v, &r, &s, op_hash.as_fixed_bytes()).unwrap()
+        );
+        let (x, y) = tx.recover_pubkey_coords().unwrap();
+        assert_eq!(Secp256k1Affine::from_xy(x, y).unwrap(), sign_data.pk);
+
+        // signed for another chain, the recovered pk is not the owner's
+        let other = Transaction::try_from((&op, &entry_point, U64::from(1))).unwrap();
+        assert_ne!(other.sign_data().unwrap().pk, sign_data.pk);
+
+        // the wallet owner signs the EIP-191 prefixed op hash with personal_sign
+        let (sig, rec_id) = libsecp256k1::sign(
+            &libsecp256k1::Message::parse(hash_message(op_hash).as_fixed_bytes()),
+            &sk,
+        );
+        let personal = UserOperation {
+            signature: [sig.serialize().as_slice(), &[rec_id.serialize() + 27]]
+                .concat()
+                .into(),
+            ..op
+        };
+        let tx = Transaction::try_from((&personal, &entry_point, chain_id, true)).unwrap();
+        assert!(tx.user_op_personal_sign);
+        assert_eq!(tx.sign_data().unwrap().pk, sign_data.pk);
+        assert_eq!(
+            personal
+                .recover_signer(&entry_point, &U256::from(5), true)
+                .unwrap(),
+            owner
+        );
+        let raw = Transaction::try_from((&personal, &entry_point, chain_id)).unwrap();
+        assert!(!raw.user_op_personal_sign);
+        assert_ne!(raw.sign_data().unwrap().pk, sign_data.pk);
+    }
+
+    #[test]
+    fn test_sign_data_error() {
+        use std::error::Error;
+
+        let data: BundlerRpcData =
+            serde_json::from_str(include_str!("rpc_data_test.json")).unwrap();
+        let mut tx = Transaction::try_from(&data.result.unwrap().tx_list[0]).unwrap();
+        assert!(tx.sign_data().is_ok());
+
+        // the signature no longer covers the tx
+        tx.nonce += U256::one();
+        let err = tx.sign_data().unwrap_err();
+        assert!(matches!(
+            err,
+            BundlerError::Signature(libsecp256k1::Error::InvalidSignature)
+        ));
+        assert!(err.source().is_some());
+        assert!(err.to_string().starts_with("signature error"));
+    }
+
+    #[test]
+    fn test_recover_pubkey_coords() {
+        let data: BundlerRpcData =
+            serde_json::from_str(include_str!("rpc_data_test.json")).unwrap();
+        let tx = Transaction::try_from(&data.result.unwrap().tx_list[0]).unwrap();
+        let (x, y) = tx.recover_pubkey_coords().unwrap();
+        assert_eq!(
+            Secp256k1Affine::from_xy(x, y).unwrap(),
+            tx.sign_data().unwrap().pk
+        );
+
+        // the coordinates are little-endian, the address hashes them in big-endian
+        let mut pk_be = [x.to_bytes(), y.to_bytes()].concat();
+        pk_be[..32].reverse();
+        pk_be[32..].reverse();
+        assert_eq!(
+            Address::from_slice(&Keccak256::digest(&pk_be)[12..]),
+            tx.from
+        );
+
+        let mut forged = tx;
+        forged.nonce += U256::one();
+        assert!(forged.recover_pubkey_coords().is_err());
+    }
+
+    #[test]
+    fn test_bundler_rpc_error() {
+        let data = serde_json::from_str::<BundlerRpcData>(
+            r#"{"jsonrpc":"2.0","id":1,"result":null,"error":{"code":-32000,"message":"no batch"}}"#,
+        )
+        .unwrap();
+        match data.into_result() {
+            Err(BundlerError::Rpc(err)) => {
+                assert_eq!(err.code, -32000);
+                assert_eq!(err.message, "no batch");
+            }
+            other => panic!("expect rpc error, got {other:?}"),
+        }
+
+        let data =
+            serde_json::from_str::<BundlerRpcData>(r#"{"jsonrpc":"2.0","id":1,"result":null}"#)
+                .unwrap();
+        assert!(data.into_result().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_verify_batch_hash() {
+        let buffer = include_str!("rpc_data_test.json");
+
+        let mut result = serde_json::from_str::<BundlerRpcData>(buffer)
+            .unwrap()
+            .result
+            .unwrap();
+        let tx = result.tx_list[0].clone();
+        result.tx_list.push(BundlerRpcTxData {
+            hash: TxHash::repeat_byte(1),
+            ..tx
+        });
+        result.batch_hash = result.compute_batch_hash();
+        assert!(result.verify_batch_hash());
+
+        let mut reordered = result.clone();
+        reordered.tx_list.reverse();
+        assert!(!reordered.verify_batch_hash());
+
+        let mut mutated = result;
+        mutated.tx_list[1].hash = TxHash::repeat_byte(2);
+        assert!(!mutated.verify_batch_hash());
+    }
+
+    #[test]
+    fn test_bundler_rpc_chunks() {
+        let buffer = include_str!("rpc_data_test.json");
+
+        let mut result = serde_json::from_str::<BundlerRpcData>(buffer)
+            .unwrap()
+            .result
+            .unwrap();
+        let tx = result.tx_list[0].clone();
+        result.tx_list = vec![tx.clone(); 5];
+
+        let lens: Vec<_> = result.chunks(2).map(|c| c.len()).collect();
+        assert_eq!(lens, vec![2, 2, 1]);
+
+        let padding = BundlerRpcTxData {
+            nonce: U256::zero(),
+            ..tx
+        };
+        let chunks: Vec<_> = result.padded_chunks(2, &padding).collect();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.len() == 2));
+        assert!(matches!(chunks[0], Cow::Borrowed(_)));
+        assert_eq!(chunks[2][0].nonce, result.tx_list[4].nonce);
+        assert_eq!(chunks[2][1].nonce, U256::zero());
+    }
+
     #[test]
     fn test_bundler_rpc_data() {
-        let mut buffer = Vec::new();
-        let mut f = File::open("src/ERC4337/rpc_data_test.json").unwrap();
-        f.read_to_end(&mut buffer).unwrap();
+        let buffer = include_str!("rpc_data_test.json");
         // println!("buffer {buffer:?}");
 
-        let rpc_txs = serde_json::from_slice::<BundlerRpcData>(&buffer)
+        let rpc_txs = serde_json::from_str::<BundlerRpcData>(buffer)
             .unwrap()
             .result
             .unwrap()
@@ -1,43 +1,56 @@
 //! Error module for the eth-types crate
 
-use core::fmt::{Display, Formatter, Result as FmtResult};
-use std::error::Error as StdError;
-
 use ethers::core::types::Eip1559TransactionRequest;
 use ethers::types::{NameOrAddress, TransactionRequest};
 
-use super::bundler::Transaction;
+use super::bundler::{JsonRpcError, Transaction};
 
 /// Error type for any BusMapping related failure.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error("serde error: {0}")]
     /// Serde de/serialization error.
-    SerdeError(serde_json::error::Error),
+    SerdeError(#[from] serde_json::error::Error),
+    #[error("tracing error: {0}")]
     /// Error while generating a trace.
     TracingError(String),
+    #[error("block misses its number or base fee")]
     /// Block is missing information about number or base_fee
     IncompleteBlock,
+    #[error("invalid opcode byte {0:#04x}")]
     /// Denotes that the byte in the bytecode does not match with any Opcode ID.
     InvalidOpcodeIdByte(u8),
+    #[error("opcode parsing error: {0}")]
     /// Error while parsing an `Instruction/Opcode`.
     OpcodeParsing(String),
+    #[error("memory address parsing error")]
     /// Error while parsing a `MemoryAddress`.
     MemAddressParsing,
+    #[error("stack address parsing error")]
     /// Error while parsing a `StackAddress`.
     StackAddressParsing,
+    #[error("invalid opcode conversion")]
     /// Error while trying to convert to an incorrect `OpcodeId`.
     InvalidOpConversion,
+    #[error("invalid stack pointer")]
     /// Error while trying to access an invalid/empty Stack location.
     InvalidStackPointer,
+    #[error("invalid memory pointer")]
     /// Error while trying to access an invalid/empty Memory location.
     InvalidMemoryPointer,
+    #[error("invalid storage key")]
     /// Error while trying to access an invalid/empty Storage key.
     InvalidStorageKey,
+    #[error("evm word too big for a memory address")]
     /// Error when an EvmWord is too big to be converted into a
     /// `MemoryAddress`.
     WordToMemAddr,
+    #[error("signature error: {0:?}")]
     /// Signature parsing error.
-    Signature(libsecp256k1::Error),
+    Signature(#[from] libsecp256k1::Error),
+    #[error("rpc error {}: {}", .0.code, .0.message)]
+    /// Error object returned by the JSON-RPC endpoint.
+    Rpc(JsonRpcError),
 }
 
 impl From<&Transaction> for TransactionRequest {
@@ -55,20 +68,19 @@ impl From<&Transaction> for TransactionRequest {
     }
 }
 
-// impl From<&Transaction> for Eip1559TransactionRequest {
-//     fn from(tx: &Transaction) -> Eip1559TransactionRequest {
-//         Eip1559TransactionRequest {
-//             from: Some(tx.from),
-//             to: tx.to.map(NameOrAddress::Address),
-//             gas: Some(tx.gas_limit),
-//             value: Some(tx.value),
-//             data: Some(tx.call_data.clone()),
-//             nonce: Some(tx.nonce),
-//             access_list: tx.access_list.clone(),
-//             max_priority_fee_per_gas: tx.gas_fee_cap,
-//             max_fee_per_gas: tx.gas_tip_cap,
-//             chain_id: Some(tx.chain_id),
-//             ..Default::default()
-//         }
-//     }
-// }
+impl From<&Transaction> for Eip1559TransactionRequest {
+    fn from(tx: &Transaction) -> Eip1559TransactionRequest {
+        Eip1559TransactionRequest {
+            from: Some(tx.from),
+            to: tx.to.map(NameOrAddress::Address),
+            gas: Some(tx.gas_limit),
+            value: Some(tx.value),
+            data: Some(tx.input.clone()),
+            nonce: Some(tx.nonce),
+            access_list: tx.access_list.clone().unwrap_or_default(),
+            max_priority_fee_per_gas: tx.gas_tip_cap,
+            max_fee_per_gas: tx.gas_fee_cap,
+            chain_id: Some(tx.chain_id),
+        }
+    }
+}
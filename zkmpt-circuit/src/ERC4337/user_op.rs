@@ -3,13 +3,15 @@ use ethers::{
     core::abi::{decode, ParamType},
     prelude::{EthAbiCodec, EthAbiType},
     types::{Address, Bytes, TraceError, TransactionReceipt, H256, U256},
-    utils::keccak256,
+    utils::{hash_message, keccak256},
 };
 
 use rustc_hex::FromHexError;
 use serde::{Deserialize, Serialize};
 use std::{ops::Deref, str::FromStr, vec};
 
+use super::bundler::{parity_recovery_id, recover_address};
+
 #[derive(
     Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone, Copy, Default, PartialOrd, Ord,
 )]
@@ -34,6 +36,34 @@ impl FromStr for UserOperationHash {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+/// malformed user operation errors
+pub enum UserOpError {
+    #[error("sender is the zero address")]
+    /// the op has no sender
+    ZeroSender,
+    #[error("signature has {0} bytes, expect 65")]
+    /// the signature is not a `r || s || v` one
+    SignatureLength(usize),
+    #[error("max priority fee {priority} exceeds max fee {max}")]
+    /// the tip can never be paid in full
+    PriorityFee {
+        /// max fee per gas
+        max: U256,
+        /// max priority fee per gas
+        priority: U256,
+    },
+    #[error("total gas limit overflow")]
+    /// the gas limits sum up beyond u256
+    GasOverflow,
+    #[error("invalid signature v {0}")]
+    /// the recovery id is none of 0, 1, 27 or 28
+    SignatureV(u8),
+    #[error("signer recovery failed: {0:?}")]
+    /// secp256k1 recovery error
+    Recover(libsecp256k1::Error),
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, EthAbiCodec, EthAbiType)]
 #[serde(rename_all = "camelCase")]
 pub struct UserOperation {
@@ -80,6 +110,78 @@ impl UserOperation {
         .into()
     }
 
+    /// reject a malformed op before hashing or recovering its signer, the
+    /// nonce is not checked here since any value is valid without the state
+    pub fn validate(&self) -> Result<(), UserOpError> {
+        if self.sender.is_zero() {
+            return Err(UserOpError::ZeroSender);
+        }
+        if self.signature.len() != 65 {
+            return Err(UserOpError::SignatureLength(self.signature.len()));
+        }
+        if self.max_priority_fee_per_gas > self.max_fee_per_gas {
+            return Err(UserOpError::PriorityFee {
+                max: self.max_fee_per_gas,
+                priority: self.max_priority_fee_per_gas,
+            });
+        }
+        self.call_gas_limit
+            .checked_add(self.verification_gas_limit)
+            .and_then(|gas| gas.checked_add(self.pre_verification_gas))
+            .ok_or(UserOpError::GasOverflow)?;
+        Ok(())
+    }
+
+    /// split the 65 bytes `r || s || v` signature into r, s and the
+    /// recovery id
+    pub fn signature_parts(&self) -> Result<(U256, U256, u8), UserOpError> {
+        if self.signature.len() != 65 {
+            return Err(UserOpError::SignatureLength(self.signature.len()));
+        }
+        let v = self.signature[64];
+        let recovery_id = parity_recovery_id(v as u64).ok_or(UserOpError::SignatureV(v))?;
+        Ok((
+            U256::from_big_endian(&self.signature[..32]),
+            U256::from_big_endian(&self.signature[32..64]),
+            recovery_id,
+        ))
+    }
+
+    /// recover the signer of the op hash, with `personal_sign` the wallet
+    /// signed the EIP-191 prefixed hash instead of the raw one
+    pub fn recover_signer(
+        &self,
+        entry_point: &Address,
+        chain_id: &U256,
+        personal_sign: bool,
+    ) -> Result<Address, UserOpError> {
+        let (r, s, v) = self.signature_parts()?;
+        let op_hash = H256::from(self.hash(entry_point, chain_id));
+        let msg_hash = if personal_sign {
+            hash_message(op_hash)
+        } else {
+            op_hash
+        };
+        recover_address(v, &r, &s, msg_hash.as_fixed_bytes()).map_err(UserOpError::Recover)
+    }
+
+    /// the paymaster address, the first 20 bytes of `paymaster_and_data`,
+    /// none if the op pays for itself or the field is malformed
+    pub fn paymaster(&self) -> Option<Address> {
+        address_prefix(&self.paymaster_and_data)
+    }
+
+    /// the data passed to the paymaster following its address
+    pub fn paymaster_data(&self) -> &[u8] {
+        self.paymaster_and_data.get(20..).unwrap_or_default()
+    }
+
+    /// the factory address, the first 20 bytes of `init_code`, none if the
+    /// sender is already deployed or the field is malformed
+    pub fn factory(&self) -> Option<Address> {
+        address_prefix(&self.init_code)
+    }
+
     #[cfg(test)]
     pub fn random() -> Self {
         Self {
@@ -98,6 +200,10 @@ impl UserOperation {
     }
 }
 
+fn address_prefix(bytes: &[u8]) -> Option<Address> {
+    bytes.get(..20).map(Address::from_slice)
+}
+
 impl TryFrom<Vec<u8>> for UserOperation {
     type Error = TraceError;
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
@@ -302,6 +408,156 @@ mod tests {
         assert_eq!(orig_use_op, user_op);
     }
 
+    #[test]
+    fn user_operation_paymaster_and_factory() {
+        let paymaster: Address = "0x2DF1592238420ecFe7f2431360e224707e77fA0E"
+            .parse()
+            .unwrap();
+        let factory: Address = "0x663F3ad617193148711d28f5334eE4Ed07016602"
+            .parse()
+            .unwrap();
+        let op = UserOperation {
+            paymaster_and_data: [paymaster.as_bytes(), &[0xde, 0xad, 0xbe, 0xef]]
+                .concat()
+                .into(),
+            init_code: [factory.as_bytes(), &[0x01]].concat().into(),
+            ..UserOperation::random()
+        };
+        assert_eq!(op.paymaster(), Some(paymaster));
+        assert_eq!(op.paymaster_data(), &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(op.factory(), Some(factory));
+
+        let op = UserOperation {
+            paymaster_and_data: paymaster.as_bytes().to_vec().into(),
+            ..op
+        };
+        assert_eq!(op.paymaster(), Some(paymaster));
+        assert!(op.paymaster_data().is_empty());
+
+        let op = UserOperation::random();
+        assert_eq!(op.paymaster(), None);
+        assert!(op.paymaster_data().is_empty());
+        assert_eq!(op.factory(), None);
+    }
+
+    #[test]
+    fn user_operation_validate() {
+        let op = UserOperation {
+            max_fee_per_gas: U256::from(3000000000_u64),
+            signature: vec![1; 65].into(),
+            ..UserOperation::random()
+        };
+        assert!(op.validate().is_ok());
+
+        for len in [64, 66] {
+            let malformed = UserOperation {
+                signature: vec![1; len].into(),
+                ..op.clone()
+            };
+            assert!(matches!(
+                malformed.validate(),
+                Err(UserOpError::SignatureLength(l)) if l == len
+            ));
+        }
+
+        let malformed = UserOperation {
+            sender: Address::zero(),
+            ..op.clone()
+        };
+        assert!(matches!(malformed.validate(), Err(UserOpError::ZeroSender)));
+
+        let malformed = UserOperation {
+            max_fee_per_gas: U256::zero(),
+            ..op.clone()
+        };
+        assert!(matches!(
+            malformed.validate(),
+            Err(UserOpError::PriorityFee { .. })
+        ));
+
+        let malformed = UserOperation {
+            call_gas_limit: U256::MAX,
+            ..op
+        };
+        assert!(matches!(
+            malformed.validate(),
+            Err(UserOpError::GasOverflow)
+        ));
+    }
+
+    #[test]
+    fn user_operation_recover_signer() {
+        // the first hardhat dev account
+        let sk = libsecp256k1::SecretKey::parse(&hex!(
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+        ))
+        .unwrap();
+        let wallet: Address = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+            .parse()
+            .unwrap();
+        let entry_point: Address = "0x2DF1592238420ecFe7f2431360e224707e77fA0E"
+            .parse()
+            .unwrap();
+        let chain_id = U256::from(1);
+
+        let op = UserOperation {
+            sender: wallet,
+            ..UserOperation::random()
+        };
+        let op_hash = H256::from(op.hash(&entry_point, &chain_id));
+        let sign = |msg_hash: H256| -> Bytes {
+            let (sig, rec_id) = libsecp256k1::sign(
+                &libsecp256k1::Message::parse(msg_hash.as_fixed_bytes()),
+                &sk,
+            );
+            [sig.serialize().as_slice(), &[rec_id.serialize() + 27]]
+                .concat()
+                .into()
+        };
+
+        let raw = UserOperation {
+            signature: sign(op_hash),
+            ..op.clone()
+        };
+        assert_eq!(
+            raw.recover_signer(&entry_point, &chain_id, false).unwrap(),
+            wallet
+        );
+        assert_ne!(
+            raw.recover_signer(&entry_point, &chain_id, true).unwrap(),
+            wallet
+        );
+
+        let personal = UserOperation {
+            signature: sign(hash_message(op_hash)),
+            ..op.clone()
+        };
+        assert_eq!(
+            personal
+                .recover_signer(&entry_point, &chain_id, true)
+                .unwrap(),
+            wallet
+        );
+        // the signature is bound to the chain
+        assert_ne!(
+            personal
+                .recover_signer(&entry_point, &U256::from(5), true)
+                .unwrap(),
+            wallet
+        );
+
+        let mut bad_v = personal.signature.to_vec();
+        bad_v[64] = 29;
+        let bad_v = UserOperation {
+            signature: bad_v.into(),
+            ..op
+        };
+        assert!(matches!(
+            bad_v.recover_signer(&entry_point, &chain_id, true),
+            Err(UserOpError::SignatureV(29))
+        ));
+    }
+
     #[test]
     fn user_operation_pack() {
         let user_operations =  vec![
@@ -1,14 +1,14 @@
 use halo2_proofs::{
-    circuit::{Chip, Region, Value},
+    circuit::{AssignedCell, Chip, Region, Value},
     halo2curves::FieldExt,
     plonk::{Advice, Column, ConstraintSystem, Error, Expression, Selector},
     poly::Rotation,
 };
 
-use crate::operation::Account;
+use crate::operation::{Account, KeyValue};
 
 use super::table_util;
-use super::{hash_util, kv_util::KeyValue, table_util::CtrlTransitionKind};
+use super::{hash_util, table_util::CtrlTransitionKind};
 
 pub const CIRCUIT_ROW: usize = 4;
 const LAST_ROW: usize = CIRCUIT_ROW - 1;
@@ -22,6 +22,30 @@ lazy_static! {
     };
 }
 
+/// the data fields of account, in the order of the rows of the gadget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AccountField {
+    Nonce,
+    GasBalance,
+    TxHash,
+    StateRoot,
+}
+
+/// the cells of an account state: the account hash and the data fields in
+/// the order of `AccountField`
+type AccountCells<Fp> = (AssignedCell<Fp, Fp>, [AssignedCell<Fp, Fp>; 4]);
+
+/// the cells of an account transition which can be copied into instances for
+/// selective disclosure: the (old, new) account hashes commit to all fields
+/// while only the selected fields are exposed. The account hash is not salted
+/// so the commitment is binding but not hiding: a private field of low
+/// entropy (e.g. the gas balance) can be brute-forced from the exposed hash
+#[derive(Clone, Debug)]
+pub(crate) struct AccountDisclosure<Fp: FieldExt> {
+    pub account_hash: [AssignedCell<Fp, Fp>; 2],
+    pub fields: Vec<(AccountField, [AssignedCell<Fp, Fp>; 2])>,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct AccountGadget {
     old_state: AccountChipConfig,
@@ -103,9 +127,10 @@ impl AccountGadget {
 
         // transition
         meta.lookup("account row trans", |meta| {
+            // every enabled row except the first one
             let s_enable = meta.query_advice(s_enable, Rotation::cur())
-                * (Expression::Constant(Fp::one()))
-                - meta.query_advice(s_ctrl_type[0], Rotation::cur());
+                * (Expression::Constant(Fp::one())
+                    - meta.query_advice(s_ctrl_type[0], Rotation::cur()));
             tables.build_lookup(
                 s_enable,
                 meta.query_advice(ctrl_type, Rotation::prev()),
@@ -116,6 +141,9 @@ impl AccountGadget {
 
         // ???
         if let Some(address_index) = address_index {
+            // the address limbs can be copied out for binding with the signer
+            meta.enable_equality(old_state.intermediate_1);
+            meta.enable_equality(new_state.intermediate_1);
             meta.create_gate("address constraint", |meta| {
                 let s_enable =
                     meta.query_selector(sel) * meta.query_advice(s_enable, Rotation::cur());
@@ -148,7 +176,11 @@ impl AccountGadget {
             });
         }
 
-        // this gate constraint each gadget handle at most one change in account data
+        // this gate constraint each gadget handle at most one change in account data:
+        // state_change_key accumulates the changed fields over the enabled rows, so
+        // it reaches 2 and breaks the boolean check once a second field changes; the
+        // state root row is only enabled when the root is kept, so an update of the
+        // storage can come along with one field change
         meta.create_gate("single update for account data", |meta| {
             let enable = meta.query_selector(sel) * meta.query_advice(s_enable, Rotation::cur());
             let data_diff = meta.query_advice(data_old, Rotation::cur())
@@ -214,6 +246,13 @@ impl AccountGadget {
         }
     }
 
+    /// enable the copy of the account hash and data cells, which is required
+    /// by `assign_disclosed`
+    pub fn enable_disclosure<Fp: FieldExt>(&self, meta: &mut ConstraintSystem<Fp>) {
+        meta.enable_equality(self.old_state.acc_data_fields);
+        meta.enable_equality(self.new_state.acc_data_fields);
+    }
+
     /// assign data and enable flag for account circuit
     pub fn assign<'d, Fp: FieldExt>(
         &self,
@@ -223,6 +262,72 @@ impl AccountGadget {
         address: KeyValue<Fp>,
         apply_last_row: Option<bool>,
     ) -> Result<usize, Error> {
+        self.assign_with_address(region, offset, data, address, apply_last_row)
+            .map(|(end_offset, _)| end_offset)
+    }
+
+    /// same as `assign`, and also return the cells of the address limbs
+    /// (limb_0, limb_1)
+    pub fn assign_with_address<'d, Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        data: (&'d Account<Fp>, &'d Account<Fp>),
+        address: KeyValue<Fp>,
+        apply_last_row: Option<bool>,
+    ) -> Result<(usize, [AssignedCell<Fp, Fp>; 2]), Error> {
+        self.assign_cells(region, offset, data, address, apply_last_row)
+            .map(|(end_offset, address_limbs, _, _)| (end_offset, address_limbs))
+    }
+
+    /// same as `assign`, and also return the cells of the account hashes and
+    /// the `disclosed` fields, for proving the transition without exposing
+    /// the other fields, which are not hidden by the hashes (see
+    /// `AccountDisclosure`)
+    pub fn assign_disclosed<'d, Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        data: (&'d Account<Fp>, &'d Account<Fp>),
+        address: KeyValue<Fp>,
+        apply_last_row: Option<bool>,
+        disclosed: &[AccountField],
+    ) -> Result<(usize, AccountDisclosure<Fp>), Error> {
+        let (end_offset, _, (old_hash, old_fields), (new_hash, new_fields)) =
+            self.assign_cells(region, offset, data, address, apply_last_row)?;
+        let fields = disclosed
+            .iter()
+            .map(|field| {
+                let row = *field as usize;
+                (*field, [old_fields[row].clone(), new_fields[row].clone()])
+            })
+            .collect();
+
+        Ok((
+            end_offset,
+            AccountDisclosure {
+                account_hash: [old_hash, new_hash],
+                fields,
+            },
+        ))
+    }
+
+    fn assign_cells<'d, Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        data: (&'d Account<Fp>, &'d Account<Fp>),
+        address: KeyValue<Fp>,
+        apply_last_row: Option<bool>,
+    ) -> Result<
+        (
+            usize,
+            [AssignedCell<Fp, Fp>; 2],
+            AccountCells<Fp>,
+            AccountCells<Fp>,
+        ),
+        Error,
+    > {
         let old_acc_chip = AccountChip::<Fp> {
             offset,
             config: &self.old_state,
@@ -246,16 +351,22 @@ impl AccountGadget {
 
         let end_offset = offset + CIRCUIT_ROW - if apply_last_row { 0 } else { 1 };
 
-        old_acc_chip.assign(region)?;
-        new_acc_chip.assign(region)?;
+        let old_cells = old_acc_chip.assign(region)?;
+        let new_cells = new_acc_chip.assign(region)?;
 
         // overwrite the datalimb in first row for address
-        for (col, val) in [
-            (old_acc_chip.config.intermediate_1, address.limb_0()),
-            (new_acc_chip.config.intermediate_1, address.limb_1()),
-        ] {
-            region.assign_advice(|| "address assignment", col, offset, || Value::known(val))?;
-        }
+        let address_limb_0 = region.assign_advice(
+            || "address assignment",
+            old_acc_chip.config.intermediate_1,
+            offset,
+            || Value::known(address.limb_0()),
+        )?;
+        let address_limb_1 = region.assign_advice(
+            || "address assignment",
+            new_acc_chip.config.intermediate_1,
+            offset,
+            || Value::known(address.limb_1()),
+        )?;
 
         let mut has_data_delta = false;
         for (index, offset) in (offset..end_offset).enumerate() {
@@ -336,7 +447,12 @@ impl AccountGadget {
             )?;
         }
 
-        Ok(end_offset)
+        Ok((
+            end_offset,
+            [address_limb_0, address_limb_1],
+            old_cells,
+            new_cells,
+        ))
     }
 }
 
@@ -447,12 +563,12 @@ impl<'d, Fp: FieldExt> AccountChip<'d, Fp> {
         }
     }
 
-    fn assign(&self, region: &mut Region<'_, Fp>) -> Result<usize, Error> {
+    fn assign(&self, region: &mut Region<'_, Fp>) -> Result<AccountCells<Fp>, Error> {
         let config = self.config();
         let data = self.loaded();
         // fill the connected circuit
         let offset = self.offset - 1;
-        region.assign_advice(
+        let hash_cell = region.assign_advice(
             || "account hash final",
             config.acc_data_fields,
             offset,
@@ -460,6 +576,7 @@ impl<'d, Fp: FieldExt> AccountChip<'d, Fp> {
         )?;
 
         // fill the main block of chip
+        let mut field_cells = Vec::new();
         for (col, vals, desc) in [
             (
                 config.acc_data_fields,
@@ -483,22 +600,25 @@ impl<'d, Fp: FieldExt> AccountChip<'d, Fp> {
             ),
         ] {
             for (i, val) in vals.iter().enumerate() {
-                region.assign_advice(
+                let cell = region.assign_advice(
                     || format!("{} row {} (offset {})", desc, i, self.offset),
                     col,
                     self.offset + i,
                     || Value::known(*val),
                 )?;
+                if col == config.acc_data_fields {
+                    field_cells.push(cell);
+                }
             }
         }
 
         // row 4: notice this is not belong to account chip in general
-        region.assign_advice(
+        field_cells.push(region.assign_advice(
             || "state root",
             config.acc_data_fields,
             self.offset + LAST_ROW,
             || Value::known(self.data.state_root),
-        )?;
+        )?);
 
         region.assign_advice(
             || "state root padding",
@@ -507,7 +627,10 @@ impl<'d, Fp: FieldExt> AccountChip<'d, Fp> {
             || Value::known(Fp::zero()),
         )?;
 
-        Ok(self.offset + LAST_ROW)
+        Ok((
+            hash_cell,
+            field_cells.try_into().expect("one cell for each row"),
+        ))
     }
 }
 
@@ -515,17 +638,19 @@ impl<'d, Fp: FieldExt> AccountChip<'d, Fp> {
 mod tests {
     #![allow(unused_imports)]
     use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner, Value};
-    use halo2_proofs::dev::MockProver;
-    use halo2_proofs::plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Selector};
+    use halo2_proofs::dev::{MockProver, VerifyFailure};
+    use halo2_proofs::plonk::{
+        Advice, Circuit, Column, ConstraintSystem, Error, Instance, Selector,
+    };
     use hash_circuit::Hashable;
 
     use crate::gadgets::{hash_util, table_util};
     use crate::operation::Account;
 
     use crate::test_utils::Fp;
-    use crate::test_utils::{hash_str_to_fp, mock_hash, rand_fp};
+    use crate::test_utils::{assert_circuit_satisfied, hash_str_to_fp, mock_hash, rand_fp};
 
-    use super::{AccountGadget, CIRCUIT_ROW};
+    use super::{AccountDisclosure, AccountField, AccountGadget, CIRCUIT_ROW};
 
     #[derive(Clone, Debug)]
     struct AccountTestConfig {
@@ -592,8 +717,19 @@ mod tests {
         fn synthesize(
             &self,
             config: Self::Config,
-            mut layouter: impl Layouter<Fp>,
+            layouter: impl Layouter<Fp>,
         ) -> Result<(), Error> {
+            self.assign_account(&config, layouter, &[]).map(|_| ())
+        }
+    }
+
+    impl AccountTestCircuit {
+        fn assign_account(
+            &self,
+            config: &AccountTestConfig,
+            mut layouter: impl Layouter<Fp>,
+            disclosed: &[AccountField],
+        ) -> Result<AccountDisclosure<Fp>, Error> {
             // initialize the op table
             config
                 .op_tabl
@@ -631,12 +767,13 @@ mod tests {
                         }
                     }
 
-                    let till = config.gadget.assign(
+                    let (till, disclosure) = config.gadget.assign_disclosed(
                         &mut region,
                         1,
                         (&self.data.0, &self.data.1),
                         Default::default(),
                         None,
+                        disclosed,
                     )?;
                     for offset in 1..till {
                         config.sel.enable(&mut region, offset)?;
@@ -649,12 +786,59 @@ mod tests {
                             || Value::known(Fp::zero()),
                         )?;
                     }
-                    Ok(())
+                    Ok(disclosure)
                 },
             )
         }
     }
 
+    // expose the account hashes and the selected fields of the transition
+    #[derive(Clone, Default)]
+    struct DisclosureTestCircuit {
+        account: AccountTestCircuit,
+        disclosed: Vec<AccountField>,
+    }
+
+    impl Circuit<Fp> for DisclosureTestCircuit {
+        type Config = (AccountTestConfig, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self {
+                disclosed: self.disclosed.clone(),
+                ..Default::default()
+            }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let config = AccountTestCircuit::configure(meta);
+            config.gadget.enable_disclosure(meta);
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            (config, instance)
+        }
+
+        fn synthesize(
+            &self,
+            (config, instance): Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let disclosure = self.account.assign_account(
+                &config,
+                layouter.namespace(|| "account"),
+                &self.disclosed,
+            )?;
+            let cells = disclosure
+                .account_hash
+                .iter()
+                .chain(disclosure.fields.iter().flat_map(|(_, cells)| cells));
+            for (row, cell) in cells.enumerate() {
+                layouter.constrain_instance(cell.cell(), instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
     #[test]
     fn test_single_account() {
         let acc_data = Account::<Fp> {
@@ -682,9 +866,185 @@ mod tests {
 
         let k = 5;
         #[cfg(feature = "print_layout")]
-        print_layout!("layouts/accgadget_layout.png", k, &circuit);
+        crate::utils::plot_layout(
+            &circuit,
+            k,
+            std::path::Path::new("layouts/accgadget_layout.png"),
+        )
+        .unwrap();
+
+        assert_circuit_satisfied(k, &circuit, vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "single update for account data")]
+    fn test_assert_circuit_satisfied_names_gate() {
+        let hasher = |a: &Fp, b: &Fp| <Fp as Hashable>::hash([*a, *b]);
+        let old_acc_data = Account::<Fp> {
+            gas_balance: Fp::from(100000u64),
+            nonce: Fp::from(41u64),
+            ..Default::default()
+        }
+        .complete(hasher);
+        let acc_data = Account::<Fp> {
+            gas_balance: Fp::from(99999u64),
+            nonce: Fp::from(42u64),
+            hash_traces: Vec::new(),
+            ..old_acc_data.clone()
+        }
+        .complete(hasher);
 
-        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
-        assert_eq!(prover.verify(), Ok(()));
+        let circuit = AccountTestCircuit {
+            data: (old_acc_data, acc_data),
+        };
+        assert_circuit_satisfied(5, &circuit, vec![]);
+    }
+
+    #[test]
+    fn test_disclose_nonce_only() {
+        let hasher = |a: &Fp, b: &Fp| <Fp as Hashable>::hash([*a, *b]);
+        let old_acc_data = Account::<Fp> {
+            gas_balance: Fp::from(100000u64),
+            address: hash_str_to_fp("0x1c5a77d9fa7ef466951b2f01f724bca3a5820b63"),
+            nonce: Fp::from(41u64),
+            state_root: rand_fp(),
+            ..Default::default()
+        }
+        .complete(hasher);
+        let acc_data = Account::<Fp> {
+            nonce: Fp::from(42u64),
+            hash_traces: Vec::new(),
+            ..old_acc_data.clone()
+        }
+        .complete(hasher);
+
+        let circuit = DisclosureTestCircuit {
+            account: AccountTestCircuit {
+                data: (old_acc_data.clone(), acc_data.clone()),
+            },
+            disclosed: vec![AccountField::Nonce],
+        };
+        let instance = vec![
+            old_acc_data.account_hash(),
+            acc_data.account_hash(),
+            Fp::from(41u64),
+            Fp::from(42u64),
+        ];
+        // the balance is only committed by the account hashes
+        assert!(!instance.contains(&old_acc_data.gas_balance));
+
+        assert_circuit_satisfied(5, &circuit, vec![instance.clone()]);
+
+        let mut wrong_nonce = instance.clone();
+        wrong_nonce[3] = Fp::from(43u64);
+        let prover = MockProver::<Fp>::run(5, &circuit, vec![wrong_nonce]).unwrap();
+        assert!(prover.verify().is_err());
+
+        let mut wrong_hash = instance.clone();
+        wrong_hash[1] = old_acc_data.account_hash();
+        let prover = MockProver::<Fp>::run(5, &circuit, vec![wrong_hash]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // the private balance is bound by the hashes in the instance, with or
+        // without the hash traces following the forged balance
+        let forge = |acc: &Account<Fp>, rehash: bool| {
+            let forged = Account::<Fp> {
+                gas_balance: Fp::from(99999u64),
+                ..acc.clone()
+            };
+            if rehash {
+                Account::<Fp> {
+                    hash_traces: Vec::new(),
+                    ..forged
+                }
+                .complete(hasher)
+            } else {
+                forged
+            }
+        };
+        for rehash in [false, true] {
+            let circuit = DisclosureTestCircuit {
+                account: AccountTestCircuit {
+                    data: (forge(&old_acc_data, rehash), forge(&acc_data, rehash)),
+                },
+                disclosed: vec![AccountField::Nonce],
+            };
+            let prover = MockProver::<Fp>::run(5, &circuit, vec![instance.clone()]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    fn verify_change(change: impl FnOnce(&mut Account<Fp>)) -> Result<(), Vec<VerifyFailure>> {
+        let old_acc_data = Account::<Fp> {
+            gas_balance: Fp::from(100000u64),
+            address: hash_str_to_fp("0x1c5a77d9fa7ef466951b2f01f724bca3a5820b63"),
+            nonce: Fp::from(41u64),
+            recrusive_tx_hash: rand_fp(),
+            state_root: rand_fp(),
+            ..Default::default()
+        };
+        let mut acc_data = old_acc_data.clone();
+        change(&mut acc_data);
+
+        let circuit = AccountTestCircuit {
+            data: (
+                old_acc_data.complete(|a, b| <Fp as Hashable>::hash([*a, *b])),
+                acc_data.complete(|a, b| <Fp as Hashable>::hash([*a, *b])),
+            ),
+        };
+        MockProver::<Fp>::run(5, &circuit, vec![]).unwrap().verify()
+    }
+
+    fn assert_single_update_violated(result: Result<(), Vec<VerifyFailure>>) {
+        let failures = result.expect_err("multiple changes must be rejected");
+        assert!(failures
+            .iter()
+            .any(|f| f.to_string().contains("single update for account data")));
+    }
+
+    #[test]
+    fn test_single_field_change() {
+        assert_eq!(verify_change(|_| {}), Ok(()));
+        assert_eq!(verify_change(|acc| acc.nonce += Fp::one()), Ok(()));
+        assert_eq!(verify_change(|acc| acc.gas_balance -= Fp::one()), Ok(()));
+        assert_eq!(
+            verify_change(|acc| acc.recrusive_tx_hash = rand_fp()),
+            Ok(())
+        );
+        // the state root row is disabled when the root changes
+        assert_eq!(verify_change(|acc| acc.state_root = rand_fp()), Ok(()));
+        assert_eq!(
+            verify_change(|acc| {
+                acc.nonce += Fp::one();
+                acc.state_root = rand_fp();
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_multiple_field_change() {
+        assert_single_update_violated(verify_change(|acc| {
+            acc.nonce += Fp::one();
+            acc.gas_balance -= Fp::one();
+        }));
+        assert_single_update_violated(verify_change(|acc| {
+            acc.nonce += Fp::one();
+            acc.recrusive_tx_hash = rand_fp();
+        }));
+        assert_single_update_violated(verify_change(|acc| {
+            acc.gas_balance -= Fp::one();
+            acc.recrusive_tx_hash = rand_fp();
+        }));
+        assert_single_update_violated(verify_change(|acc| {
+            acc.nonce += Fp::one();
+            acc.gas_balance -= Fp::one();
+            acc.recrusive_tx_hash = rand_fp();
+        }));
+        assert_single_update_violated(verify_change(|acc| {
+            acc.nonce += Fp::one();
+            acc.gas_balance -= Fp::one();
+            acc.state_root = rand_fp();
+        }));
     }
 }
@@ -7,27 +7,134 @@ use ecc::{
 use ecdsa::ecdsa::{AssignedEcdsaSig, AssignedPublicKey, EcdsaChip};
 use halo2_proofs::{
     circuit::{Layouter, SimpleFloorPlanner, Value},
-    halo2curves::{CurveAffine, FieldExt},
+    halo2curves::{group::ff::PrimeField, CurveAffine, FieldExt},
     plonk::{Circuit, ConstraintSystem, Error},
 };
 use maingate::{MainGate, MainGateConfig, RangeChip, RangeConfig, RangeInstructions, RegionCtx};
 
 const BIT_LEN_LIMB: usize = 68;
 const NUMBER_OF_LIMBS: usize = 4;
+const DEFAULT_WINDOW_SIZE: usize = 2;
+
+/// the public key, the signature (r, s) and the msg hash of an ecdsa verification
+pub(crate) type EcdsaInput<E> = (
+    Value<E>,
+    Value<(<E as CurveAffine>::ScalarExt, <E as CurveAffine>::ScalarExt)>,
+    Value<<E as CurveAffine>::ScalarExt>,
+);
 
 #[derive(Clone, Debug)]
-pub(crate) struct Spec256k1Gadget {}
+pub(crate) struct Spec256k1Gadget<E: CurveAffine> {
+    aux_generator: E,
+    window_size: usize,
+}
+
+impl<E: CurveAffine> Default for Spec256k1Gadget<E> {
+    fn default() -> Self {
+        Self {
+            aux_generator: E::default(),
+            window_size: DEFAULT_WINDOW_SIZE,
+        }
+    }
+}
+
+impl<E: CurveAffine> Spec256k1Gadget<E> {
+    /// create the gadget with the aux generator and the window size of the
+    /// scalar multiplication, a larger window takes fewer rows but more
+    /// aux points; the window must divide the bits of scalar cleanly
+    pub fn new(aux_generator: E, window_size: usize) -> Result<Self, Error> {
+        let scalar_bits = E::ScalarExt::NUM_BITS as usize;
+        if window_size == 0 || scalar_bits % window_size != 0 {
+            return Err(Error::Synthesis);
+        }
+
+        Ok(Self {
+            aux_generator,
+            window_size,
+        })
+    }
+
+    pub fn aux_generator(&self) -> E {
+        self.aux_generator
+    }
 
-impl Spec256k1Gadget {
-    pub fn configure<Fp: FieldExt>(meta: &mut ConstraintSystem<Fp>) -> Self {
-        Self {}
+    pub fn window_size(&self) -> usize {
+        self.window_size
     }
 
-    pub fn verify_sig() {}
+    pub fn configure<N: FieldExt>(meta: &mut ConstraintSystem<N>) -> CircuitEcdsaVerifyConfig {
+        CircuitEcdsaVerifyConfig::new::<E, N>(meta)
+    }
+
+    /// assign the aux generator and the aux points for the window
+    pub fn assign_aux<N: FieldExt>(
+        &self,
+        ecc_chip: &mut GeneralEccChip<E, N, NUMBER_OF_LIMBS, BIT_LEN_LIMB>,
+        layouter: &mut impl Layouter<N>,
+    ) -> Result<(), Error> {
+        layouter.assign_region(
+            || "assign aux values",
+            |region| {
+                let offset = 0;
+                let ctx = &mut RegionCtx::new(region, offset);
+
+                ecc_chip.assign_aux_generator(ctx, Value::known(self.aux_generator))?;
+                ecc_chip.assign_aux(ctx, self.window_size, 1)?;
+                Ok(())
+            },
+        )
+    }
+
+    /// verify one signature in the region, the aux values must have been
+    /// assigned in the ecc chip
+    pub fn verify_sig<N: FieldExt>(
+        ecc_chip: &GeneralEccChip<E, N, NUMBER_OF_LIMBS, BIT_LEN_LIMB>,
+        ctx: &mut RegionCtx<'_, N>,
+        (public_key, signature, msg_hash): &EcdsaInput<E>,
+    ) -> Result<(), Error> {
+        let ecdsa_chip = EcdsaChip::new(ecc_chip.clone());
+        let scalar_chip = ecc_chip.scalar_field_chip();
+
+        let r = signature.map(|signature| signature.0);
+        let s = signature.map(|signature| signature.1);
+        let integer_r = ecc_chip.new_unassigned_scalar(r);
+        let integer_s = ecc_chip.new_unassigned_scalar(s);
+        let msg_hash = ecc_chip.new_unassigned_scalar(*msg_hash);
+
+        let r_assigned = scalar_chip.assign_integer(ctx, integer_r, Range::Remainder)?;
+        let s_assigned = scalar_chip.assign_integer(ctx, integer_s, Range::Remainder)?;
+        let sig = AssignedEcdsaSig {
+            r: r_assigned,
+            s: s_assigned,
+        };
+
+        let pk_in_circuit = ecc_chip.assign_point(ctx, *public_key)?;
+        let pk_assigned = AssignedPublicKey {
+            point: pk_in_circuit,
+        };
+        let msg_hash = scalar_chip.assign_integer(ctx, msg_hash, Range::Remainder)?;
+        ecdsa_chip.verify(ctx, &sig, &pk_assigned, &msg_hash)
+    }
+
+    /// verify a batch of signatures in the region, the aux generator and aux
+    /// points are assigned only once and shared by all the verifications
+    pub fn verify_batch<N: FieldExt>(
+        &self,
+        ecc_chip: &mut GeneralEccChip<E, N, NUMBER_OF_LIMBS, BIT_LEN_LIMB>,
+        ctx: &mut RegionCtx<'_, N>,
+        sigs: &[EcdsaInput<E>],
+    ) -> Result<(), Error> {
+        ecc_chip.assign_aux_generator(ctx, Value::known(self.aux_generator))?;
+        ecc_chip.assign_aux(ctx, self.window_size, 1)?;
+        for sig in sigs {
+            Self::verify_sig(ecc_chip, ctx, sig)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
-struct CircuitEcdsaVerifyConfig {
+pub(crate) struct CircuitEcdsaVerifyConfig {
     main_gate_config: MainGateConfig,
     range_config: RangeConfig,
 }
@@ -71,8 +178,7 @@ struct CircuitEcdsaVerify<E: CurveAffine, N: FieldExt> {
     signature: Value<(E::Scalar, E::Scalar)>,
     msg_hash: Value<E::Scalar>,
 
-    aux_generator: E,
-    window_size: usize,
+    gadget: Spec256k1Gadget<E>,
     _marker: PhantomData<N>,
 }
 
@@ -81,11 +187,14 @@ impl<E: CurveAffine, N: FieldExt> Circuit<N> for CircuitEcdsaVerify<E, N> {
     type FloorPlanner = SimpleFloorPlanner;
 
     fn without_witnesses(&self) -> Self {
-        Self::default()
+        Self {
+            gadget: self.gadget.clone(),
+            ..Default::default()
+        }
     }
 
     fn configure(meta: &mut ConstraintSystem<N>) -> Self::Config {
-        CircuitEcdsaVerifyConfig::new::<E, N>(meta)
+        Spec256k1Gadget::<E>::configure(meta)
     }
 
     fn synthesize(
@@ -96,20 +205,7 @@ impl<E: CurveAffine, N: FieldExt> Circuit<N> for CircuitEcdsaVerify<E, N> {
         let mut ecc_chip =
             GeneralEccChip::<E, N, NUMBER_OF_LIMBS, BIT_LEN_LIMB>::new(config.ecc_chip_config());
 
-        layouter.assign_region(
-            || "assign aux values",
-            |region| {
-                let offset = 0;
-                let ctx = &mut RegionCtx::new(region, offset);
-
-                ecc_chip.assign_aux_generator(ctx, Value::known(self.aux_generator))?;
-                ecc_chip.assign_aux(ctx, self.window_size, 1)?;
-                Ok(())
-            },
-        )?;
-
-        let ecdsa_chip = EcdsaChip::new(ecc_chip.clone());
-        let scalar_chip = ecc_chip.scalar_field_chip();
+        self.gadget.assign_aux(&mut ecc_chip, &mut layouter)?;
 
         layouter.assign_region(
             || "region 0",
@@ -117,25 +213,11 @@ impl<E: CurveAffine, N: FieldExt> Circuit<N> for CircuitEcdsaVerify<E, N> {
                 let offset = 0;
                 let ctx = &mut RegionCtx::new(region, offset);
 
-                let r = self.signature.map(|signature| signature.0);
-                let s = self.signature.map(|signature| signature.1);
-                let integer_r = ecc_chip.new_unassigned_scalar(r);
-                let integer_s = ecc_chip.new_unassigned_scalar(s);
-                let msg_hash = ecc_chip.new_unassigned_scalar(self.msg_hash);
-
-                let r_assigned = scalar_chip.assign_integer(ctx, integer_r, Range::Remainder)?;
-                let s_assigned = scalar_chip.assign_integer(ctx, integer_s, Range::Remainder)?;
-                let sig = AssignedEcdsaSig {
-                    r: r_assigned,
-                    s: s_assigned,
-                };
-
-                let pk_in_circuit = ecc_chip.assign_point(ctx, self.public_key)?;
-                let pk_assigned = AssignedPublicKey {
-                    point: pk_in_circuit,
-                };
-                let msg_hash = scalar_chip.assign_integer(ctx, msg_hash, Range::Remainder)?;
-                ecdsa_chip.verify(ctx, &sig, &pk_assigned, &msg_hash)
+                Spec256k1Gadget::verify_sig(
+                    &ecc_chip,
+                    ctx,
+                    &(self.public_key, self.signature, self.msg_hash),
+                )
             },
         )?;
 
@@ -152,23 +234,38 @@ mod tests {
     use halo2_proofs::{
         arithmetic::{CurveAffine, Field, FieldExt},
         circuit::Value,
+        dev::MockProver,
+        halo2curves::{
+            bn256::Fr as BnScalar,
+            secp256k1::{Secp256k1, Secp256k1Affine},
+        },
     };
     use maingate::{big_to_fe, fe_to_big, mock_prover_verify};
     use rand::{rngs::OsRng, thread_rng};
     // use crate::{test_utils::{Fp}};
     use halo2_proofs::halo2curves::secp256k1::Fp;
 
-    use crate::gadgets::ecsdsa::CircuitEcdsaVerify;
+    use crate::gadgets::ecsdsa::{
+        CircuitEcdsaVerify, CircuitEcdsaVerifyConfig, EcdsaInput, Spec256k1Gadget, BIT_LEN_LIMB,
+        NUMBER_OF_LIMBS,
+    };
+    use ecc::GeneralEccChip;
     use ethers::{
         prelude::k256::ecdsa::SigningKey,
         signers::{LocalWallet, Signer, Wallet},
         utils::hash_message,
     };
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        plonk::{Circuit, ConstraintSystem, Error},
+    };
     use hex_literal::hex;
     use k256::{
         elliptic_curve::{ops::MulByGenerator, PrimeField},
         ProjectivePoint, Scalar,
     };
+    use maingate::RegionCtx;
+    use std::cell::Cell;
 
     macro_rules! aw {
         ($e:expr) => {
@@ -212,13 +309,178 @@ mod tests {
         println!("signatrue data R: {R} S: {S} HASH: {HASH:?}");
     }
 
+    fn mod_n<C: CurveAffine>(x: C::Base) -> C::Scalar {
+        let x_big = fe_to_big(x);
+        big_to_fe(x_big)
+    }
+
+    fn random_sig<C: CurveAffine>() -> EcdsaInput<C> {
+        let g = C::generator();
+        let sk = <C as CurveAffine>::ScalarExt::random(OsRng);
+        let public_key = (g * sk).to_affine();
+        let msg_hash = <C as CurveAffine>::ScalarExt::random(OsRng);
+
+        let k = <C as CurveAffine>::ScalarExt::random(OsRng);
+        let r = mod_n::<C>(*(g * k).to_affine().coordinates().unwrap().x());
+        let s = k.invert().unwrap() * (msg_hash + (r * sk));
+
+        (
+            Value::known(public_key),
+            Value::known((r, s)),
+            Value::known(msg_hash),
+        )
+    }
+
+    fn random_circuit<C: CurveAffine, N: FieldExt>(window_size: usize) -> CircuitEcdsaVerify<C, N> {
+        let (public_key, signature, msg_hash) = random_sig::<C>();
+        let aux_generator = C::CurveExt::random(OsRng).to_affine();
+        CircuitEcdsaVerify::<C, N> {
+            public_key,
+            signature,
+            msg_hash,
+            gadget: Spec256k1Gadget::new(aux_generator, window_size).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    /// verify the signatures in one region, either with the aux values shared
+    /// or assigned for each signature, and record the rows it takes
+    struct BatchVerifyCircuit {
+        sigs: Vec<EcdsaInput<Secp256k1Affine>>,
+        gadget: Spec256k1Gadget<Secp256k1Affine>,
+        batched: bool,
+        rows: Cell<usize>,
+    }
+
+    impl BatchVerifyCircuit {
+        fn new(n: usize, batched: bool) -> Self {
+            Self {
+                sigs: (0..n).map(|_| random_sig()).collect(),
+                gadget: Spec256k1Gadget::new(Secp256k1::random(OsRng).to_affine(), 2).unwrap(),
+                batched,
+                rows: Cell::new(0),
+            }
+        }
+    }
+
+    impl Circuit<BnScalar> for BatchVerifyCircuit {
+        type Config = CircuitEcdsaVerifyConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self {
+                sigs: vec![(Value::unknown(), Value::unknown(), Value::unknown()); self.sigs.len()],
+                gadget: self.gadget.clone(),
+                batched: self.batched,
+                rows: Cell::new(0),
+            }
+        }
+
+        fn configure(meta: &mut ConstraintSystem<BnScalar>) -> Self::Config {
+            Spec256k1Gadget::<Secp256k1Affine>::configure(meta)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<BnScalar>,
+        ) -> Result<(), Error> {
+            let mut ecc_chip =
+                GeneralEccChip::<Secp256k1Affine, BnScalar, NUMBER_OF_LIMBS, BIT_LEN_LIMB>::new(
+                    config.ecc_chip_config(),
+                );
+
+            layouter.assign_region(
+                || "batch verify",
+                |region| {
+                    let ctx = &mut RegionCtx::new(region, 0);
+                    if self.batched {
+                        self.gadget.verify_batch(&mut ecc_chip, ctx, &self.sigs)?;
+                    } else {
+                        for sig in &self.sigs {
+                            self.gadget.verify_batch(
+                                &mut ecc_chip,
+                                ctx,
+                                std::slice::from_ref(sig),
+                            )?;
+                        }
+                    }
+                    self.rows.set(ctx.offset());
+                    Ok(())
+                },
+            )?;
+
+            config.config_range(&mut layouter)
+        }
+    }
+
+    /// the k the batch circuits are verified at in the default suite
+    const BATCH_K: u32 = 20;
+
+    /// verify `n` signatures separately and batched at the k picked by
+    /// `pick_k`, return the rows taken by both
+    fn verify_batch_rows(n: usize, pick_k: impl Fn(&BatchVerifyCircuit) -> u32) -> [usize; 2] {
+        [false, true].map(|batched| {
+            let circuit = BatchVerifyCircuit::new(n, batched);
+            let prover = MockProver::run(pick_k(&circuit), &circuit, vec![]).unwrap();
+            assert_eq!(prover.verify(), Ok(()));
+            circuit.rows.get()
+        })
+    }
+
     #[test]
-    fn test_ecdsa_verifier() {
-        fn mod_n<C: CurveAffine>(x: C::Base) -> C::Scalar {
-            let x_big = fe_to_big(x);
-            big_to_fe(x_big)
+    fn test_verify_batch() {
+        let rows = verify_batch_rows(2, |_| BATCH_K);
+        assert!(rows[1] < rows[0]);
+    }
+
+    // search the smallest k for each batch size, which takes minutes:
+    // cargo test test_verify_batch_min_k -- --ignored
+    #[test]
+    #[ignore]
+    fn test_verify_batch_min_k() {
+        for n in [2, 4] {
+            let rows = verify_batch_rows(n, |circuit| {
+                (17..=22)
+                    .find(|k| MockProver::run(*k, circuit, vec![]).is_ok())
+                    .expect("circuit exceeds k = 22")
+            });
+            assert!(rows[1] < rows[0]);
         }
+    }
+
+    #[test]
+    fn test_window_size_validation() {
+        let aux_generator = Secp256k1::random(OsRng).to_affine();
+        assert!(Spec256k1Gadget::new(aux_generator, 0).is_err());
+        assert!(Spec256k1Gadget::new(aux_generator, 3).is_err());
+        for window_size in [1, 2, 4, 8] {
+            let gadget = Spec256k1Gadget::new(aux_generator, window_size).unwrap();
+            assert_eq!(gadget.window_size(), window_size);
+            assert_eq!(gadget.aux_generator(), aux_generator);
+        }
+    }
 
+    // search the smallest k for each window size, which takes minutes; the
+    // default window size is covered by `test_ecdsa_verifier`:
+    // cargo test test_rows_by_window_size -- --ignored
+    #[test]
+    #[ignore]
+    fn test_rows_by_window_size() {
+        for window_size in [1, 2, 4] {
+            let circuit = random_circuit::<Secp256k1Affine, BnScalar>(window_size);
+            // the smallest k the circuit can be laid out in
+            let k = (16..=22)
+                .find(|k| MockProver::run(*k, &circuit, vec![]).is_ok())
+                .expect("circuit exceeds k = 22");
+
+            let prover = MockProver::run(k, &circuit, vec![]).unwrap();
+            assert_eq!(prover.verify(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_ecdsa_verifier() {
         fn run<C: CurveAffine, N: FieldExt>() {
             let g = C::generator();
 
@@ -271,17 +533,14 @@ mod tests {
                 public_key: Value::known(public_key),
                 signature: Value::known((r, s)),
                 msg_hash: Value::known(msg_hash),
-                aux_generator,
-                window_size: 2,
+                gadget: Spec256k1Gadget::new(aux_generator, 2).unwrap(),
                 ..Default::default()
             };
             let instance = vec![vec![]];
             mock_prover_verify(&circuit, instance);
         }
 
-        use halo2_proofs::halo2curves::bn256::Fr as BnScalar;
         use halo2_proofs::halo2curves::pasta::{Fp as PastaFp, Fq as PastaFq};
-        use halo2_proofs::halo2curves::secp256k1::Secp256k1Affine;
         run::<Secp256k1Affine, BnScalar>();
         // run::<Secp256k1Affine, PastaFp>();
         // run::<Secp256k1Affine, PastaFq>();
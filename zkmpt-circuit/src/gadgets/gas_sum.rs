@@ -0,0 +1,391 @@
+use std::marker::PhantomData;
+
+use eth_types::Field;
+use ethers::types::U256;
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    plonk::{Advice, Column, ConstraintSystem, Error, Expression, Instance, Selector, TableColumn},
+    poly::Rotation,
+};
+
+use super::{
+    hashes_sum::{Number, SumChip, SumConfig},
+    u256_to_fp,
+};
+use crate::ERC4337::bundler::Transaction;
+
+/// the bits of a limb the gas fields are decomposed into
+const LIMB_BITS: usize = 4;
+/// the limbs of the gas limit, which is up to 64 bits
+const GAS_LIMIT_LIMBS: usize = 16;
+/// the limbs of the gas price, which is up to 128 bits
+const GAS_PRICE_LIMBS: usize = 32;
+/// the rows of the gas fee of a tx: the limbs of its gas limit followed by
+/// the limbs of its gas price
+const FEE_ROWS: usize = GAS_LIMIT_LIMBS + GAS_PRICE_LIMBS;
+
+/// the columns of `GasSumChip`: the gas fee of each tx is computed from its
+/// range checked gas fields, and copied into the elements of `SumConfig`
+#[derive(Debug, Clone)]
+pub struct GasSumConfig<F: Field> {
+    sum: SumConfig<F>,
+    limb: Column<Advice>,
+    acc: Column<Advice>,
+    fee: Column<Advice>,
+    q_first: Selector,
+    q_acc: Selector,
+    q_limb: Selector,
+    q_fee: Selector,
+    limb_table: TableColumn,
+}
+
+impl<F: Field> GasSumConfig<F> {
+    /// the instance column the sum is exposed to
+    pub fn instance(&self) -> Column<Instance> {
+        self.sum.instance()
+    }
+}
+
+/// Sums the gas fee `gas_limit * gas_price` of every tx in a batch on top of
+/// `SumChip`, the total is the declared block gas exposed as public input.
+/// Each fee is constrained to the product of the gas fields of its tx, which
+/// are range checked into 64 and 128 bits, so a fee is under 2^192 and the
+/// sum of fewer than 2^61 fees never wraps. The gas fields are witnessed
+/// from the txs natively, they are bound to the tx signatures only once the
+/// signatures are verified in the circuit.
+#[derive(Debug, Clone)]
+pub struct GasSumChip<F: Field> {
+    sum_chip: SumChip<F>,
+}
+
+impl<F: Field> Default for GasSumChip<F> {
+    fn default() -> Self {
+        Self {
+            sum_chip: SumChip {
+                _marker: PhantomData,
+            },
+        }
+    }
+}
+
+impl<F: Field> GasSumChip<F> {
+    pub fn configure(meta: &mut ConstraintSystem<F>) -> GasSumConfig<F> {
+        let sum = SumChip::configure(meta);
+        let limb = meta.advice_column();
+        let acc = meta.advice_column();
+        let fee = meta.advice_column();
+        meta.enable_equality(fee);
+        let q_first = meta.selector();
+        let q_acc = meta.selector();
+        let q_limb = meta.complex_selector();
+        let q_fee = meta.selector();
+        let limb_table = meta.lookup_table_column();
+
+        meta.lookup("gas limb range", |meta| {
+            let q_limb = meta.query_selector(q_limb);
+            vec![(
+                q_limb * meta.query_advice(limb, Rotation::cur()),
+                limb_table,
+            )]
+        });
+
+        // a gas field is accumulated from its limbs in big-endian
+        meta.create_gate("gas field first limb", |meta| {
+            let q_first = meta.query_selector(q_first);
+            let limb = meta.query_advice(limb, Rotation::cur());
+            vec![q_first * (meta.query_advice(acc, Rotation::cur()) - limb)]
+        });
+        meta.create_gate("gas field limbs", |meta| {
+            let q_acc = meta.query_selector(q_acc);
+            let limb = meta.query_advice(limb, Rotation::cur());
+            let acc_prev = meta.query_advice(acc, Rotation::prev());
+            let base = Expression::Constant(F::from(1u64 << LIMB_BITS));
+            vec![q_acc * (meta.query_advice(acc, Rotation::cur()) - acc_prev * base - limb)]
+        });
+
+        // on the last limb of the gas price, with the gas limit accumulated
+        // `GAS_PRICE_LIMBS` rows before
+        meta.create_gate("gas fee", |meta| {
+            let q_fee = meta.query_selector(q_fee);
+            let gas_limit = meta.query_advice(acc, Rotation(-(GAS_PRICE_LIMBS as i32)));
+            let gas_price = meta.query_advice(acc, Rotation::cur());
+            let fee = meta.query_advice(fee, Rotation::cur());
+            vec![q_fee * (fee - gas_limit * gas_price)]
+        });
+
+        GasSumConfig {
+            sum,
+            limb,
+            acc,
+            fee,
+            q_first,
+            q_acc,
+            q_limb,
+            q_fee,
+            limb_table,
+        }
+    }
+
+    /// the (gas limit, gas price) a tx pays its fee with, eip1559 txs without
+    /// a gas price use their fee cap and padding txs pay nothing
+    fn gas_fields(tx: &Transaction) -> (U256, U256) {
+        if tx.is_padding() {
+            return (U256::zero(), U256::zero());
+        }
+        let gas_price = tx.gas_price.or(tx.gas_fee_cap).unwrap_or_default();
+        (tx.gas_limit, gas_price)
+    }
+
+    /// the gas fee a tx contributes to the sum, see `gas_fields`
+    pub fn tx_gas_element(tx: &Transaction) -> F {
+        let (gas_limit, gas_price) = Self::gas_fields(tx);
+        u256_to_fp::<F>(gas_limit).unwrap() * u256_to_fp::<F>(gas_price).unwrap()
+    }
+
+    /// the block gas of `txs`, the value `assign` constrains
+    pub fn gas_sum(txs: &[Transaction]) -> F {
+        txs.iter()
+            .map(Self::tx_gas_element)
+            .fold(F::zero(), |acc, e| acc + e)
+    }
+
+    fn load_limb_table(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        config: &GasSumConfig<F>,
+    ) -> Result<(), Error> {
+        layouter.assign_table(
+            || "gas limb table",
+            |mut table| {
+                for limb in 0..(1u64 << LIMB_BITS) {
+                    table.assign_cell(
+                        || "gas limb",
+                        config.limb_table,
+                        limb as usize,
+                        || Value::known(F::from(limb)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// assign the limbs of the gas fields of a tx and return the cell of its
+    /// fee, a gas field exceeding its limbs fails the synthesis
+    fn assign_fee(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        config: &GasSumConfig<F>,
+        tx: &Transaction,
+    ) -> Result<AssignedCell<F, F>, Error> {
+        let (gas_limit, gas_price) = Self::gas_fields(tx);
+        let mut limbs = Vec::with_capacity(FEE_ROWS);
+        for (field, n) in [(gas_limit, GAS_LIMIT_LIMBS), (gas_price, GAS_PRICE_LIMBS)] {
+            if field.bits() > n * LIMB_BITS {
+                return Err(Error::Synthesis);
+            }
+            let mask = U256::from((1u64 << LIMB_BITS) - 1);
+            limbs.extend(
+                (0..n)
+                    .rev()
+                    .map(|i| ((field >> (i * LIMB_BITS)) & mask).as_u64()),
+            );
+        }
+
+        layouter.assign_region(
+            || "gas fee",
+            |mut region| {
+                let mut acc = U256::zero();
+                for (offset, limb) in limbs.iter().enumerate() {
+                    config.q_limb.enable(&mut region, offset)?;
+                    if offset == 0 || offset == GAS_LIMIT_LIMBS {
+                        config.q_first.enable(&mut region, offset)?;
+                        acc = U256::zero();
+                    } else {
+                        config.q_acc.enable(&mut region, offset)?;
+                    }
+                    acc = (acc << LIMB_BITS) + limb;
+                    region.assign_advice(
+                        || "gas limb",
+                        config.limb,
+                        offset,
+                        || Value::known(F::from(*limb)),
+                    )?;
+                    region.assign_advice(
+                        || "gas field",
+                        config.acc,
+                        offset,
+                        || Value::known(u256_to_fp::<F>(acc).unwrap()),
+                    )?;
+                }
+                config.q_fee.enable(&mut region, FEE_ROWS - 1)?;
+                region.assign_advice(
+                    || "gas fee",
+                    config.fee,
+                    FEE_ROWS - 1,
+                    || Value::known(Self::tx_gas_element(tx)),
+                )
+            },
+        )
+    }
+
+    /// constrain the sum of the gas fees of `txs`, padded with the zero fee
+    /// of padding txs up to `slots` rows so the layout only depends on
+    /// `slots`
+    pub fn assign(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        config: &GasSumConfig<F>,
+        txs: &[Transaction],
+        slots: usize,
+    ) -> Result<Number<F>, Error> {
+        if txs.len() > slots || slots == 0 {
+            return Err(Error::Synthesis);
+        }
+        self.load_limb_table(layouter, config)?;
+
+        let padding = Transaction::padding(0);
+        let txs = txs.iter().chain(std::iter::repeat(&padding)).take(slots);
+        let fees = txs
+            .map(|tx| self.assign_fee(layouter, config, tx))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let elements: Vec<Value<F>> = fees.iter().map(|fee| fee.value().copied()).collect();
+
+        let (element_cells, sum) = self.sum_chip.constraint_list_sum_elements(
+            layouter,
+            &config.sum,
+            &elements,
+            Value::known(F::zero()),
+        )?;
+        layouter.assign_region(
+            || "gas fee elements",
+            |mut region| {
+                fees.iter()
+                    .zip(&element_cells)
+                    .try_for_each(|(fee, element)| {
+                        region.constrain_equal(fee.cell(), element.cell())
+                    })
+            },
+        )?;
+        Ok(sum)
+    }
+
+    pub fn expose_public(
+        &self,
+        layouter: impl Layouter<F>,
+        config: &GasSumConfig<F>,
+        num: Number<F>,
+        row: usize,
+    ) -> Result<(), Error> {
+        self.sum_chip.expose_public(layouter, &config.sum, num, row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::U256;
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        dev::MockProver,
+        plonk::{Circuit, ConstraintSystem, Error},
+    };
+
+    use super::{GasSumChip, GasSumConfig};
+    use crate::{test_utils::Fp, zkprover_circuit::MOCK_RPC_TXS, ERC4337::bundler::Transaction};
+
+    // expose the gas sum of the txs in 2 slots
+    #[derive(Clone, Default)]
+    struct GasSumCircuit {
+        txs: Vec<Transaction>,
+    }
+
+    impl Circuit<Fp> for GasSumCircuit {
+        type Config = GasSumConfig<Fp>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            GasSumChip::configure(meta)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            let chip = GasSumChip::default();
+            let sum = chip.assign(&mut layouter, &config, &self.txs, 2)?;
+            chip.expose_public(layouter.namespace(|| "expose gas sum"), &config, sum, 0)
+        }
+    }
+
+    fn fixture_txs() -> Vec<Transaction> {
+        let rpc_txs = MOCK_RPC_TXS.clone().result.unwrap().tx_list;
+        rpc_txs.iter().map(|tr| tr.try_into().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_tx_gas_element() {
+        let rpc_txs = MOCK_RPC_TXS.clone().result.unwrap().tx_list;
+        let txs: Vec<Transaction> = rpc_txs.iter().map(|tr| tr.try_into().unwrap()).collect();
+
+        let expected = rpc_txs.iter().fold(Fp::zero(), |acc, tr| {
+            acc + Fp::from((tr.gas * tr.gas_price.unwrap_or_default()).as_u64())
+        });
+        assert_ne!(expected, Fp::zero());
+        assert_eq!(GasSumChip::<Fp>::gas_sum(&txs), expected);
+
+        let mut tx = txs[0].clone();
+        tx.gas_price = None;
+        tx.gas_fee_cap = Some(U256::from(7));
+        assert_eq!(
+            GasSumChip::<Fp>::tx_gas_element(&tx),
+            Fp::from(tx.gas_limit.as_u64() * 7)
+        );
+
+        let padding = Transaction::padding(5);
+        assert_eq!(GasSumChip::<Fp>::tx_gas_element(&padding), Fp::zero());
+    }
+
+    #[test]
+    fn test_gas_sum_circuit() {
+        let txs = fixture_txs();
+        let gas = GasSumChip::<Fp>::gas_sum(&txs);
+        let circuit = GasSumCircuit { txs: txs.clone() };
+
+        let prover = MockProver::<Fp>::run(7, &circuit, vec![vec![gas]]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+        let prover = MockProver::<Fp>::run(7, &circuit, vec![vec![gas + Fp::one()]]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // both slots are taken by the fixture tx
+        let double = GasSumCircuit {
+            txs: vec![txs[0].clone(); 2],
+        };
+        let prover = MockProver::<Fp>::run(7, &double, vec![vec![gas + gas]]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_gas_fields_out_of_range() {
+        let mut tx = fixture_txs()[0].clone();
+        // the fee would wrap around the field
+        tx.gas_price = Some(U256::one() << 128);
+        let circuit = GasSumCircuit { txs: vec![tx] };
+        assert!(matches!(
+            MockProver::<Fp>::run(7, &circuit, vec![vec![Fp::zero()]]),
+            Err(Error::Synthesis)
+        ));
+
+        let mut tx = fixture_txs()[0].clone();
+        tx.gas_limit = U256::from(u64::MAX) + 1;
+        let circuit = GasSumCircuit { txs: vec![tx] };
+        assert!(matches!(
+            MockProver::<Fp>::run(7, &circuit, vec![vec![Fp::zero()]]),
+            Err(Error::Synthesis)
+        ));
+    }
+}
@@ -9,12 +9,22 @@ use ethers::abi::Token;
 use ethers::core::utils::keccak256;
 use ethers::types::{Address, U256};
 use halo2_proofs::{
-    circuit::{AssignedCell, Layouter},
+    circuit::{AssignedCell, Layouter, Region, Value},
     halo2curves::bn256::Fq,
     halo2curves::FieldExt,
-    plonk::{Column, Error, Instance, Selector},
+    plonk::{
+        Advice, Column, ConstraintSystem, Error, Expression, Instance, Selector, TableColumn,
+        VirtualCells,
+    },
+    poly::Rotation,
 };
 
+use crate::operation::Account;
+
+/// the gas balances and the gas used are range checked in bytes, so each of
+/// them must be less than 2^(8 * GAS_BALANCE_BYTES)
+pub const GAS_BALANCE_BYTES: usize = 16;
+
 #[derive(Clone)]
 pub struct HashData<F: FieldExt>(AssignedCell<F, F>);
 
@@ -130,8 +140,285 @@ impl<F: FieldExt, const TX_NUM: usize> GasTicketChip<F, TX_NUM> {
     // data.bn256_fp_hash()).collect(); }
 }
 
+/// constrain the gas deduction of an account:
+/// `gas_balance_after = gas_balance_before - gas_used`, where none of the
+/// three values may wrap around the field (each is range checked in
+/// `GAS_BALANCE_BYTES` bytes), so a field-negative gas used can not mint
+/// balance
+///
+/// the gadget takes `GAS_BALANCE_BYTES` rows for each of balance_before,
+/// gas_used and balance_after in turn, and require 5 advice cols:
+/// + balance_before, gas_used, balance_after (only in the first row)
+/// + acc: the running sum of the bytes of the value decomposed in the block,
+/// from the most significant one in the last row of the block
+/// + byte: the byte in current row, lookup from the byte table (256 rows)
+#[derive(Clone, Debug)]
+pub struct GasTicketGadget {
+    s_first: Selector,
+    s_step: Selector,
+    s_last: Selector,
+    s_byte: Selector,
+    balance_before: Column<Advice>,
+    gas_used: Column<Advice>,
+    balance_after: Column<Advice>,
+    acc: Column<Advice>,
+    byte: Column<Advice>,
+    byte_table: TableColumn,
+}
+
+impl GasTicketGadget {
+    pub fn min_free_cols() -> usize {
+        5
+    }
+
+    /// rows taken by each deduction
+    pub fn use_rows() -> usize {
+        3 * GAS_BALANCE_BYTES
+    }
+
+    pub fn configure<Fp: FieldExt>(
+        meta: &mut ConstraintSystem<Fp>,
+        free: &[Column<Advice>],
+    ) -> Self {
+        assert!(
+            free.len() >= Self::min_free_cols(),
+            "require at least 5 free cols"
+        );
+        let s_first = meta.selector();
+        let s_step = meta.selector();
+        let s_last = meta.selector();
+        let s_byte = meta.complex_selector();
+        let byte_table = meta.lookup_table_column();
+        let (balance_before, gas_used, balance_after, acc, byte) =
+            (free[0], free[1], free[2], free[3], free[4]);
+
+        meta.create_gate("gas deduction", |meta| {
+            let s_first = meta.query_selector(s_first);
+            let balance_before = meta.query_advice(balance_before, Rotation::cur());
+            let gas_used = meta.query_advice(gas_used, Rotation::cur());
+            let balance_after = meta.query_advice(balance_after, Rotation::cur());
+            // each value is tied to the acc in the first row of its block
+            let acc_at = |meta: &mut VirtualCells<'_, Fp>, block: usize| {
+                meta.query_advice(acc, Rotation((block * GAS_BALANCE_BYTES) as i32))
+            };
+            vec![
+                s_first.clone()
+                    * (balance_before.clone() - gas_used.clone() - balance_after.clone()),
+                s_first.clone() * (acc_at(meta, 0) - balance_before),
+                s_first.clone() * (acc_at(meta, 1) - gas_used),
+                s_first * (acc_at(meta, 2) - balance_after),
+            ]
+        });
+
+        meta.create_gate("gas balance decompose", |meta| {
+            let acc_cur = meta.query_advice(acc, Rotation::cur());
+            let byte = meta.query_advice(byte, Rotation::cur());
+            vec![
+                meta.query_selector(s_step)
+                    * (acc_cur.clone()
+                        - meta.query_advice(acc, Rotation::next())
+                            * Expression::Constant(Fp::from(256u64))
+                        - byte.clone()),
+                meta.query_selector(s_last) * (acc_cur - byte),
+            ]
+        });
+
+        meta.lookup("gas balance byte range", |meta| {
+            vec![(
+                meta.query_selector(s_byte) * meta.query_advice(byte, Rotation::cur()),
+                byte_table,
+            )]
+        });
+
+        Self {
+            s_first,
+            s_step,
+            s_last,
+            s_byte,
+            balance_before,
+            gas_used,
+            balance_after,
+            acc,
+            byte,
+            byte_table,
+        }
+    }
+
+    /// fill the byte table for range check, should be called once
+    pub fn load_table<Fp: FieldExt>(&self, layouter: &mut impl Layouter<Fp>) -> Result<(), Error> {
+        layouter.assign_table(
+            || "gas balance byte table",
+            |mut table| {
+                for i in 0..256u64 {
+                    table.assign_cell(
+                        || "byte",
+                        self.byte_table,
+                        i as usize,
+                        || Value::known(Fp::from(i)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// assign the deduction from the gas_balance of the account states,
+    /// return the offset after the gadget
+    pub fn assign<'d, Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        data: (&'d Account<Fp>, &'d Account<Fp>),
+        gas_used: Fp,
+    ) -> Result<usize, Error> {
+        let (before, after) = data;
+        for (col, val, desc) in [
+            (self.balance_before, before.gas_balance, "balance before"),
+            (self.gas_used, gas_used, "gas used"),
+            (self.balance_after, after.gas_balance, "balance after"),
+        ] {
+            region.assign_advice(|| desc, col, offset, || Value::known(val))?;
+        }
+        self.s_first.enable(region, offset)?;
+
+        let mut offset = offset;
+        for val in [before.gas_balance, gas_used, after.gas_balance] {
+            offset = self.assign_bytes(region, offset, val)?;
+        }
+        Ok(offset)
+    }
+
+    // decompose `val` into `GAS_BALANCE_BYTES` rows from offset, a wrapped
+    // value has non-zero bytes beyond the range and would fail the first acc
+    fn assign_bytes<Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        val: Fp,
+    ) -> Result<usize, Error> {
+        let repr = val.to_repr();
+        let bytes = &repr.as_ref()[..GAS_BALANCE_BYTES];
+        let mut acc = Fp::zero();
+        for (i, byte) in bytes.iter().enumerate().rev() {
+            let row = offset + i;
+            let byte = Fp::from(*byte as u64);
+            acc = acc * Fp::from(256u64) + byte;
+            region.assign_advice(|| "value byte", self.byte, row, || Value::known(byte))?;
+            region.assign_advice(|| "value acc", self.acc, row, || Value::known(acc))?;
+            self.s_byte.enable(region, row)?;
+            if i == GAS_BALANCE_BYTES - 1 {
+                self.s_last.enable(region, row)?;
+            } else {
+                self.s_step.enable(region, row)?;
+            }
+        }
+
+        Ok(offset + GAS_BALANCE_BYTES)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+
+    use crate::operation::Account;
+    use crate::test_utils::Fp;
+
+    use super::GasTicketGadget;
+
     #[test]
     fn test_hash() {}
+
+    #[derive(Clone, Default)]
+    struct GasTicketTestCircuit {
+        data: (Account<Fp>, Account<Fp>),
+        gas_used: Fp,
+    }
+
+    impl Circuit<Fp> for GasTicketTestCircuit {
+        type Config = GasTicketGadget;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let free_cols = [(); 5].map(|_| meta.advice_column());
+            GasTicketGadget::configure(meta, &free_cols)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            config.load_table(&mut layouter)?;
+            layouter.assign_region(
+                || "gas ticket",
+                |mut region| {
+                    config.assign(&mut region, 0, (&self.data.0, &self.data.1), self.gas_used)?;
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    fn gas_circuit(before: u64, gas_used: u64, after: Fp) -> GasTicketTestCircuit {
+        gas_circuit_with(before, Fp::from(gas_used), after)
+    }
+
+    fn gas_circuit_with(before: u64, gas_used: Fp, after: Fp) -> GasTicketTestCircuit {
+        let acc_before = Account::<Fp> {
+            gas_balance: Fp::from(before),
+            ..Default::default()
+        };
+        let acc_after = Account::<Fp> {
+            gas_balance: after,
+            ..acc_before.clone()
+        };
+        GasTicketTestCircuit {
+            data: (acc_before, acc_after),
+            gas_used,
+        }
+    }
+
+    #[test]
+    fn test_gas_deduction() {
+        let k = 9;
+        let circuit = gas_circuit(100000, 21000, Fp::from(79000u64));
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        // deduct all
+        let circuit = gas_circuit(21000, 21000, Fp::zero());
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        // wrong balance after
+        let circuit = gas_circuit(100000, 21000, Fp::from(79001u64));
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_gas_underflow() {
+        let k = 9;
+        // balance wraps around the field
+        let circuit = gas_circuit(1000, 21000, Fp::from(1000u64) - Fp::from(21000u64));
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_gas_used_negative() {
+        let k = 9;
+        // a gas used of -1 would mint one unit of balance, though the balance
+        // after is in range
+        let circuit = gas_circuit_with(1000, -Fp::one(), Fp::from(1001u64));
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
 }
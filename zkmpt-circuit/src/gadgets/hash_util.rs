@@ -7,29 +7,67 @@ use halo2_proofs::{
 
 use hash_circuit::hash::{Hashable, PoseidonHashChip, PoseidonHashConfig, PoseidonHashTable};
 
-/// a companied hash circuit as the companion of mpt hashes
-pub struct HashCircuit<F: Hashable>(PoseidonHashTable<F>, usize);
+use crate::operation::{AccountOp, HashTracesSrc};
+
+/// the domain seed the poseidon chip is constructed with for the mpt hashes,
+/// both `HashTable::fill_with_chip` and `HashCircuit::new` use it together
+/// with `hash_circuit::DEFAULT_STEP`
+pub const MPT_HASH_DOMAIN: u64 = 42;
+
+/// a companied hash circuit as the companion of mpt hashes, `STEP` is the
+/// step of the poseidon chip
+pub struct HashCircuit<F: Hashable, const STEP: usize = { hash_circuit::DEFAULT_STEP }>(
+    PoseidonHashTable<F>,
+    usize,
+    Option<F>,
+);
 
 impl<Fp: Hashable> HashCircuit<Fp> {
-    /// re-warped, all-in-one creation
+    /// re-warped, all-in-one creation for the mpt hashing domain, each record
+    /// is checked against its claimed output
     pub fn new(calcs: usize, input_with_check: &[&(Fp, Fp, Fp)]) -> Self {
+        Self::with_step(calcs, input_with_check, Some(Fp::from(MPT_HASH_DOMAIN)))
+    }
+
+    /// like `new` but only the inputs are hashed, without checking any claimed
+    /// output: it is cheaper for large tables, but the caller must trust the
+    /// inputs, since a wrong output elsewhere would not be caught here
+    pub fn new_unchecked(calcs: usize, inputs: &[[Fp; 2]]) -> Self {
+        let mut tbl = PoseidonHashTable::default();
+        tbl.constant_inputs(inputs.iter());
+        Self(tbl, calcs, Some(Fp::from(MPT_HASH_DOMAIN)))
+    }
+
+    /// the companion hash circuit of `ops`, whose hash traces are flattened
+    /// and deduplicated, checked as in `new`
+    pub fn from_ops<'a>(calcs: usize, ops: impl Iterator<Item = &'a AccountOp<Fp>>) -> Self {
+        let traces: Vec<_> = HashTracesSrc::from(ops.flat_map(|op| op.hash_traces())).collect();
+        Self::new(calcs, &traces)
+    }
+}
+
+impl<Fp: Hashable, const STEP: usize> HashCircuit<Fp, STEP> {
+    /// creation for another poseidon instance: the step is picked by the
+    /// `STEP` parameter and `domain` is the seed passed to the chip, use
+    /// `Some(MPT_HASH_DOMAIN)` to match the mpt hashes
+    pub fn with_step(calcs: usize, input_with_check: &[&(Fp, Fp, Fp)], domain: Option<Fp>) -> Self {
         let mut tbl = PoseidonHashTable::default();
         tbl.constant_inputs_with_check(input_with_check.iter().copied());
-        Self(tbl, calcs)
+        Self(tbl, calcs, domain)
     }
 }
 
-impl<Fp: Hashable> Circuit<Fp> for HashCircuit<Fp> {
+impl<Fp: Hashable, const STEP: usize> Circuit<Fp> for HashCircuit<Fp, STEP> {
     type Config = PoseidonHashConfig<Fp>;
     type FloorPlanner = SimpleFloorPlanner;
 
     fn without_witnesses(&self) -> Self {
-        Self(Default::default(), self.1)
+        Self(Default::default(), self.1, self.2)
     }
 
     fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
         let hash_tbl = [0; 5].map(|_| meta.advice_column());
-        PoseidonHashConfig::configure_sub(meta, hash_tbl, hash_circuit::DEFAULT_STEP)
+        PoseidonHashConfig::configure_sub(meta, hash_tbl, STEP)
     }
 
     fn synthesize(
@@ -37,13 +75,7 @@ impl<Fp: Hashable> Circuit<Fp> for HashCircuit<Fp> {
         config: Self::Config,
         mut layouter: impl Layouter<Fp>,
     ) -> Result<(), Error> {
-        let chip = PoseidonHashChip::<Fp, { hash_circuit::DEFAULT_STEP }>::construct(
-            config,
-            &self.0,
-            self.1,
-            false,
-            Some(Fp::from(42u64)),
-        );
+        let chip = PoseidonHashChip::<Fp, STEP>::construct(config, &self.0, self.1, false, self.2);
         chip.load(&mut layouter)
     }
 }
@@ -51,6 +83,34 @@ impl<Fp: Hashable> Circuit<Fp> for HashCircuit<Fp> {
 #[derive(Clone, Debug)]
 pub(crate) struct HashTable(pub [Column<Advice>; 5]);
 
+/// hash table config whose advice columns are shared between the mpt gadget
+/// lookups and the output of the poseidon chip
+#[derive(Clone, Debug)]
+pub(crate) struct HashTableConfig<Fp: Hashable> {
+    pub table: HashTable,
+    pub poseidon: PoseidonHashConfig<Fp>,
+}
+
+impl<Fp: Hashable> HashTableConfig<Fp> {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        let table = HashTable::configure_create(meta);
+        let poseidon = PoseidonHashConfig::configure_sub(meta, table.0, hash_circuit::DEFAULT_STEP);
+        Self { table, poseidon }
+    }
+
+    /// fill the shared columns by running the poseidon chip, see
+    /// `HashTable::fill_with_chip`
+    pub fn fill<'d>(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        hashing_records: impl Iterator<Item = &'d (Fp, Fp, Fp)>,
+        calcs: usize,
+    ) -> Result<(), Error> {
+        self.table
+            .fill_with_chip(layouter, self.poseidon.clone(), hashing_records, calcs)
+    }
+}
+
 impl HashTable {
     pub fn configure_create<Fp: Field>(meta: &mut ConstraintSystem<Fp>) -> Self {
         Self([0; 5].map(|_| meta.advice_column()))
@@ -94,6 +154,37 @@ impl HashTable {
         ]
     }
 
+    /// fill hash table by the poseidon chip so every record is constrained by
+    /// the permutation instead of being assigned as a trusted witness, the
+    /// `config` must have been configured over the columns of this table
+    /// (see `HashTableConfig`)
+    ///
+    /// row budget: the table region takes `calcs + 1` rows (row 0 is the
+    /// all-zero record hit by disabled lookups), records less than `calcs`
+    /// are padded by the chip and more than `calcs` cause a synthesis
+    /// error; the permutation region grows linearly with `calcs` as in
+    /// `HashCircuit`, so `calcs` should be the number of hash traces of the
+    /// ops rather than the number of rows of the mpt gadgets
+    pub fn fill_with_chip<'d, Fp: Hashable>(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        config: PoseidonHashConfig<Fp>,
+        hashing_records: impl Iterator<Item = &'d (Fp, Fp, Fp)>,
+        calcs: usize,
+    ) -> Result<(), Error> {
+        let mut tbl = PoseidonHashTable::default();
+        tbl.constant_inputs_with_check(hashing_records);
+
+        let chip = PoseidonHashChip::<Fp, { hash_circuit::DEFAULT_STEP }>::construct(
+            config,
+            &tbl,
+            calcs,
+            false,
+            Some(Fp::from(MPT_HASH_DOMAIN)),
+        );
+        chip.load(layouter)
+    }
+
     /// a helper entry to fill hash table with specified rows, use padding
     /// record when hashing_records is not enough
     pub fn dev_fill_with_paddings<'d, Fp: FieldExt>(
@@ -112,6 +203,27 @@ impl HashTable {
         )
     }
 
+    /// same as `dev_fill`, but the records must fit in the rows following the
+    /// default row among the usable rows of a circuit with degree `k`, i.e.
+    /// `2^k - (blinding_factors + 1)` where `blinding_factors` is taken from
+    /// `cs.blinding_factors()`, or `NotEnoughRowsAvailable` is returned before
+    /// anything is assigned
+    pub fn dev_fill_checked<'d, Fp: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        hashing_records: impl Iterator<Item = &'d (Fp, Fp, Fp)> + Clone,
+        k: u32,
+        blinding_factors: usize,
+    ) -> Result<(), Error> {
+        let usable_rows = (1usize << k).saturating_sub(blinding_factors + 1);
+        let records = hashing_records.clone().count();
+        if records + 1 > usable_rows {
+            log::error!("{records} hash records overflow the table with k = {k}, increase k");
+            return Err(Error::NotEnoughRowsAvailable { current_k: k });
+        }
+        self.dev_fill(layouter, hashing_records)
+    }
+
     /// a helper entry to fill hash table, only for dev (in using cases)
     pub fn dev_fill<'d, Fp: FieldExt>(
         &self,
@@ -166,8 +278,9 @@ impl HashTable {
 mod tests {
     #![allow(unused_imports)]
     use crate::{
-        gadgets::hash_util::HashCircuit,
-        test_utils::{rand_fp, Fp},
+        gadgets::hash_util::{HashCircuit, HashTable, HashTableConfig, MPT_HASH_DOMAIN},
+        operation::{AccountOp, HashTracesSrc},
+        test_utils::{rand_fp, rand_gen, Fp},
     };
 
     use halo2_proofs::{
@@ -207,19 +320,105 @@ mod tests {
                 &self.0,
                 self.1,
                 false,
-                Some(Fp::from(42u64)),
+                Some(Fp::from(MPT_HASH_DOMAIN)),
             );
             chip.load(&mut layouter)
         }
     }
 
+    struct SharedTableCircuit(Vec<(Fp, Fp, Fp)>, usize);
+
+    impl Circuit<Fp> for SharedTableCircuit {
+        type Config = HashTableConfig<Fp>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self(Vec::new(), self.1)
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            HashTableConfig::configure(meta)
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            config.fill(&mut layouter, self.0.iter(), self.1)
+        }
+    }
+
+    struct DevFillCircuit(Vec<(Fp, Fp, Fp)>, u32);
+
+    impl Circuit<Fp> for DevFillCircuit {
+        // the table and the blinding factors of the circuit
+        type Config = (HashTable, usize);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self(Vec::new(), self.1)
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let table = HashTable::configure_create(meta);
+            (table, meta.blinding_factors())
+        }
+
+        fn synthesize(
+            &self,
+            (config, blinding_factors): Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            config.dev_fill_checked(&mut layouter, self.0.iter(), self.1, blinding_factors)
+        }
+    }
+
     #[test]
-    fn test_hash_circuit_degree() {
-        // let mut cs: ConstraintSystem<Fp> = Default::default();
-        // HashCircuit::configure(&mut cs);
+    fn test_hash_table_dev_fill_checked() {
+        let k = 4;
+        let record = (
+            Fp::one(),
+            Fp::from(2),
+            <Fp as Hashable>::hash([Fp::one(), Fp::from(2)]),
+        );
+
+        let circuit = DevFillCircuit(vec![record; 8], k);
+        assert!(MockProver::<Fp>::run(k, &circuit, vec![]).is_ok());
+
+        let mut cs = ConstraintSystem::<Fp>::default();
+        DevFillCircuit::configure(&mut cs);
+        // the default row comes first, and the blinding rows last
+        let max_records = (1 << k) - (cs.blinding_factors() + 1) - 1;
 
-        // println!("hash circuit degree: {}", cs.degree());
-        // assert!(cs.degree() <= 9);
+        let circuit = DevFillCircuit(vec![record; max_records], k);
+        assert!(MockProver::<Fp>::run(k, &circuit, vec![]).is_ok());
+
+        let circuit = DevFillCircuit(vec![record; max_records + 1], k);
+        assert!(matches!(
+            MockProver::<Fp>::run(k, &circuit, vec![]),
+            Err(Error::NotEnoughRowsAvailable { current_k: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_hash_table_fill_with_chip() {
+        let k = 7;
+        let (m1, m2) = (Fp::from(1), Fp::from(2));
+        let records = vec![
+            (m1, m2, <Fp as Hashable>::hash([m1, m2])),
+            (m2, m1, <Fp as Hashable>::hash([m2, m1])),
+        ];
+
+        let circuit = SharedTableCircuit(records, 3);
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_hash_circuit_degree() {
+        let stats = crate::utils::report_circuit(&HashCircuit::<Fp>::new(3, &[]), 7);
+        assert!(stats.degree <= 9);
     }
 
     #[test]
@@ -243,6 +442,51 @@ mod tests {
         assert_eq!(prover_hash.verify(), Ok(()));
     }
 
+    #[test]
+    fn test_hash_circuit_unchecked() {
+        let k = 7;
+        let (m1, m2) = (Fp::from(1), Fp::from(2));
+
+        let circuit = HashCircuit::new_unchecked(1, &[[m1, m2]]);
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        // only the checked variant rejects a wrong output
+        let circuit = HashCircuit::new(1, &[&(m1, m2, Fp::from(3))]);
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_hash_circuit_from_ops() {
+        let k = 10;
+        let op = AccountOp::<Fp>::random(&mut rand_gen([3u8; 32]));
+        let ops = [op.clone(), op];
+        let distinct = HashTracesSrc::from(ops.iter().flat_map(|op| op.hash_traces())).count();
+        assert!(distinct < ops.iter().flat_map(|op| op.hash_traces()).count());
+
+        let circuit = HashCircuit::from_ops(distinct, ops.iter());
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_hash_circuit_with_step() {
+        let k = 7;
+        let (m1, m2) = (Fp::from(1), Fp::from(2));
+        let hashes = &[&(m1, m2, <Fp as Hashable>::hash([m1, m2]))];
+
+        let mpt =
+            HashCircuit::<Fp, DEFAULT_STEP>::with_step(1, hashes, Some(Fp::from(MPT_HASH_DOMAIN)));
+        assert_eq!(mpt.2, HashCircuit::new(1, hashes).2);
+
+        for domain in [Some(Fp::from(MPT_HASH_DOMAIN)), Some(Fp::from(7u64))] {
+            let circuit = HashCircuit::<Fp, DEFAULT_STEP>::with_step(1, hashes, domain);
+            let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+            assert_eq!(prover.verify(), Ok(()));
+        }
+    }
+
     #[test]
     fn test_hash_circuit_without_check() {
         let message1 = [
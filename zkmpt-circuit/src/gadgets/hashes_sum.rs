@@ -1,7 +1,7 @@
 use std::marker::PhantomData;
 
 use halo2_proofs::{
-    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    circuit::{AssignedCell, Cell, Layouter, SimpleFloorPlanner, Value},
     plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Expression, Instance, Selector},
     poly::Rotation
 };
@@ -11,6 +11,13 @@ use eth_types::Field;
 #[derive(Clone)]
 pub struct Number<F: Field>(AssignedCell<F, F>);
 
+impl<F: Field> Number<F> {
+    /// the cell of the number, for copying it elsewhere
+    pub fn cell(&self) -> Cell {
+        self.0.cell()
+    }
+}
+
 // Config that contains the columns used in the circuit
 #[derive(Debug, Clone)]
 pub struct SumConfig<F: Field> {
@@ -22,6 +29,13 @@ pub struct SumConfig<F: Field> {
     _marker: PhantomData<F>,
 }
 
+impl<F: Field> SumConfig<F> {
+    /// the instance column the sum is exposed to
+    pub fn instance(&self) -> Column<Instance> {
+        self.sum
+    }
+}
+
 // The chip that configures the gate and fills in the witness
 #[derive(Debug, Clone)]
 pub struct SumChip<F: Field> {
@@ -118,7 +132,7 @@ impl<F: Field> SumChip<F> {
         config: &SumConfig<F>,
         post_sum_num_ref: &Number<F>,
         element: Value<F>,
-    ) -> Result<Number<F>, Error> {
+    ) -> Result<(Number<F>, Number<F>), Error> {
         layouter.assign_region(
             || "row",
             |mut region| {
@@ -153,7 +167,7 @@ impl<F: Field> SumChip<F> {
                     )
                     .map(Number)?;
 
-                Ok(post_sum_num)
+                Ok((element_num, post_sum_num))
             },
         )
     }
@@ -175,24 +189,39 @@ impl<F: Field> SumChip<F> {
         element_list: &Vec<Value<F>>,
         zero: Value<F>,
     ) -> Result<Number<F>, Error> {
-        let (_, _, mut post_sum) = self.load_first_row(
+        self.constraint_list_sum_elements(layouter, config, element_list, zero)
+            .map(|(_, post_sum)| post_sum)
+    }
+
+    /// same as `constraint_list_sum`, and also return the cells of the
+    /// elements, so they can be copied from where they are constrained
+    pub fn constraint_list_sum_elements(
+        &self,
+        layouter: &mut impl Layouter<F>,
+        config: &SumConfig<F>,
+        element_list: &Vec<Value<F>>,
+        zero: Value<F>,
+    ) -> Result<(Vec<Number<F>>, Number<F>), Error> {
+        let (_, first_element, mut post_sum) = self.load_first_row(
             layouter.namespace(|| "first row"),
             config,
             element_list[0],
             zero,
         )?;
+        let mut elements = vec![first_element];
         for i in 1..element_list.len() {
-            let new_sum_acc = self.load_row(
+            let (element, new_sum_acc) = self.load_row(
                 layouter.namespace(|| "row"),
                 config,
                 &post_sum,
                 element_list[i],
             )?;
+            elements.push(element);
             post_sum = new_sum_acc;
         }
         // self.expose_public(layouter.namespace(|| "expose sum"), config, post_sum,
         // 0)?;
-        Ok(post_sum)
+        Ok((elements, post_sum))
     }
 }
 
@@ -1,9 +1,10 @@
+use crate::operation::{read_fp, write_fp, TraceError};
 use halo2_proofs::halo2curves::FieldExt;
 use hash_circuit::Hashable;
 
 /// 2 fields for representing 32 byte, used for storage key or value, the hash
 /// is also saved
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct KeyValue<Fp> {
     data: (Fp, Fp, Fp), // (the first 16 bytes, the second 16 bytes, hash value)
 }
@@ -52,6 +53,18 @@ impl<Fp: FieldExt> KeyValue<Fp> {
     pub fn limb_1(&self) -> Fp {
         self.data.1
     }
+
+    pub(crate) fn write_bytes(&self, buf: &mut Vec<u8>) {
+        for fp in [&self.data.0, &self.data.1, &self.data.2] {
+            write_fp(buf, fp);
+        }
+    }
+
+    pub(crate) fn read_bytes(bytes: &mut &[u8]) -> Result<Self, TraceError> {
+        Ok(Self {
+            data: (read_fp(bytes)?, read_fp(bytes)?, read_fp(bytes)?),
+        })
+    }
 }
 
 impl<Fp: Hashable> KeyValue<Fp> {
@@ -70,3 +83,9 @@ impl<Fp: Hashable> KeyValue<Fp> {
         &self.data
     }
 }
+
+impl<Fp: Hashable> From<(Fp, Fp)> for KeyValue<Fp> {
+    fn from(bytes32: (Fp, Fp)) -> Self {
+        Self::create(bytes32)
+    }
+}
@@ -47,6 +47,11 @@ impl LayerGadget {
         self.address_index
     }
 
+    // obtain the index cols for (old, new) root value
+    pub fn get_root_indexs(&self) -> (Column<Advice>, Column<Advice>) {
+        (self.old_root_index, self.new_root_index)
+    }
+
     pub fn get_ctrl_type_flags(&self) -> &[Column<Advice>] {
         &self.s_ctrl_type
     }
@@ -88,7 +93,11 @@ impl LayerGadget {
         let control_table = [(); 5].map(|_| meta.lookup_table_column());
 
         // require permutation with constants
+        let constant = meta.fixed_column();
+        meta.enable_constant(constant);
         meta.enable_equality(series);
+        meta.enable_equality(op_type);
+        meta.enable_equality(ctrl_type);
 
         meta.create_gate("series", |meta| {
             let sel = meta.query_selector(sel);
@@ -176,6 +185,11 @@ impl LayerGadget {
         self.s_stepflags.len() as u32
     }
 
+    /// the offset other gadgets start from, which `assign` returns
+    pub fn start_offset(&self) -> usize {
+        1
+    }
+
     /// LayerGadget must be first assigned, with other gadgets start from the
     /// offset it has returned
     pub fn assign<Fp: FieldExt>(
@@ -250,7 +264,83 @@ impl LayerGadget {
             || Value::known(Fp::zero()),
         )?;
 
-        Ok(1)
+        Ok(self.start_offset())
+    }
+
+    /// enforce the roots are chained: a row starting a block, where the series
+    /// or the op type changes, has the old root as the new root of the row
+    /// before (or the init root for the first block), and any other row keeps
+    /// the (old, new) root of the row before
+    pub fn constrain_root_continuity<Fp: FieldExt>(&self, meta: &mut ConstraintSystem<Fp>) {
+        let (old_root_index, new_root_index) = self.get_root_indexs();
+        meta.create_gate("root continuity", |meta| {
+            let sel = meta.query_selector(self.sel);
+            let old_root = meta.query_advice(old_root_index, Rotation::cur());
+            let new_root = meta.query_advice(new_root_index, Rotation::cur());
+            let prev_old_root = meta.query_advice(old_root_index, Rotation::prev());
+            let prev_new_root = meta.query_advice(new_root_index, Rotation::prev());
+            // both are boolean under the "series" and "op transition" gates
+            let series_delta = meta.query_advice(self.series, Rotation::cur())
+                - meta.query_advice(self.series, Rotation::prev());
+            let op_changed = meta.query_advice(self.op_delta_aux, Rotation::cur())
+                * (meta.query_advice(self.op_type, Rotation::cur())
+                    - meta.query_advice(self.op_type, Rotation::prev()));
+            let block_start = series_delta.clone() + op_changed.clone() - series_delta * op_changed;
+            let in_block = Expression::Constant(Fp::one()) - block_start.clone();
+            vec![
+                sel.clone() * block_start * (old_root.clone() - prev_new_root.clone()),
+                sel.clone() * in_block.clone() * (old_root - prev_old_root),
+                sel * in_block * (new_root - prev_new_root),
+            ]
+        });
+    }
+
+    /// assign the (old, new) root indexs for a block of `rows` from `offset`,
+    /// which are kept on every row of the block like
+    /// `MPTOpGadget::assign_root_index` does
+    pub fn pace_roots<Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        roots: (Fp, Fp), //root before -> root after
+        rows: usize,
+    ) -> Result<(), Error> {
+        for row in offset..(offset + rows) {
+            region.assign_advice(
+                || "old root",
+                self.old_root_index,
+                row,
+                || Value::known(roots.0),
+            )?;
+            region.assign_advice(
+                || "new root",
+                self.new_root_index,
+                row,
+                || Value::known(roots.1),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// assign the series of a block of `rows` from `offset`, which counts the
+    /// ops: the blocks of the same op share one series and the next op
+    /// starts with the series increased by 1
+    pub fn pace_series<Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        series: u64,
+        rows: usize,
+    ) -> Result<(), Error> {
+        for row in offset..(offset + rows) {
+            region.assign_advice(
+                || "series",
+                self.series,
+                row,
+                || Value::known(Fp::from(series)),
+            )?;
+        }
+        Ok(())
     }
 
     /// pace has to be called before a working gadget is assigned on the
@@ -295,6 +385,13 @@ impl LayerGadget {
                     )
                     .map(|_| ())
             })?;
+            // flush the index, which the gadget assigns on its own rows
+            region.assign_advice(
+                || "flushing index",
+                self.address_index,
+                offset,
+                || Value::known(Fp::zero()),
+            )?;
             // flush all cols to avoid unassigned error
             self.s_ctrl_type.iter().try_for_each(|col| {
                 region
@@ -337,3 +434,109 @@ impl LayerGadget {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{rand_fp, Fp};
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        dev::MockProver,
+        plonk::Circuit,
+    };
+
+    const ROWS: usize = 8;
+
+    #[derive(Clone, Default)]
+    struct RootsCircuit {
+        init_root: Fp,
+        // (rows, series, op type, (old root, new root)) of each block
+        blocks: Vec<(usize, u64, u32, (Fp, Fp))>,
+    }
+
+    impl Circuit<Fp> for RootsCircuit {
+        type Config = LayerGadget;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let layer = LayerGadget::configure(meta, 2, 1, 1);
+            layer.constrain_root_continuity(meta);
+            layer
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            layouter.assign_region(
+                || "main",
+                |mut region| {
+                    let mut offset = config.assign(&mut region, ROWS, self.init_root)?;
+                    let mut last_op = config.start_op_code();
+                    for (rows, series, op, roots) in &self.blocks {
+                        config.pace_op(&mut region, offset, (last_op, *op), *rows)?;
+                        config.pace_series(&mut region, offset, *series, *rows)?;
+                        config.pace_roots(&mut region, offset, *roots, *rows)?;
+                        offset += rows;
+                        last_op = *op;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    // only the failures of "root continuity" matter, the other layer gates
+    // are not satisfied by this bare layout
+    fn root_failures(circuit: &RootsCircuit) -> usize {
+        let prover = MockProver::<Fp>::run(6, circuit, vec![]).unwrap();
+        prover
+            .verify()
+            .err()
+            .unwrap_or_default()
+            .iter()
+            .filter(|failure| failure.to_string().contains("root continuity"))
+            .count()
+    }
+
+    #[test]
+    fn test_root_continuity() {
+        let [init, a, b] = [0; 3].map(|_| rand_fp());
+        let circuit = RootsCircuit {
+            init_root: init,
+            // the last block pads the rest rows with the final root
+            blocks: vec![(3, 1, 0, (init, a)), (2, 2, 0, (a, b)), (2, 2, 1, (b, b))],
+        };
+        assert_eq!(root_failures(&circuit), 0);
+
+        // the second block does not start from the root the first one ends
+        let mut broken = circuit.clone();
+        broken.blocks[1].3 .0 = rand_fp();
+        assert_ne!(root_failures(&broken), 0);
+
+        // the first block does not start from the init root
+        let mut broken = circuit;
+        broken.init_root = rand_fp();
+        assert_ne!(root_failures(&broken), 0);
+    }
+
+    #[test]
+    fn test_root_continuity_padding() {
+        let [init, a, b] = [0; 3].map(|_| rand_fp());
+        let circuit = RootsCircuit {
+            init_root: init,
+            blocks: vec![(3, 1, 0, (init, a)), (2, 2, 0, (a, b)), (1, 2, 1, (b, b))],
+        };
+        assert_eq!(root_failures(&circuit), 0);
+
+        // a padding row, which starts no block, changes the new root
+        let mut broken = circuit;
+        broken.blocks.push((1, 2, 1, (b, rand_fp())));
+        assert_ne!(root_failures(&broken), 0);
+    }
+}
@@ -1,7 +1,17 @@
+use eth_types::Field;
 use ethers::types::U256;
+use halo2_proofs::{
+    arithmetic::FieldExt,
+    halo2curves::{
+        bn256::{Fq, Fr},
+        group::ff::PrimeField,
+    },
+};
+use subtle::{Choice, CtOption};
 
 pub mod account;
 pub mod ecsdsa;
+pub mod gas_sum;
 pub mod gas_ticket;
 pub mod hash_poseidon;
 pub mod hash_util;
@@ -45,3 +55,115 @@ impl ToBigEndian for U256 {
         bytes
     }
 }
+
+macro_rules! impl_field_endian {
+    ($($field:ty),*) => {
+        $(
+            impl ToLittleEndian for $field {
+                /// Encode the field element by its canonical repr.
+                fn to_le_bytes(&self) -> [u8; 32] {
+                    self.to_repr()
+                }
+            }
+
+            impl ToBigEndian for $field {
+                /// Encode the field element by its reversed canonical repr.
+                fn to_be_bytes(&self) -> [u8; 32] {
+                    let mut bytes = self.to_repr();
+                    bytes.reverse();
+                    bytes
+                }
+            }
+        )*
+    };
+}
+
+impl_field_endian!(Fr, Fq);
+
+/// Inverse of `ToLittleEndian` for field elements, none if the value is not
+/// less than the modulus (no reduction like `from_bytes_wide`).
+pub fn from_le_bytes<F: Field>(bytes: &[u8; 32]) -> CtOption<F> {
+    F::from_repr(*bytes)
+}
+
+/// Inverse of `ToBigEndian` for field elements, none if the value is not
+/// less than the modulus.
+pub fn from_be_bytes<F: Field>(bytes: &[u8; 32]) -> CtOption<F> {
+    let mut repr = *bytes;
+    repr.reverse();
+    F::from_repr(repr)
+}
+
+/// Convert a U256 into the field, values not less than the modulus are reduced
+/// (like `from_bytes_wide`) instead of being rejected, so it is always some.
+pub fn u256_to_fp<Fp: FieldExt>(v: U256) -> CtOption<Fp> {
+    let mut wide = [0u8; 64];
+    v.to_little_endian(&mut wide[..32]);
+    CtOption::new(Fp::from_bytes_wide(&wide), Choice::from(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_be_bytes, from_le_bytes, u256_to_fp, ToBigEndian, ToLittleEndian};
+    use crate::test_utils::{rand_fp, Fp};
+    use ethers::types::U256;
+    use halo2_proofs::arithmetic::FieldExt;
+
+    #[test]
+    fn test_field_endian_round_trip() {
+        let fp = rand_fp();
+        let le = fp.to_le_bytes();
+        let mut be = fp.to_be_bytes();
+        assert_eq!(from_le_bytes::<Fp>(&le).unwrap(), fp);
+        assert_eq!(from_be_bytes::<Fp>(&be).unwrap(), fp);
+        be.reverse();
+        assert_eq!(be, le);
+
+        assert_eq!(Fp::from(0x1234u64).to_be_bytes()[30..], [0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_field_endian_with_bytes_wide() {
+        // canonical values agree with the wide reduction
+        let fp = rand_fp();
+        let mut wide = [0u8; 64];
+        wide[..32].copy_from_slice(&fp.to_le_bytes());
+        assert_eq!(Fp::from_bytes_wide(&wide), fp);
+
+        // while values over the modulus are rejected instead of being reduced
+        let over = [0xffu8; 32];
+        assert!(bool::from(from_le_bytes::<Fp>(&over).is_none()));
+        assert!(bool::from(from_be_bytes::<Fp>(&over).is_none()));
+        let mut wide = [0u8; 64];
+        wide[..32].copy_from_slice(&over);
+        let reduced = Fp::from_bytes_wide(&wide);
+        assert_ne!(reduced.to_le_bytes(), over);
+    }
+
+    #[test]
+    fn test_u256_to_fp() {
+        assert_eq!(u256_to_fp::<Fp>(U256::zero()).unwrap(), Fp::zero());
+        assert_eq!(
+            u256_to_fp::<Fp>(U256::from(0x1234u64)).unwrap(),
+            Fp::from(0x1234u64)
+        );
+
+        let fp = rand_fp();
+        let v = U256::from_little_endian(&fp.to_le_bytes());
+        assert_eq!(u256_to_fp::<Fp>(v).unwrap(), fp);
+
+        // the modulus and values over it are reduced
+        let modulus = U256::from_str_radix(Fp::MODULUS.trim_start_matches("0x"), 16).unwrap();
+        assert_eq!(u256_to_fp::<Fp>(modulus).unwrap(), Fp::zero());
+        assert_eq!(
+            u256_to_fp::<Fp>(modulus + U256::from(5u64)).unwrap(),
+            Fp::from(5u64)
+        );
+        let mut wide = [0u8; 64];
+        wide[..32].copy_from_slice(&[0xffu8; 32]);
+        assert_eq!(
+            u256_to_fp::<Fp>(U256::MAX).unwrap(),
+            Fp::from_bytes_wide(&wide)
+        );
+    }
+}
@@ -9,6 +9,16 @@ use halo2_proofs::{
 use hash_circuit::Hashable;
 
 use super::{hash_util::HashTable, table_util::MPTOpTables};
+use crate::operation::{
+    check_decompose, decompose_key, expect_consumed, read_fp, read_fps, read_len, read_traces,
+    write_fp, write_fps, write_len, write_traces, TraceError,
+};
+
+/// the root of an empty trie, an empty node is represented by zero instead of
+/// the hash of any leaf so the empty trie has the zero root
+pub fn empty_root<Fp: FieldExt>() -> Fp {
+    Fp::zero()
+}
 
 /// Represent a sequence of hashes in a path inside MPT, it can be full
 /// (with leaf) or truncated and being padded to an "empty" leaf node,
@@ -32,7 +42,7 @@ impl<Fp: FieldExt> Default for MPTPath<Fp> {
     fn default() -> Self {
         Self {
             hash_types: vec![HashType::Start, HashType::Empty],
-            hashes: vec![Fp::zero(), Fp::zero()],
+            hashes: vec![empty_root(), empty_root()],
             hash_traces: Default::default(),
             status: MPTPathStatus::Empty,
         }
@@ -40,16 +50,29 @@ impl<Fp: FieldExt> Default for MPTPath<Fp> {
 }
 
 impl<Fp: FieldExt> MPTPath<Fp> {
-    /// the root of MPT
+    /// the root of MPT, panic if the path has no rows
     pub fn root(&self) -> Fp {
-        self.hashes[0]
+        self.try_root().expect("mpt path has no hashes")
     }
 
-    /// the leaf value, for truncated path, give None
+    /// the root of MPT, give None for a path without any rows (i.e. built
+    /// manually or partially deserialized)
+    pub fn try_root(&self) -> Option<Fp> {
+        self.hashes.first().copied()
+    }
+
+    /// the leaf value, for truncated path, give None; panic if the path has
+    /// no rows
     pub fn leaf(&self) -> Option<Fp> {
-        match *self.hash_types.last().unwrap() {
-            HashType::Empty => None,
-            _ => Some(*self.hashes.last().unwrap()),
+        self.try_leaf().expect("mpt path has no hash types")
+    }
+
+    /// the leaf value like `leaf`, but give None instead of panic for a path
+    /// without any rows
+    pub fn try_leaf(&self) -> Option<Option<Fp>> {
+        match self.hash_types.last()? {
+            HashType::Empty => Some(None),
+            _ => self.hashes.last().map(|h| Some(*h)),
         }
     }
 
@@ -91,6 +114,63 @@ impl<Fp: FieldExt> MPTPath<Fp> {
         self.hashes.len() - 2
     }
 
+    pub(crate) fn write_bytes(&self, buf: &mut Vec<u8>) {
+        write_len(buf, self.hash_types.len());
+        buf.extend(self.hash_types.iter().map(|t| *t as u8));
+        write_fps(buf, &self.hashes);
+        write_traces(buf, &self.hash_traces);
+        match self.status {
+            MPTPathStatus::Empty => buf.push(0),
+            MPTPathStatus::Leaf((key, key_immediate)) => {
+                buf.push(1);
+                write_fp(buf, &key);
+                write_fp(buf, &key_immediate);
+            }
+            MPTPathStatus::Extended(((key, key_immediate), (s_key, s_key_immediate, s_val))) => {
+                buf.push(2);
+                for fp in [key, key_immediate, s_key, s_key_immediate, s_val] {
+                    write_fp(buf, &fp);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn read_bytes(bytes: &mut &[u8]) -> Result<Self, TraceError> {
+        let read_tag = |bytes: &mut &[u8]| -> Result<u8, TraceError> {
+            let (tag, rest) = bytes
+                .split_first()
+                .ok_or_else(|| TraceError::DataErr("unexpected end of data".to_string()))?;
+            *bytes = rest;
+            Ok(*tag)
+        };
+
+        let hash_types = (0..read_len(bytes)?)
+            .map(|_| HashType::try_from(read_tag(bytes)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let hashes = read_fps(bytes)?;
+        let hash_traces = read_traces(bytes)?;
+        let status = match read_tag(bytes)? {
+            0 => MPTPathStatus::Empty,
+            1 => MPTPathStatus::Leaf((read_fp(bytes)?, read_fp(bytes)?)),
+            2 => MPTPathStatus::Extended((
+                (read_fp(bytes)?, read_fp(bytes)?),
+                (read_fp(bytes)?, read_fp(bytes)?, read_fp(bytes)?),
+            )),
+            tag => {
+                return Err(TraceError::DataErr(format!(
+                    "invalid mpt path status: {tag}"
+                )))
+            }
+        };
+
+        Ok(Self {
+            hash_types,
+            hashes,
+            hash_traces,
+            status,
+        })
+    }
+
     pub(crate) fn extend_with_hasher(
         self,
         l: usize,
@@ -207,6 +287,49 @@ impl<Fp: Hashable> MPTPath<Fp> {
     pub fn extend(self, l: usize, new_key: Fp) -> Self {
         self.extend_with_hasher(l, new_key, |a, b| <Fp as Hashable>::hash([*a, *b]))
     }
+
+    /// check the path is consistent with its hash traces: each trace must be
+    /// a correct hash and, from the leaf up to the top, the hash of every row
+    /// must be calculated from the one under it, so the chain ends at `root()`;
+    /// rows of leaf extension are left to the circuit
+    pub fn verify_hashes(&self) -> Result<(), TraceError> {
+        if self.hashes.is_empty() || self.hashes.len() != self.hash_types.len() {
+            return Err(TraceError::DataErr(format!(
+                "malformed path: {} hashes with {} hash types",
+                self.hashes.len(),
+                self.hash_types.len()
+            )));
+        }
+
+        for (i, (a, b, c)) in self.hash_traces.iter().enumerate() {
+            if <Fp as Hashable>::hash([*a, *b]) != *c {
+                return Err(TraceError::DataErr(format!("hash trace {i} mismatch")));
+            }
+        }
+
+        for (i, hash_type) in self.hash_types.iter().enumerate().skip(1).rev() {
+            let (upper, cur) = (self.hashes[i - 1], self.hashes[i]);
+            let consistent = match hash_type {
+                HashType::Middle | HashType::Leaf => self
+                    .hash_traces
+                    .iter()
+                    .any(|(a, b, c)| *c == upper && (*a == cur || *b == cur)),
+                HashType::Empty => {
+                    cur == Fp::zero()
+                        && (upper == Fp::zero() || self.hash_types[i - 1] == HashType::LeafExtFinal)
+                }
+                HashType::LeafExt | HashType::LeafExtFinal => true,
+                HashType::Start => false,
+            };
+            if !consistent {
+                return Err(TraceError::DataErr(format!(
+                    "hash of row {i} ({hash_type:?}) is not consistent with the upper row"
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// Represent for a single operation
@@ -234,6 +357,51 @@ impl<Fp: FieldExt> SingleOp<Fp> {
         self.siblings.len() + 2
     }
 
+    /// classify the op by the leaf of both paths, which have been extended to
+    /// the same depth, so an insert (or delete) is the side with an extended
+    /// empty leaf turned into a leaf (or the reverse)
+    pub fn op_kind(&self) -> OpKind {
+        let leaf = |path: &MPTPath<Fp>| path.try_leaf().flatten();
+        match (leaf(&self.old), leaf(&self.new)) {
+            (None, Some(_)) => OpKind::Insert,
+            (Some(_), None) => OpKind::Delete,
+            (Some(old), Some(new)) if old != new => OpKind::Update,
+            _ => OpKind::NoOp,
+        }
+    }
+
+    /// render the old and new paths side by side for debugging, one line per
+    /// level with the hash types, the path bit and the sibling, the levels
+    /// where both paths diverge are marked with `*`
+    pub fn explain(&self) -> String {
+        use std::fmt::Write;
+
+        let mut out = String::new();
+        writeln!(out, "key {:?} ({:?})", self.key, self.op_kind()).unwrap();
+        writeln!(out, "lvl bit old | new").unwrap();
+        let levels = std::cmp::max(self.old.hash_types.len(), self.new.hash_types.len());
+        let side = |path: &MPTPath<Fp>, i: usize| {
+            path.hash_types
+                .get(i)
+                .zip(path.hashes.get(i))
+                .map_or("-".to_string(), |(t, h)| format!("{t:?} {h:?}"))
+        };
+        for i in 0..levels {
+            let bit = i
+                .checked_sub(1)
+                .and_then(|i| self.path.get(i))
+                .map_or("-", |b| if *b == Fp::zero() { "0" } else { "1" });
+            let (old, new) = (side(&self.old, i), side(&self.new, i));
+            let mark = if old != new { " *" } else { "" };
+            write!(out, "{i:>3} {bit:>3} {old} | {new}").unwrap();
+            if let Some(sibling) = i.checked_sub(1).and_then(|i| self.siblings.get(i)) {
+                write!(out, " sibling {sibling:?}").unwrap();
+            }
+            writeln!(out, "{mark}").unwrap();
+        }
+        out
+    }
+
     /// calculate the ctrl_type base on the two hash type of MPTPath
     pub fn ctrl_type(&self) -> Vec<HashType> {
         self.old
@@ -259,6 +427,44 @@ impl<Fp: FieldExt> SingleOp<Fp> {
             .collect()
     }
 
+    /// canonical binary encoding of the op, field elements are encoded as
+    /// 32-byte little-endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write_bytes(&mut buf);
+        buf
+    }
+
+    /// decode the op from the encoding of `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TraceError> {
+        let mut cursor = bytes;
+        let op = Self::read_bytes(&mut cursor)?;
+        expect_consumed(cursor)?;
+        Ok(op)
+    }
+
+    pub(crate) fn write_bytes(&self, buf: &mut Vec<u8>) {
+        write_fp(buf, &self.key);
+        write_fp(buf, &self.key_immediate);
+        write_fp(buf, &self.key_residual);
+        write_fps(buf, &self.path);
+        write_fps(buf, &self.siblings);
+        self.old.write_bytes(buf);
+        self.new.write_bytes(buf);
+    }
+
+    pub(crate) fn read_bytes(bytes: &mut &[u8]) -> Result<Self, TraceError> {
+        Ok(Self {
+            key: read_fp(bytes)?,
+            key_immediate: read_fp(bytes)?,
+            key_residual: read_fp(bytes)?,
+            path: read_fps(bytes)?,
+            siblings: read_fps(bytes)?,
+            old: MPTPath::read_bytes(bytes)?,
+            new: MPTPath::read_bytes(bytes)?,
+        })
+    }
+
     /// the root of MPT before operation
     pub fn start_root(&self) -> Fp {
         self.old.root()
@@ -279,26 +485,7 @@ impl<Fp: FieldExt> SingleOp<Fp> {
     ) -> Self {
         let siblings = Vec::from(siblings);
 
-        //decompose path
-        let (path, key_residual): (Vec<bool>, Fp) = {
-            assert!(
-                (layers as u32) * 8 < Fp::NUM_BITS,
-                "not able to decompose more than bits"
-            );
-            let mut ret = Vec::new();
-            let mut tested_key = key;
-            let invert_2 = Fp::one().double().invert().unwrap();
-            for _ in 0..layers {
-                if tested_key.is_odd().unwrap_u8() == 1 {
-                    tested_key = tested_key * invert_2 - invert_2;
-                    ret.push(true);
-                } else {
-                    tested_key *= invert_2;
-                    ret.push(false);
-                }
-            }
-            (ret, tested_key)
-        };
+        let (path, key_residual) = decompose_key(key, layers);
         let (old_leaf, new_leaf) = leafs;
 
         let old = MPTPath::<Fp>::create_with_hasher(
@@ -328,6 +515,21 @@ impl<Fp: FieldExt> SingleOp<Fp> {
         }
     }
 
+    /// like `create_update_op_with_hasher`, but reject a path too deep to be
+    /// decomposed from the key instead of panicking
+    pub(crate) fn try_create_update_op_with_hasher(
+        layers: usize,
+        siblings: &[Fp],
+        key: Fp,
+        leafs: (Fp, Fp),
+        hasher: impl FnMut(&Fp, &Fp) -> Fp + Clone,
+    ) -> Result<Self, TraceError> {
+        check_decompose::<Fp>(layers)?;
+        Ok(Self::create_update_op_with_hasher(
+            layers, siblings, key, leafs, hasher,
+        ))
+    }
+
     /// create another updating op base on a previous action
     pub(crate) fn update_next_with_hasher(
         self,
@@ -375,12 +577,38 @@ impl<Fp: Hashable> SingleOp<Fp> {
         })
     }
 
+    /// create update op, give DataErr for a path too deep to be decomposed
+    /// from the key
+    pub fn try_create_update_op(
+        layers: usize,
+        siblings: &[Fp],
+        key: Fp,
+        leafs: (Fp, Fp),
+    ) -> Result<Self, TraceError> {
+        Self::try_create_update_op_with_hasher(layers, siblings, key, leafs, |a, b| {
+            <Fp as Hashable>::hash([*a, *b])
+        })
+    }
+
     /// create another updating op base on a previous action
     pub fn update_next(self, new_leaf: Fp) -> Self {
         self.update_next_with_hasher(new_leaf, |a, b| <Fp as Hashable>::hash([*a, *b]))
     }
 }
 
+/// the kind of change a `SingleOp` makes on the leaf of its key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpKind {
+    /// the key is absent before and present after
+    Insert,
+    /// the value of the key is changed
+    Update,
+    /// the key is present before and absent after
+    Delete,
+    /// the leaf is left unchanged (or absent on both sides)
+    NoOp,
+}
+
 /// Indicate the operation type of a row in MPT circuit
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum HashType {
@@ -401,6 +629,22 @@ pub enum HashType {
 
 const HASH_TYPE_CNT: usize = 6;
 
+impl TryFrom<u8> for HashType {
+    type Error = TraceError;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        Ok(match v {
+            0 => HashType::Start,
+            1 => HashType::Empty,
+            2 => HashType::Middle,
+            3 => HashType::LeafExt,
+            4 => HashType::LeafExtFinal,
+            5 => HashType::Leaf,
+            _ => return Err(TraceError::DataErr(format!("invalid hash type: {v}"))),
+        })
+    }
+}
+
 /// Indicate the current status of an MPTPath
 #[derive(Clone, Copy, Debug)]
 pub enum MPTPathStatus<Fp: FieldExt> {
@@ -490,6 +734,7 @@ impl<'d, Fp: FieldExt> PathChip<'d, Fp> {
         let s_row = g_config.s_row;
         let sibling = g_config.sibling;
         let path = g_config.path;
+        let ctrl_type = g_config.ctrl_type;
         let trans_table = &g_config.tables;
 
         // Only lookup for hash table should be
@@ -573,6 +818,21 @@ impl<'d, Fp: FieldExt> PathChip<'d, Fp> {
             )
         });
 
+        // s_match_ctrl_type must be 1 iff hash type equal to ctrl type, or the
+        // hash lookups above can be switched on / off arbitrarily by prover
+        meta.create_gate("hash type match", |meta| {
+            let enable = meta.query_selector(s_row) * meta.query_advice(s_enable, Rotation::cur());
+            let s_match = meta.query_advice(s_match_ctrl_type, Rotation::cur());
+            let type_diff = meta.query_advice(ctrl_type, Rotation::cur())
+                - meta.query_advice(hash_type, Rotation::cur());
+            let aux = meta.query_advice(s_match_ctrl_aux, Rotation::cur());
+
+            vec![
+                enable.clone() * s_match.clone() * type_diff.clone(),
+                enable * (Expression::Constant(Fp::one()) - s_match - type_diff * aux),
+            ]
+        });
+
         meta.create_gate("leaf extended", |meta| {
             let enable = meta.query_selector(s_row) * meta.query_advice(s_enable, Rotation::cur());
             let s_extended = meta.query_advice(s_match_ctrl_type, Rotation::cur())
@@ -610,7 +870,8 @@ impl<'d, Fp: FieldExt> PathChip<'d, Fp> {
 
         // prove the silbing is really a leaf when extended
         meta.lookup_any("extended sibling proof 1", |meta| {
-            let s_last_extended = meta.query_advice(s_enable, Rotation::cur())
+            let s_last_extended = meta.query_selector(s_row)
+                * meta.query_advice(s_enable, Rotation::cur())
                 * meta.query_advice(s_match_ctrl_type, Rotation::cur())
                 * meta.query_advice(
                     s_hash_type[HashType::LeafExtFinal as usize],
@@ -629,7 +890,8 @@ impl<'d, Fp: FieldExt> PathChip<'d, Fp> {
         });
 
         meta.lookup_any("extended sibling proof 2", |meta| {
-            let s_last_extended = meta.query_advice(s_enable, Rotation::cur())
+            let s_last_extended = meta.query_selector(s_row)
+                * meta.query_advice(s_enable, Rotation::cur())
                 * meta.query_advice(s_match_ctrl_type, Rotation::cur())
                 * meta.query_advice(
                     s_hash_type[HashType::LeafExtFinal as usize],
@@ -719,7 +981,7 @@ impl<'d, Fp: FieldExt> PathChip<'d, Fp> {
                 offset + index,
                 || {
                     Value::known(
-                        Fp::from(ref_type as u64 - hash_type as u64)
+                        (Fp::from(ref_type as u64) - Fp::from(hash_type as u64))
                             .invert()
                             .unwrap_or_else(Fp::zero),
                     )
@@ -966,12 +1228,16 @@ impl<'d, Fp: FieldExt> OpChip<'d, Fp> {
                 offset,
                 || Value::known(*sibling),
             )?;
-            // currently we simply fill key_aux col with extend_proof (if any)
+            // key_aux col only carry the extend proof on the final extended row
+            let key_aux = match (ctrl_type[index + 1], extend_proof) {
+                (HashType::LeafExtFinal, Some(pf)) => pf.1,
+                _ => Fp::zero(),
+            };
             region.assign_advice(
                 || "ext proof key immediate",
                 config.key_aux,
                 offset,
-                || Value::known(extend_proof.map(|pf| pf.1).unwrap_or_default()),
+                || Value::known(key_aux),
             )?;
             region.assign_advice(
                 || "ctrl type",
@@ -1060,6 +1326,7 @@ struct MPTOpConfig {
     old_val: Column<Advice>,
     new_val: Column<Advice>,
     key_aux: Column<Advice>,
+    root_index: Option<(Column<Advice>, Column<Advice>)>,
 
     hash_table: HashTable,
     tables: MPTOpTables,
@@ -1071,6 +1338,7 @@ pub(crate) struct MPTOpGadget {
     old_path: PathChipConfig,
     new_path: PathChipConfig,
     s_enable: Column<Advice>,
+    root_index: Option<(Column<Advice>, Column<Advice>)>,
 
     pub hash_table: HashTable,
     pub tables: MPTOpTables,
@@ -1079,9 +1347,9 @@ pub(crate) struct MPTOpGadget {
 impl MPTOpGadget {
     /// create gadget from assigned cols, we need:
     /// + circuit selector * 1
-    /// + exported col * 4 (MUST by following sequence: layout_flag, s_enable,
-    /// old_val, new_val) + s_op_flags * 6 (corresponding 6 ctrl_types)
-    /// + free col * 8
+    /// + exported col * 5 (MUST by following sequence: layout_flag, s_enable,
+    /// old_val, new_val, key) + s_op_flags * 6 (corresponding 6 ctrl_types)
+    /// + free col * 11
     /// notice the gadget has bi-direction exporting (on top it exporting mpt
     /// root and bottom exporting leaf)
     pub fn configure<Fp: FieldExt>(
@@ -1094,7 +1362,7 @@ impl MPTOpGadget {
         tables: MPTOpTables,
         hash_tbl: HashTable,
     ) -> Self {
-        assert!(free.len() >= 8, "require at least 8 free cols");
+        assert!(free.len() >= 11, "require at least 11 free cols");
 
         let g_config = MPTOpConfig {
             tables,
@@ -1114,6 +1382,7 @@ impl MPTOpGadget {
             new_val: exported[3],
             acc_key: exported[4],
             s_ctrl_type: s_ctrl_type[0..6].try_into().expect("same size"),
+            root_index,
             hash_table: hash_tbl,
         };
 
@@ -1124,7 +1393,7 @@ impl MPTOpGadget {
             vec![s_row * (Expression::Constant(Fp::one()) - s_enable.clone()) * s_enable]
         });
 
-        if let Some((old_root_index, new_root_index)) = root_index {
+        if let Some((old_root_index, new_root_index)) = g_config.root_index {
             meta.create_gate("root index", |meta| {
                 let s_row = meta.query_selector(g_config.s_row);
                 let s_enable = s_row
@@ -1153,6 +1422,7 @@ impl MPTOpGadget {
 
         Self {
             s_enable: g_config.s_enable,
+            root_index: g_config.root_index,
             op: OpChip::<Fp>::configure(meta, &g_config),
             old_path: PathChip::<Fp>::configure(meta, &g_config, true),
             new_path: PathChip::<Fp>::configure(meta, &g_config, false),
@@ -1160,4 +1430,377 @@ impl MPTOpGadget {
             tables: g_config.tables.clone(),
         }
     }
+
+    /// assign data and enable flag for MPT circuit, return the offset after
+    /// the op block
+    pub fn assign<Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        data: &SingleOp<Fp>,
+    ) -> Result<usize, Error> {
+        let ctrl_type = data.ctrl_type();
+        let old_path_chip = PathChip::<Fp>::construct(
+            self.old_path.clone(),
+            offset,
+            &data.old,
+            Some(ctrl_type.as_slice()),
+        );
+        let new_path_chip = PathChip::<Fp>::construct(
+            self.new_path.clone(),
+            offset,
+            &data.new,
+            Some(ctrl_type.as_slice()),
+        );
+        let op_chip = OpChip::<Fp>::construct(self.op.clone(), offset, data);
+
+        let op_end = op_chip.assign(region)?;
+        let old_path_end = old_path_chip.assign(region)?;
+        let new_path_end = new_path_chip.assign(region)?;
+        assert_eq!(op_end, old_path_end);
+        assert_eq!(op_end, new_path_end);
+
+        for offset in offset..op_end {
+            region.assign_advice(
+                || "enable MPT",
+                self.s_enable,
+                offset,
+                || Value::known(Fp::one()),
+            )?;
+        }
+
+        Ok(op_end)
+    }
+
+    /// fill the root index cols for an op block occupying `rows` rows from
+    /// `offset`, each row carry the (old, new) root of the op so the heading
+    /// row of next block can refer to the new root by Rotation::prev();
+    /// do nothing if the gadget is configured without root index
+    pub fn assign_root_index<Fp: FieldExt>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        offset: usize,
+        rows: usize,
+        roots: (Fp, Fp),
+    ) -> Result<(), Error> {
+        if let Some((old_root_index, new_root_index)) = self.root_index {
+            for offset in offset..(offset + rows) {
+                region.assign_advice(
+                    || "old root index",
+                    old_root_index,
+                    offset,
+                    || Value::known(roots.0),
+                )?;
+                region.assign_advice(
+                    || "new root index",
+                    new_root_index,
+                    offset,
+                    || Value::known(roots.1),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_circuit_satisfied, rand_fp, Fp};
+    use halo2_proofs::{circuit::SimpleFloorPlanner, plonk::Circuit};
+
+    #[derive(Clone, Debug)]
+    struct MPTTestConfig {
+        gadget: MPTOpGadget,
+        sel: Selector,
+        free_cols: [Column<Advice>; 16],
+        s_ctrl_cols: [Column<Advice>; HASH_TYPE_CNT],
+    }
+
+    #[derive(Clone, Default)]
+    struct MPTTestCircuit {
+        op: SingleOp<Fp>,
+    }
+
+    impl Circuit<Fp> for MPTTestCircuit {
+        type Config = MPTTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            Self::default()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let sel = meta.complex_selector();
+            let free_cols = [(); 16].map(|_| meta.advice_column());
+            let s_ctrl_cols = [(); HASH_TYPE_CNT].map(|_| meta.advice_column());
+            let tables = MPTOpTables::configure_create(meta);
+            let hash_tbl = HashTable::configure_create(meta);
+
+            let gadget = MPTOpGadget::configure(
+                meta,
+                sel,
+                &free_cols[..5],
+                &s_ctrl_cols,
+                &free_cols[5..],
+                None,
+                tables,
+                hash_tbl,
+            );
+
+            MPTTestConfig {
+                gadget,
+                sel,
+                free_cols,
+                s_ctrl_cols,
+            }
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            config
+                .gadget
+                .tables
+                .fill_mpt_transitions(&mut layouter, std::iter::empty())?;
+            config
+                .gadget
+                .hash_table
+                .dev_fill(&mut layouter, self.op.hash_traces())?;
+
+            layouter.assign_region(
+                || "mpt",
+                |mut region| {
+                    let rows = self.op.old.hash_types.len();
+                    for offset in 0..=rows + 1 {
+                        for col in config.free_cols.iter().chain(&config.s_ctrl_cols) {
+                            region.assign_advice(
+                                || "flush",
+                                *col,
+                                offset,
+                                || Value::known(Fp::zero()),
+                            )?;
+                        }
+                    }
+
+                    let till = config.gadget.assign(&mut region, 1, &self.op)?;
+                    assert_eq!(till, rows + 1);
+                    for offset in 1..till {
+                        config.sel.enable(&mut region, offset)?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn test_mpt_gadget_update() {
+        let siblings: Vec<Fp> = (0..3).map(|_| rand_fp()).collect();
+        let op =
+            SingleOp::<Fp>::create_update_op(3, &siblings, Fp::from(5u64), (rand_fp(), rand_fp()));
+
+        assert_circuit_satisfied(6, &MPTTestCircuit { op }, vec![]);
+    }
+
+    // the old leaf is resident at depth 1 and the inserted key share 3 bits
+    // with it, so the old path has to be extended by 3 levels
+    fn insert_extended_op() -> SingleOp<Fp> {
+        let old_key = Fp::from(0b110000u64);
+        let new_key = Fp::from(0b1000u64);
+        let (old_leaf, new_leaf) = (rand_fp(), rand_fp());
+        let sibling = rand_fp();
+
+        let old = MPTPath::<Fp>::create(&[false], &[sibling], old_key, Some(old_leaf));
+        let old_leaf_hash = old.hashes[1];
+        let old = old.extend(3, new_key);
+
+        let (path, key_residual) = decompose_key(new_key, 4);
+        let siblings = vec![sibling, Fp::zero(), Fp::zero(), old_leaf_hash];
+        let new = MPTPath::<Fp>::create(&path, &siblings, new_key, Some(new_leaf));
+
+        SingleOp {
+            key: new_key,
+            key_immediate: new.key_immediate().unwrap(),
+            key_residual,
+            path: path
+                .iter()
+                .map(|b| if *b { Fp::one() } else { Fp::zero() })
+                .collect(),
+            siblings,
+            old,
+            new,
+        }
+    }
+
+    #[test]
+    fn test_mpt_gadget_insert_extended() {
+        // ctrl types: Start, Middle, LeafExt, LeafExt, LeafExtFinal, Leaf
+        let op = insert_extended_op();
+        assert_eq!(
+            op.ctrl_type(),
+            vec![
+                HashType::Start,
+                HashType::Middle,
+                HashType::LeafExt,
+                HashType::LeafExt,
+                HashType::LeafExtFinal,
+                HashType::Leaf
+            ]
+        );
+
+        assert_circuit_satisfied(6, &MPTTestCircuit { op }, vec![]);
+    }
+
+    #[test]
+    fn test_op_kind() {
+        let siblings: Vec<Fp> = (0..3).map(|_| rand_fp()).collect();
+        let key = Fp::from(5u64);
+        let leaf = rand_fp();
+        let update = SingleOp::<Fp>::create_update_op(3, &siblings, key, (leaf, rand_fp()));
+        assert_eq!(update.op_kind(), OpKind::Update);
+        let noop = SingleOp::<Fp>::create_update_op(3, &siblings, key, (leaf, leaf));
+        assert_eq!(noop.op_kind(), OpKind::NoOp);
+
+        let insert = insert_extended_op();
+        assert!(insert.old.is_extended());
+        assert_eq!(insert.op_kind(), OpKind::Insert);
+        let delete = SingleOp {
+            old: insert.new.clone(),
+            new: insert.old.clone(),
+            ..insert
+        };
+        assert_eq!(delete.op_kind(), OpKind::Delete);
+
+        assert_eq!(SingleOp::<Fp>::default().op_kind(), OpKind::NoOp);
+    }
+
+    #[test]
+    fn test_explain() {
+        let siblings: Vec<Fp> = (0..3).map(|_| rand_fp()).collect();
+        let leaf = rand_fp();
+        let op = SingleOp::<Fp>::create_update_op(3, &siblings, Fp::from(5u64), (leaf, leaf));
+        let text = op.explain();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 2 + op.use_rows());
+        assert!(lines[0].contains("NoOp"));
+        assert!(!text.contains('*'));
+        assert!(lines[4].contains(&format!("sibling {:?}", siblings[1])));
+
+        // an update diverges on every level from the root to the leaf
+        let op = op.update_next(rand_fp());
+        let text = op.explain();
+        assert_eq!(
+            text.lines().filter(|l| l.ends_with('*')).count(),
+            op.use_rows()
+        );
+    }
+
+    fn empty_path() -> MPTPath<Fp> {
+        MPTPath {
+            hash_types: Vec::new(),
+            hashes: Vec::new(),
+            hash_traces: Vec::new(),
+            status: MPTPathStatus::Empty,
+        }
+    }
+
+    #[test]
+    fn test_try_root_and_leaf() {
+        let path = empty_path();
+        assert_eq!(path.try_root(), None);
+        assert_eq!(path.try_leaf(), None);
+
+        let path = MPTPath::<Fp>::default();
+        assert_eq!(path.try_root(), Some(Fp::zero()));
+        assert_eq!(path.try_leaf(), Some(None));
+
+        let leaf = rand_fp();
+        let path = MPTPath {
+            hash_types: vec![HashType::Start, HashType::Leaf],
+            hashes: vec![leaf, leaf],
+            ..empty_path()
+        };
+        assert_eq!(path.root(), leaf);
+        assert_eq!(path.try_leaf(), Some(Some(leaf)));
+    }
+
+    #[test]
+    fn test_verify_hashes() {
+        assert!(empty_path().verify_hashes().is_err());
+        assert!(MPTPath::<Fp>::default().verify_hashes().is_ok());
+
+        let siblings: Vec<Fp> = (0..3).map(|_| rand_fp()).collect();
+        let path = MPTPath::<Fp>::create(
+            &[true, false, true],
+            &siblings,
+            Fp::from(5u64),
+            Some(rand_fp()),
+        );
+        assert!(path.verify_hashes().is_ok());
+        assert!(path
+            .clone()
+            .extend(2, Fp::from(13u64))
+            .verify_hashes()
+            .is_ok());
+
+        let empty = MPTPath::<Fp>::create(&[true, false, true], &siblings, Fp::from(5u64), None);
+        assert!(empty.verify_hashes().is_ok());
+
+        let mut tampered = path.clone();
+        tampered.hashes[1] = rand_fp();
+        assert!(tampered.verify_hashes().is_err());
+
+        let mut tampered = path;
+        tampered.hash_traces[0].2 = rand_fp();
+        assert!(tampered.verify_hashes().is_err());
+    }
+
+    #[test]
+    fn test_single_op_bytes_round_trip() {
+        let siblings: Vec<Fp> = (0..3).map(|_| rand_fp()).collect();
+        let op =
+            SingleOp::<Fp>::create_update_op(3, &siblings, Fp::from(5u64), (rand_fp(), rand_fp()));
+
+        let bytes = op.to_bytes();
+        let decoded = SingleOp::<Fp>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.key, op.key);
+        assert_eq!(decoded.key_residual, op.key_residual);
+        assert_eq!(decoded.path, op.path);
+        assert_eq!(decoded.old.hash_types, op.old.hash_types);
+        assert_eq!(decoded.new.hashes, op.new.hashes);
+        assert_eq!(decoded.hash_traces().count(), op.hash_traces().count());
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        let extended = SingleOp {
+            old: op.old.clone().extend(2, Fp::from(9u64)),
+            ..op
+        };
+        let bytes = extended.to_bytes();
+        let decoded = SingleOp::<Fp>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.extended_proof(), extended.extended_proof());
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        assert!(SingleOp::<Fp>::from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_try_create_update_op() {
+        let siblings: Vec<Fp> = (0..32).map(|_| rand_fp()).collect();
+        let leafs = (rand_fp(), rand_fp());
+
+        assert!(SingleOp::<Fp>::try_create_update_op(8, &siblings[..8], rand_fp(), leafs).is_ok());
+        match SingleOp::<Fp>::try_create_update_op(32, &siblings, rand_fp(), leafs) {
+            Err(TraceError::DataErr(msg)) => assert!(msg.contains("32 layers")),
+            _ => panic!("deep path should be rejected"),
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_root_of_empty_path() {
+        empty_path().root();
+    }
 }
@@ -2,7 +2,11 @@ use halo2_proofs::{
     circuit::{Region, Value},
     halo2curves::FieldExt,
     plonk::{Advice, Column, ConstraintSystem, Error, Selector},
+    poly::Rotation,
 };
+use hash_circuit::Hashable;
+
+use crate::operation::AccountOp;
 
 // padding gadget keep start and end root identical, it often act as the
 // "terminal" circuit to fill the rest space in the region, it has only one ctrl
@@ -12,19 +16,40 @@ pub(crate) struct PaddingGadget {
     s_enable: Column<Advice>,
     ctrl_type: Column<Advice>,
     s_ctrl_type: Column<Advice>,
+    old_val: Column<Advice>,
+    new_val: Column<Advice>,
+    key_val: Column<Advice>,
 }
 
 impl PaddingGadget {
+    /// create gadget from the exported cols (layout_flag, s_enable, old_val,
+    /// new_val, key_val ...) of layer
     pub fn configure<Fp: FieldExt>(
-        _meta: &mut ConstraintSystem<Fp>,
-        _sel: Selector,
+        meta: &mut ConstraintSystem<Fp>,
+        sel: Selector,
         exported: &[Column<Advice>],
         s_ctrl_type: &[Column<Advice>],
     ) -> Self {
+        let s_enable = exported[1];
+        let old_val = exported[2];
+        let new_val = exported[3];
+
+        meta.create_gate("padding is no-op", |meta| {
+            let enable = meta.query_selector(sel) * meta.query_advice(s_enable, Rotation::cur());
+            vec![
+                enable
+                    * (meta.query_advice(old_val, Rotation::cur())
+                        - meta.query_advice(new_val, Rotation::cur())),
+            ]
+        });
+
         Self {
             ctrl_type: exported[0],
-            s_enable: exported[1],
+            s_enable,
             s_ctrl_type: s_ctrl_type[0],
+            old_val,
+            new_val,
+            key_val: exported[4],
         }
     }
 
@@ -56,4 +81,95 @@ impl PaddingGadget {
         }
         Ok(())
     }
+
+    /// fill rows in [from_offset, to_offset) with the repeated no-op record
+    /// (the padding hash of `AccountOp`), so the circuit shape is kept
+    /// regardless of how many ops are assigned; old and new values are
+    /// identical so the root is not affected
+    pub fn assign<Fp: Hashable>(
+        &self,
+        region: &mut Region<'_, Fp>,
+        from_offset: usize,
+        to_offset: usize,
+    ) -> Result<usize, Error> {
+        let rows = to_offset.saturating_sub(from_offset);
+        self.padding(region, from_offset, rows)?;
+
+        let (old_val, new_val, key_val) = AccountOp::<Fp>::padding_hash();
+        for offset in from_offset..to_offset {
+            for (col, val, desc) in [
+                (self.old_val, old_val, "padding old"),
+                (self.new_val, new_val, "padding new"),
+                (self.key_val, key_val, "padding key"),
+            ] {
+                region.assign_advice(|| desc, col, offset, || Value::known(val))?;
+            }
+        }
+
+        Ok(from_offset + rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Selector};
+
+    use crate::test_utils::Fp;
+
+    use super::PaddingGadget;
+
+    #[derive(Clone, Debug)]
+    struct PaddingTestConfig {
+        gadget: PaddingGadget,
+        sel: Selector,
+    }
+
+    #[derive(Clone, Default)]
+    struct PaddingTestCircuit {
+        rows: usize,
+    }
+
+    impl Circuit<Fp> for PaddingTestCircuit {
+        type Config = PaddingTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let sel = meta.selector();
+            let exported: [Column<Advice>; 5] = [(); 5].map(|_| meta.advice_column());
+            let s_ctrl = meta.advice_column();
+            let gadget = PaddingGadget::configure(meta, sel, &exported, &[s_ctrl]);
+            PaddingTestConfig { gadget, sel }
+        }
+
+        fn synthesize(
+            &self,
+            config: Self::Config,
+            mut layouter: impl Layouter<Fp>,
+        ) -> Result<(), Error> {
+            layouter.assign_region(
+                || "padding",
+                |mut region| {
+                    let till = config.gadget.assign(&mut region, 0, self.rows)?;
+                    assert_eq!(till, self.rows);
+                    for offset in 0..till {
+                        config.sel.enable(&mut region, offset)?;
+                    }
+                    Ok(())
+                },
+            )
+        }
+    }
+
+    #[test]
+    fn test_padding_rows() {
+        let circuit = PaddingTestCircuit { rows: 8 };
+        let prover = MockProver::<Fp>::run(5, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
 }
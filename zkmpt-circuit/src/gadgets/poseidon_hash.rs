@@ -22,6 +22,40 @@ pub trait Hashable: FieldExt {
     }
 }
 
+/// bytes packed into one field element, 31 bytes always fit under the modulus
+pub const BYTES_PER_ELEMENT: usize = 31;
+
+/// pack bytes into field elements, each from a big-endian chunk of
+/// `BYTES_PER_ELEMENT` bytes (the last chunk may be shorter)
+pub fn pack_bytes<Fp: FieldExt>(input: &[u8]) -> Vec<Fp> {
+    input
+        .chunks(BYTES_PER_ELEMENT)
+        .map(|chunk| {
+            let mut wide = [0u8; 64];
+            wide[..chunk.len()].copy_from_slice(chunk);
+            wide[..chunk.len()].reverse();
+            Fp::from_bytes_wide(&wide)
+        })
+        .collect()
+}
+
+/// the domain tag of `poseidon_hash_bytes`, packed as one element, which
+/// keeps its hashes apart from the 2-to-1 hashes of the tries
+pub const BYTES_HASH_DOMAIN: &[u8] = b"zkprover.poseidon_hash_bytes";
+
+/// poseidon hash of a variable-length byte slice: the state starts from the
+/// domain tag and the input length (so trailing zero bytes are not ambiguous)
+/// and absorbs each packed element with the 2-to-1 hash used by the hash
+/// table, i.e. `h = hash([domain, len])`, `h = hash([h, e_i])`; an empty
+/// input absorbs nothing
+pub fn poseidon_hash_bytes<Fp: hash_circuit::Hashable>(input: &[u8]) -> Fp {
+    let domain = pack_bytes::<Fp>(BYTES_HASH_DOMAIN)[0];
+    pack_bytes::<Fp>(input).into_iter().fold(
+        <Fp as hash_circuit::Hashable>::hash([domain, Fp::from(input.len() as u64)]),
+        |state, e| <Fp as hash_circuit::Hashable>::hash([state, e]),
+    )
+}
+
 // impl Hashable for Fr {
 
 //     type SpecType = P128Pow5T3<Self>;
@@ -44,4 +78,91 @@ mod tests {
         let message = [Fp::from(1), Fp::from(2)];
         let output = poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash(message);
     }
+
+    #[test]
+    fn test_pack_bytes() {
+        use super::pack_bytes;
+        use crate::test_utils::Fp;
+        use halo2_proofs::arithmetic::FieldExt;
+
+        assert!(pack_bytes::<Fp>(&[]).is_empty());
+        assert_eq!(pack_bytes::<Fp>(&[0x12, 0x34]), vec![Fp::from(0x1234)]);
+
+        let input: Vec<u8> = (1..=33).collect();
+        let packed = pack_bytes::<Fp>(&input);
+        assert_eq!(packed.len(), 2);
+        assert_eq!(packed[1], Fp::from(0x2021));
+        let mut expected = [0u8; 64];
+        for (i, b) in (1..=31u8).rev().enumerate() {
+            expected[i] = b;
+        }
+        assert_eq!(packed[0], Fp::from_bytes_wide(&expected));
+    }
+
+    #[test]
+    fn test_poseidon_hash_bytes() {
+        use super::{pack_bytes, poseidon_hash_bytes, BYTES_HASH_DOMAIN};
+        use crate::test_utils::Fp;
+        use halo2_proofs::arithmetic::FieldExt;
+        use hash_circuit::Hashable;
+
+        let hash = |a: Fp, b: Fp| <Fp as Hashable>::hash([a, b]);
+        let domain = pack_bytes::<Fp>(BYTES_HASH_DOMAIN)[0];
+        let start = |len: u64| hash(domain, Fp::from(len));
+
+        assert_eq!(poseidon_hash_bytes::<Fp>(&[]), start(0));
+        assert_eq!(
+            poseidon_hash_bytes::<Fp>(&[0x12, 0x34]),
+            hash(start(2), Fp::from(0x1234))
+        );
+
+        let input = [0xffu8; 32];
+        let e0 = Fp::from_bytes_wide(&{
+            let mut wide = [0u8; 64];
+            wide[..31].copy_from_slice(&[0xff; 31]);
+            wide
+        });
+        assert_eq!(
+            poseidon_hash_bytes::<Fp>(&input),
+            hash(hash(start(32), e0), Fp::from(0xff))
+        );
+
+        // trailing zeros and leading zeros are both significant
+        assert_ne!(
+            poseidon_hash_bytes::<Fp>(&[0]),
+            poseidon_hash_bytes::<Fp>(&[])
+        );
+        assert_ne!(
+            poseidon_hash_bytes::<Fp>(&[0, 1]),
+            poseidon_hash_bytes::<Fp>(&[1])
+        );
+    }
+
+    #[test]
+    fn test_poseidon_hash_bytes_collision() {
+        use super::poseidon_hash_bytes;
+        use crate::test_utils::Fp;
+        use halo2_proofs::arithmetic::Field;
+        use hash_circuit::Hashable;
+
+        let hash = |a: Fp, b: Fp| <Fp as Hashable>::hash([a, b]);
+
+        // not a plain 2-to-1 hash of the tries
+        let empty = poseidon_hash_bytes::<Fp>(&[]);
+        assert_ne!(empty, hash(Fp::zero(), Fp::zero()));
+        assert_ne!(poseidon_hash_bytes::<Fp>(&[0]), hash(Fp::one(), Fp::zero()));
+
+        // trailing zero bytes, also across the chunk boundary
+        let mut input = vec![7u8; 31];
+        let mut hashes = vec![empty];
+        for _ in 0..3 {
+            hashes.push(poseidon_hash_bytes::<Fp>(&input));
+            input.push(0);
+        }
+        hashes.push(poseidon_hash_bytes::<Fp>(&[0; 31]));
+        hashes.push(poseidon_hash_bytes::<Fp>(&[0; 32]));
+        for (i, a) in hashes.iter().enumerate() {
+            assert!(hashes[i + 1..].iter().all(|b| a != b));
+        }
+    }
 }
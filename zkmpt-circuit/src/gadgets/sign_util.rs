@@ -37,6 +37,27 @@ pub(crate) mod rlc {
         }
     }
 
+    /// rlc of field elements, e.g. the limbs of address or key
+    pub(crate) fn value_fp<F: FieldExt>(values: &[F], randomness: F) -> F {
+        if !values.is_empty() {
+            generic(values.iter().copied(), randomness)
+        } else {
+            F::zero()
+        }
+    }
+
+    /// expression of `value_fp`, e.g. over the queried limb cells
+    pub(crate) fn expr_fp<F: FieldExt>(
+        expressions: &[Expression<F>],
+        randomness: Expression<F>,
+    ) -> Expression<F> {
+        if !expressions.is_empty() {
+            generic(expressions.iter().cloned(), randomness)
+        } else {
+            0.expr()
+        }
+    }
+
     fn generic<V, I>(values: I, randomness: V) -> V
     where
         I: IntoIterator<Item = V>,
@@ -246,3 +267,56 @@ impl<F: Field> Challenges<Expression<F>> {
         Self::powers_of(self.lookup_input.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::rlc;
+    use crate::test_utils::{rand_fp, Fp};
+    use halo2_proofs::plonk::Expression;
+
+    #[test]
+    fn test_rlc_value_fp() {
+        let randomness = rand_fp();
+        let bytes = [3u8, 5, 7];
+        let limbs = bytes.map(|b| Fp::from(b as u64));
+        assert_eq!(
+            rlc::value_fp(&limbs, randomness),
+            rlc::value(&bytes, randomness)
+        );
+        assert_eq!(
+            rlc::value_fp(&limbs, randomness),
+            limbs[0] + limbs[1] * randomness + limbs[2] * randomness * randomness
+        );
+        assert_eq!(rlc::value_fp::<Fp>(&[], randomness), Fp::zero());
+    }
+
+    #[test]
+    fn test_rlc_expr_fp() {
+        // only constants are involved, so the expression evaluates to a value
+        let eval = |expr: Expression<Fp>| {
+            expr.evaluate(
+                &|c| c,
+                &|_| unreachable!(),
+                &|_| unreachable!(),
+                &|_| unreachable!(),
+                &|_| unreachable!(),
+                &|_| unreachable!(),
+                &|a| -a,
+                &|a, b| a + b,
+                &|a, b| a * b,
+                &|a, f| a * f,
+            )
+        };
+        let randomness = rand_fp();
+        let limbs = [3u64, 5, 7].map(Fp::from);
+        let exprs = limbs.map(Expression::Constant);
+        assert_eq!(
+            eval(rlc::expr_fp(&exprs, Expression::Constant(randomness))),
+            rlc::value_fp(&limbs, randomness)
+        );
+        assert_eq!(
+            eval(rlc::expr_fp(&[], Expression::Constant(randomness))),
+            Fp::zero()
+        );
+    }
+}
@@ -10,7 +10,10 @@ use crate::gadgets::{
 };
 use ecc::{maingate, EccConfig, GeneralEccChip};
 use ecdsa::ecdsa::{AssignedEcdsaSig, AssignedPublicKey, EcdsaChip};
-use eth_types::sign_types::{pk_bytes_le, pk_bytes_swap_endianness, SignData};
+use eth_types::sign_types::{pk_bytes_le, pk_bytes_swap_endianness};
+/// the witness of a signature verification, re-exported so the gadget can be
+/// fed directly without a full `Transaction`
+pub use eth_types::sign_types::SignData;
 use eth_types::Field;
 use halo2_proofs::{
     arithmetic::{CurveAffine, FieldExt},
@@ -119,6 +122,7 @@ pub(crate) struct SignVerifyConfig {
     rlc: Column<Advice>,
     // Keccak
     q_keccak: Selector,
+    q_msg_keccak: Selector,
     keccak_table: KeccakTable,
 }
 
@@ -196,6 +200,38 @@ impl SignVerifyConfig {
             input.into_iter().zip(table).collect()
         });
 
+        // Verify that keccak(msg) = msg_hash by keccak table lookup, where msg is
+        // the signed message (the rlp of tx fields) supplied as witness, so the
+        // signature can not be over an unrelated hash.
+        let q_msg_keccak = meta.complex_selector();
+        meta.lookup_any("msg hash keccak", |meta| {
+            // Layout:
+            // | q_msg_keccak |        a        |    b    |     rlc      |
+            // | ------------ | --------------- | ------- | ------------ |
+            // |       1      | is_address_zero | msg_len |    msg_rlc   |
+            // |              |                 |         | msg_hash_rlc |
+            let q_msg_keccak = meta.query_selector(q_msg_keccak);
+            let [is_address_zero, msg_len] =
+                [0, 1].map(|i| meta.query_advice(main_gate_config.advices()[i], Rotation::cur()));
+            let is_enable = q_msg_keccak * not::expr(is_address_zero);
+
+            let input = [
+                is_enable.clone(),
+                is_enable.clone() * meta.query_advice(rlc, Rotation::cur()),
+                is_enable.clone() * msg_len,
+                is_enable * meta.query_advice(rlc, Rotation::next()),
+            ];
+            let table = [
+                keccak_table.is_enabled,
+                keccak_table.input_rlc,
+                keccak_table.input_len,
+                keccak_table.output_rlc,
+            ]
+            .map(|column| meta.query_advice(column, Rotation::cur()));
+
+            input.into_iter().zip(table).collect()
+        });
+
         Self {
             range_config,
             main_gate_config,
@@ -204,6 +240,7 @@ impl SignVerifyConfig {
             q_rlc_keccak_input,
             rlc,
             q_keccak,
+            q_msg_keccak,
         }
     }
 
@@ -483,6 +520,33 @@ impl<F: Field> SignVerifyChip<F> {
         Ok(())
     }
 
+    fn enable_msg_keccak_lookup(
+        &self,
+        config: &SignVerifyConfig,
+        ctx: &mut RegionCtx<F>,
+        is_address_zero: &AssignedCell<F, F>,
+        msg_len: &AssignedCell<F, F>,
+        msg_rlc: &AssignedCell<F, F>,
+        msg_hash_rlc: &AssignedCell<F, F>,
+    ) -> Result<(), Error> {
+        let copy = |ctx: &mut RegionCtx<F>, name, column, assigned: &AssignedCell<F, F>| {
+            let copied = ctx.assign_advice(|| name, column, assigned.value().copied())?;
+            ctx.constrain_equal(assigned.cell(), copied.cell())?;
+            Ok::<_, Error>(())
+        };
+
+        let [a, b, ..] = config.main_gate_config.advices();
+        ctx.enable(config.q_msg_keccak)?;
+        copy(ctx, "is_address_zero", a, is_address_zero)?;
+        copy(ctx, "msg_len", b, msg_len)?;
+        copy(ctx, "msg_rlc", config.rlc, msg_rlc)?;
+        ctx.next();
+        copy(ctx, "msg_hash_rlc", config.rlc, msg_hash_rlc)?;
+        ctx.next();
+
+        Ok(())
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn assign_signature_verify(
         &self,
@@ -490,6 +554,7 @@ impl<F: Field> SignVerifyChip<F> {
         ctx: &mut RegionCtx<F>,
         chips: &ChipsRef<F, NUMBER_OF_LIMBS, BIT_LEN_LIMB>,
         sign_data: Option<&SignData>,
+        msg: &[u8],
         assigned_ecdsa: &AssignedECDSA<F>,
         challenges: &Challenges<Value<F>>,
     ) -> Result<AssignedSignatureVerify<F>, Error> {
@@ -600,17 +665,41 @@ impl<F: Field> SignVerifyChip<F> {
 
         self.enable_keccak_lookup(config, ctx, &is_address_zero, &pk_rlc, &pk_hash_rlc)?;
 
+        let msg_len = main_gate.assign_constant(ctx, F::from(msg.len() as u64))?;
+        let msg_rlc = self.assign_rlc_le(
+            config,
+            ctx,
+            chips,
+            "msg",
+            config.q_rlc_keccak_input,
+            challenges.keccak_input(),
+            msg.iter()
+                .rev()
+                .map(|byte| Term::unassigned(Value::known(F::from(*byte as u64)))),
+        )?;
+        self.enable_msg_keccak_lookup(
+            config,
+            ctx,
+            &is_address_zero,
+            &msg_len,
+            &msg_rlc,
+            &msg_hash_rlc,
+        )?;
+
         Ok(AssignedSignatureVerify {
             address,
             msg_hash_rlc,
         })
     }
 
+    /// verify `signatures` where `msgs[i]` is the message signed by
+    /// `signatures[i]` (its keccak must be the msg_hash)
     pub(crate) fn assign(
         &self,
         config: &SignVerifyConfig,
         layouter: &mut impl Layouter<F>,
         signatures: &[SignData],
+        msgs: &[Vec<u8>],
         challenges: &Challenges<Value<F>>,
     ) -> Result<Vec<AssignedSignatureVerify<F>>, Error> {
         if signatures.len() > self.max_verif {
@@ -621,6 +710,14 @@ impl<F: Field> SignVerifyChip<F> {
             );
             return Err(Error::Synthesis);
         }
+        if msgs.len() != signatures.len() {
+            error!(
+                "msgs.len() = {} != signatures.len() = {}",
+                msgs.len(),
+                signatures.len()
+            );
+            return Err(Error::Synthesis);
+        }
         let main_gate = MainGate::new(config.main_gate_config.clone());
         let range_chip = RangeChip::new(config.range_config.clone());
         let mut ecc_chip = GeneralEccChip::<Secp256k1Affine, F, NUMBER_OF_LIMBS, BIT_LEN_LIMB>::new(
@@ -676,11 +773,13 @@ impl<F: Field> SignVerifyChip<F> {
                 let mut ctx = RegionCtx::new(region, 0);
                 for (i, assigned_ecdsa) in assigned_ecdsas.iter().enumerate() {
                     let sign_data = signatures.get(i); // None when padding (enabled when address == 0)
+                    let msg = msgs.get(i).map(Vec::as_slice).unwrap_or_default();
                     let assigned_sig_verif = self.assign_signature_verify(
                         config,
                         &mut ctx,
                         &chips,
                         sign_data,
+                        msg,
                         assigned_ecdsa,
                         challenges,
                     )?;
@@ -691,6 +790,54 @@ impl<F: Field> SignVerifyChip<F> {
             },
         )
     }
+
+    /// bind the recovered address of `assigned_sigs[i]` to the account address
+    /// in `address_limbs[i]`, given as the (limb_0, limb_1) cells of the
+    /// account gadget, so a signature only authorizes the update of the
+    /// account it is recovered to
+    pub(crate) fn bind_address(
+        &self,
+        config: &SignVerifyConfig,
+        layouter: &mut impl Layouter<F>,
+        assigned_sigs: &[AssignedSignatureVerify<F>],
+        address_limbs: &[[AssignedCell<F, F>; 2]],
+    ) -> Result<(), Error> {
+        if assigned_sigs.len() != address_limbs.len() {
+            error!(
+                "assigned_sigs.len() = {} != address_limbs.len() = {}",
+                assigned_sigs.len(),
+                address_limbs.len()
+            );
+            return Err(Error::Synthesis);
+        }
+        let main_gate = MainGate::new(config.main_gate_config.clone());
+        // address = limb_0 * 2^32 + limb_1 / 2^96, the same as the address
+        // constraint in account gadget
+        let limb_0_coeff = F::from(0x100000000u64);
+        let limb_1_coeff = F::from_u128(0x1000000000000000000000000u128)
+            .invert()
+            .unwrap();
+
+        layouter.assign_region(
+            || "signature address binding",
+            |region| {
+                let mut ctx = RegionCtx::new(region, 0);
+                for (assigned_sig, [limb_0, limb_1]) in assigned_sigs.iter().zip(address_limbs) {
+                    let address = main_gate.compose(
+                        &mut ctx,
+                        &[
+                            maingate::Term::Assigned(limb_0, limb_0_coeff),
+                            maingate::Term::Assigned(limb_1, limb_1_coeff),
+                        ],
+                        F::zero(),
+                    )?;
+                    main_gate.assert_equal(&mut ctx, &address, &assigned_sig.address)?;
+                }
+                log::debug!("signature address binding: {} rows", ctx.offset());
+                Ok(())
+            },
+        )
+    }
 }
 
 fn pub_key_hash_to_address<F: Field>(pk_hash: &[u8]) -> F {
@@ -700,9 +847,9 @@ fn pub_key_hash_to_address<F: Field>(pk_hash: &[u8]) -> F {
 }
 
 /// Generate the keccak inputs required by the SignVerify Chip from the
-/// signature datas.
-pub fn keccak_inputs_sign_verify(sigs: &[SignData]) -> Vec<Vec<u8>> {
-    let mut inputs = Vec::new();
+/// signature datas and the signed messages.
+pub fn keccak_inputs_sign_verify(sigs: &[SignData], msgs: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut inputs = msgs.to_vec();
     for sig in sigs {
         let pk_le = pk_bytes_le(&sig.pk);
         let pk_be = pk_bytes_swap_endianness(&pk_le);
@@ -726,16 +873,17 @@ mod sign_verify_tests {
     };
     use eth_types::sign_types::sign;
     use halo2_proofs::{
-        arithmetic::Field as HaloField,
-        circuit::{SimpleFloorPlanner, Layouter},
+        arithmetic::{Field as HaloField, FieldExt},
+        circuit::{SimpleFloorPlanner, Layouter, Value},
         dev::MockProver,
         halo2curves::{
             bn256::Fr,
             group::{Curve, Group},
             CurveAffine, secp256k1::{Secp256k1Affine, self},
         },
-        plonk::{Circuit, ConstraintSystem, Error}
+        plonk::{Advice, Circuit, Column, ConstraintSystem, Error}
     };
+    use keccak256::plain::Keccak;
     use rand::{RngCore, SeedableRng};
     use rand_xorshift::XorShiftRng;
 
@@ -745,6 +893,7 @@ mod sign_verify_tests {
     struct TestCircuitSignVerifyConfig {
         sign_verify: SignVerifyConfig,
         challenges: Challenges,
+        address_limbs: [Column<Advice>; 2],
     }
 
     impl TestCircuitSignVerifyConfig {
@@ -757,9 +906,15 @@ mod sign_verify_tests {
                 SignVerifyConfig::new(meta, keccak_table, challenges)
             };
 
+            let address_limbs = [0; 2].map(|_| meta.advice_column());
+            for col in address_limbs {
+                meta.enable_equality(col);
+            }
+
             TestCircuitSignVerifyConfig {
                 sign_verify,
                 challenges,
+                address_limbs,
             }
         }
     }
@@ -768,6 +923,8 @@ mod sign_verify_tests {
     struct TestCircuitSignVerify<F: Field> {
         sign_verify: SignVerifyChip<F>,
         signatures: Vec<SignData>,
+        msgs: Vec<Vec<u8>>,
+        address_limbs: Vec<[F; 2]>,
     }
 
     impl<F: Field> Circuit<F> for TestCircuitSignVerify<F> {
@@ -789,15 +946,44 @@ mod sign_verify_tests {
         ) -> Result<(), Error> {
             let challenges = config.challenges.values(&mut layouter);
 
-            self.sign_verify.assign(
+            let assigned_sigs = self.sign_verify.assign(
                 &config.sign_verify,
                 &mut layouter,
                 &self.signatures,
+                &self.msgs,
                 &challenges,
             )?;
+            if !self.address_limbs.is_empty() {
+                let address_limbs = layouter.assign_region(
+                    || "account address limbs",
+                    |mut region| {
+                        self.address_limbs
+                            .iter()
+                            .enumerate()
+                            .map(|(offset, limbs)| {
+                                let mut assign = |i: usize| {
+                                    region.assign_advice(
+                                        || "address limb",
+                                        config.address_limbs[i],
+                                        offset,
+                                        || Value::known(limbs[i]),
+                                    )
+                                };
+                                Ok([assign(0)?, assign(1)?])
+                            })
+                            .collect::<Result<Vec<_>, Error>>()
+                    },
+                )?;
+                self.sign_verify.bind_address(
+                    &config.sign_verify,
+                    &mut layouter,
+                    &assigned_sigs[..address_limbs.len()],
+                    &address_limbs,
+                )?;
+            }
             config.sign_verify.keccak_table.dev_load(
                 &mut layouter,
-                &keccak_inputs_sign_verify(&self.signatures),
+                &keccak_inputs_sign_verify(&self.signatures, &self.msgs),
                 &challenges,
             )?;
             config.sign_verify.load_range(&mut layouter)?;
@@ -805,7 +991,18 @@ mod sign_verify_tests {
         }
     }
 
-    fn run<F: Field>(k: u32, max_verif: usize, signatures: Vec<SignData>) {
+    fn run<F: Field>(k: u32, max_verif: usize, signatures: Vec<SignData>, msgs: Vec<Vec<u8>>) {
+        let prover = mock_prove::<F>(k, max_verif, signatures, msgs, vec![]);
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    fn mock_prove<F: Field>(
+        k: u32,
+        max_verif: usize,
+        signatures: Vec<SignData>,
+        msgs: Vec<Vec<u8>>,
+        address_limbs: Vec<[F; 2]>,
+    ) -> MockProver<F> {
         let mut rng = XorShiftRng::seed_from_u64(2);
         let aux_generator =
             <Secp256k1Affine as CurveAffine>::CurveExt::random(&mut rng).to_affine();
@@ -819,13 +1016,14 @@ mod sign_verify_tests {
                 _marker: PhantomData,
             },
             signatures,
+            msgs,
+            address_limbs,
         };
 
-        let prover = match MockProver::run(k, &circuit, vec![vec![]]) {
+        match MockProver::run(k, &circuit, vec![vec![]]) {
             Ok(prover) => prover,
             Err(e) => panic!("{:#?}", e),
-        };
-        assert_eq!(prover.verify(), Ok(()));
+        }
     }
 
     // Generate a test key pair
@@ -839,9 +1037,29 @@ mod sign_verify_tests {
         (sk, pk)
     }
 
-    // Generate a test message hash
-    fn gen_msg_hash(rng: impl RngCore) -> secp256k1::Fq {
-        secp256k1::Fq::random(rng)
+    // Generate a test message and its hash
+    fn gen_msg(mut rng: impl RngCore) -> (Vec<u8>, secp256k1::Fq) {
+        let mut msg = vec![0u8; 1 + (rng.next_u32() % 128) as usize];
+        rng.fill_bytes(&mut msg);
+        let mut keccak = Keccak::default();
+        keccak.update(&msg);
+        let mut hash = [0u8; 64];
+        hash[..32].copy_from_slice(&keccak.digest());
+        hash[..32].reverse();
+        (msg, secp256k1::Fq::from_bytes_wide(&hash))
+    }
+
+    fn gen_signatures(rng: &mut impl RngCore, num_sigs: usize) -> (Vec<SignData>, Vec<Vec<u8>>) {
+        let mut signatures = Vec::new();
+        let mut msgs = Vec::new();
+        for _ in 0..num_sigs {
+            let (sk, pk) = gen_key_pair(&mut *rng);
+            let (msg, msg_hash) = gen_msg(&mut *rng);
+            let sig = sign_with_rng(&mut *rng, sk, msg_hash);
+            signatures.push(SignData::new(sig, pk, msg_hash));
+            msgs.push(msg);
+        }
+        (signatures, msgs)
     }
 
     // Returns (r, s)
@@ -867,19 +1085,74 @@ mod sign_verify_tests {
         let mut rng = XorShiftRng::seed_from_u64(1);
         const MAX_VERIF: usize = 3;
         const NUM_SIGS: usize = 2;
-        let mut signatures = Vec::new();
-        for _ in 0..NUM_SIGS {
-            let (sk, pk) = gen_key_pair(&mut rng);
-            let msg_hash = gen_msg_hash(&mut rng);
-            let sig = sign_with_rng(&mut rng, sk, msg_hash);
-            signatures.push(SignData {
-                signature: sig,
-                pk,
-                msg_hash,
-            });
-        }
+        let (signatures, msgs) = gen_signatures(&mut rng, NUM_SIGS);
+
+        let k = 19;
+        run::<Fr>(k, MAX_VERIF, signatures, msgs);
+    }
+
+    #[test]
+    fn sign_data_verify() {
+        let mut rng = XorShiftRng::seed_from_u64(1);
+        let (signatures, _) = gen_signatures(&mut rng, 2);
+        assert!(signatures.iter().all(SignData::verify));
+        assert!(SignData::default().verify());
+
+        let mut tampered = signatures[0].clone();
+        tampered.msg_hash += secp256k1::Fq::one();
+        assert!(!tampered.verify());
+        let mut tampered = signatures[0].clone();
+        tampered.pk = signatures[1].pk;
+        assert!(!tampered.verify());
+        let mut tampered = signatures[0].clone();
+        tampered.signature.1 = secp256k1::Fq::zero();
+        assert!(!tampered.verify());
+    }
+
+    #[test]
+    fn sign_verify_unrelated_msg() {
+        let mut rng = XorShiftRng::seed_from_u64(1);
+        let (signatures, mut msgs) = gen_signatures(&mut rng, 1);
+        // the signature is valid but over the hash of another message
+        msgs[0][0] ^= 1;
+
+        let k = 19;
+        let prover = mock_prove::<Fr>(k, 2, signatures, msgs, vec![]);
+        assert!(prover.verify().is_err());
+    }
+
+    // The (limb_0, limb_1) of the address recovered from the public key, as
+    // the account gadget holds it
+    fn address_limbs<F: Field>(sign_data: &SignData) -> [F; 2] {
+        let pk_be = pk_bytes_swap_endianness(&pk_bytes_le(&sign_data.pk));
+        let mut keccak = Keccak::default();
+        keccak.update(&pk_be);
+        let mut word = [0u8; 32];
+        word[..20].copy_from_slice(&keccak.digest()[12..]);
+        [&word[..16], &word[16..]]
+            .map(|limb| F::from_u128(u128::from_be_bytes(limb.try_into().unwrap())))
+    }
+
+    #[test]
+    fn sign_verify_address_binding() {
+        let mut rng = XorShiftRng::seed_from_u64(1);
+        let (signatures, msgs) = gen_signatures(&mut rng, 2);
+        let limbs = signatures.iter().map(address_limbs::<Fr>).collect();
+
+        let k = 19;
+        let prover = mock_prove::<Fr>(k, 2, signatures, msgs, limbs);
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn sign_verify_address_binding_other_account() {
+        let mut rng = XorShiftRng::seed_from_u64(1);
+        let (signatures, msgs) = gen_signatures(&mut rng, 2);
+        // each signature is valid but is bound to the account of the other one
+        let limbs = signatures.iter().rev().map(address_limbs::<Fr>).collect();
 
         let k = 19;
-        run::<Fr>(k, MAX_VERIF, signatures);
+        let prover = mock_prove::<Fr>(k, 2, signatures, msgs, limbs);
+        assert!(prover.verify().is_err());
     }
 }
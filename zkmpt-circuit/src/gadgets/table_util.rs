@@ -5,6 +5,8 @@ use halo2_proofs::{
     plonk::{ConstraintSystem, Error, Expression, TableColumn},
 };
 
+use super::mpt::HashType;
+
 // we lookup the transition of ctrl type from the preset table, and different
 // kind of rules is specified here
 pub(crate) enum CtrlTransitionKind {
@@ -77,6 +79,56 @@ impl MPTOpTables {
         self.build_lookup_any(enable, [old, new], mark)
     }
 
+    /// legal transitions of hash type in MPT: (prev, cur) along a path, and
+    /// (old, new) between the paths before and after an op
+    pub fn mpt_transition_rules() -> impl Iterator<Item = ([u32; 3], u32)> + Clone {
+        use HashType::*;
+        let mpt = [
+            (Start, Empty),
+            (Start, Leaf),
+            (Start, Middle),
+            (Middle, Middle),
+            (Middle, Empty),
+            (Middle, Leaf),
+            (Middle, LeafExt),
+            (Middle, LeafExtFinal),
+            (LeafExt, LeafExt),
+            (LeafExt, LeafExtFinal),
+            (LeafExtFinal, Empty),
+            (LeafExtFinal, Leaf),
+        ]
+        .map(|(a, b)| ([a as u32, b as u32, 0], CtrlTransitionKind::Mpt as u32));
+        let op = [
+            (Start, Start),
+            (Empty, Empty),
+            (Empty, Leaf),
+            (Leaf, Empty),
+            (Leaf, Leaf),
+            (Middle, Middle),
+            (Middle, LeafExt),
+            (Middle, LeafExtFinal),
+            (LeafExt, Middle),
+            (LeafExtFinal, Middle),
+        ]
+        .map(|(a, b)| {
+            (
+                [a as u32, b as u32, 0],
+                CtrlTransitionKind::Operation as u32,
+            )
+        });
+        mpt.into_iter().chain(op)
+    }
+
+    /// fill the table with the mpt transitions, and the rules from other
+    /// gadgets which share the same table
+    pub fn fill_mpt_transitions<Fp: FieldExt>(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        other_rules: impl Iterator<Item = ([u32; 3], u32)> + Clone,
+    ) -> Result<(), Error> {
+        self.fill_constant(layouter, Self::mpt_transition_rules().chain(other_rules))
+    }
+
     pub fn fill_constant<Fp: FieldExt>(
         &self,
         layouter: &mut impl Layouter<Fp>,
@@ -7,8 +7,8 @@
 // #![deny(missing_docs)]
 #![deny(unsafe_code)]
 
-#[cfg(test)]
-mod test_utils;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 
 pub use hash_circuit::{hash, poseidon};
 
@@ -28,6 +28,6 @@ pub mod utils;
 
 pub mod ERC4337;
 
-// pub mod smt_rocksdb_store;
+pub mod smt_rocksdb_store;
 
 pub mod manager;
@@ -1,4 +1,3 @@
-use ethers::solc::resolver::print;
 use ethers::types::{Bytes, H256, U256};
 use halo2_proofs::halo2curves::bn256::Fr as Fp;
 use halo2_proofs::{
@@ -9,15 +8,21 @@ use halo2_proofs::{
 use hash_circuit::Bn256Fr;
 use std::{path::Path, time::Duration};
 
+use crate::operation::TraceError;
+use crate::smt_rocksdb_store::{MemoryTrieStore, StoreError, TrieStore};
 use crate::verifier::evm_verify;
-use crate::zkprover_circuit::MOCK_RPC_TXS;
 use crate::{
     verifier::{
-        circuit_deploy::{keygen, load_target_circuit_params, load_target_circuit_vk},
-        gen_evm_verifier, gen_proof,
+        circuit_deploy::{
+            keygen, load_target_circuit_params, load_target_circuit_vk, TargetCircuit,
+        },
+        gen_evm_verifier, gen_proof, VerifierError,
     },
     zkprover_circuit::{IntergrateCircuit, ZkProverCircuit},
-    ERC4337::bundler::{BundlerRpcData, BundlerRpcTxData},
+    ERC4337::{
+        bundler::{BundlerRpcData, BundlerRpcResult, BundlerRpcTxData, JsonRpcError, Transaction},
+        geth_types::Error as BundlerError,
+    },
 };
 use reqwest::header::HeaderMap;
 use reqwest::ClientBuilder;
@@ -25,6 +30,77 @@ use reqwest::Error;
 use reqwest::{Client, Response};
 use serde_json::json;
 
+#[derive(Debug, thiserror::Error)]
+/// mission errors of the manager
+pub enum ManagerError {
+    #[error(transparent)]
+    /// transport or decoding error talking to the bundler
+    Http(#[from] Error),
+    #[error("bundler rpc error {}: {}", .0.code, .0.message)]
+    /// the error object returned by the bundler
+    Rpc(JsonRpcError),
+    #[error(transparent)]
+    /// the response of the bundler can not be used
+    Bundler(BundlerError),
+    #[error("no batch for block {0}")]
+    /// the bundler has no batch for the requested block
+    NoBatch(u64),
+    #[error("batch hash {0:?} unmatch the tx list")]
+    /// the claimed batch hash is inconsistent with the tx list
+    BatchHash(H256),
+    #[error(transparent)]
+    /// the proving setup can not be made or the batch can not be proved
+    Prove(#[from] VerifierError),
+    #[error("invalid tx in batch: {0}")]
+    /// a tx of the batch can not be converted for the circuit
+    Tx(#[from] TraceError),
+    #[error("{0} txs exceed the circuit capacity {}", TX_NUM)]
+    /// the batch has more txs than the circuit slots
+    Capacity(usize),
+    #[error(transparent)]
+    /// the state root can not be loaded or committed
+    Store(#[from] StoreError),
+}
+
+impl From<BundlerError> for ManagerError {
+    fn from(err: BundlerError) -> Self {
+        match err {
+            BundlerError::Rpc(err) => Self::Rpc(err),
+            err => Self::Bundler(err),
+        }
+    }
+}
+
+/// the tx slots of the circuit the manager proves with
+pub const TX_NUM: usize = 1;
+
+/// the batch carried by a bundler response, None means there is no mission
+fn batch_of(data: BundlerRpcData) -> Result<Option<BundlerRpcResult>, ManagerError> {
+    Ok(data.into_result()?)
+}
+
+/// the circuit over the txs of a batch, padded to `TX_NUM` slots; the circuit
+/// does not compute any state transition yet, so `mpt_root` is carried over
+/// unchanged
+fn batch_circuit(
+    tx_list: &[BundlerRpcTxData],
+    mpt_root: H256,
+) -> Result<ZkProverCircuit<Fp, TX_NUM>, ManagerError> {
+    if tx_list.len() > TX_NUM {
+        return Err(ManagerError::Capacity(tx_list.len()));
+    }
+    let txs = tx_list
+        .iter()
+        .map(Transaction::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let chain_id = tx_list.first().map_or(0, |tx| tx.chain_id.as_u64());
+    Ok(ZkProverCircuit {
+        mpt_root_before: mpt_root,
+        mpt_root_after: mpt_root,
+        ..ZkProverCircuit::with_padded_txs(txs, chain_id)
+    })
+}
+
 pub struct BundlerRpcClient {
     base_url: String, // default http:://127.0.0.1:4337
     client: Client,
@@ -43,14 +119,22 @@ impl BundlerRpcClient {
     }
 
     pub async fn pull_mission(&self) -> Result<Response, Error> {
+        self.pull_pool_batch(json!([])).await
+    }
+
+    /// pull the batch the bundler has packed for `block_number`
+    pub async fn pull_block_mission(&self, block_number: u64) -> Result<Response, Error> {
+        self.pull_pool_batch(json!([format!("{block_number:#x}")]))
+            .await
+    }
+
+    async fn pull_pool_batch(&self, params: serde_json::Value) -> Result<Response, Error> {
         let mut headers = HeaderMap::new();
         headers.insert("Content-Type", "application/json".parse().unwrap());
         let mission_body = json!({
             "jsonrpc": "2.0",
             "method": "zkp_getPoolBatch",
-            "params": [
-
-            ],
+            "params": params,
             "id": 1
         });
         self.client
@@ -92,64 +176,121 @@ pub struct Manager {
     proving_key: ProvingKey<G1Affine>,
     verifier_code: Vec<u8>,
     bundler_rpc_client: BundlerRpcClient,
+    store: Box<dyn TrieStore + Send>,
+    mpt_root: H256,
 }
 
 impl Manager {
-    pub fn new(param_path: String, rpc_url: String) -> Self {
+    /// create a manager with the in-memory store, which starts from an empty
+    /// root on every run
+    pub fn new(param_path: String, rpc_url: String) -> Result<Self, ManagerError> {
+        Self::with_store(param_path, rpc_url, Box::new(MemoryTrieStore::default()))
+    }
+
+    /// create a manager on a persistent store, reloading the last committed
+    /// root from it
+    pub fn with_store(
+        param_path: String,
+        rpc_url: String,
+        store: Box<dyn TrieStore + Send>,
+    ) -> Result<Self, ManagerError> {
+        let mpt_root = store.root()?.unwrap_or_default();
         let mut folder = Path::new(&param_path).to_path_buf();
         let params = load_target_circuit_params::<IntergrateCircuit>(&mut folder);
         let vk = load_target_circuit_vk::<IntergrateCircuit>(&mut folder, &params);
-        let zkprover = ZkProverCircuit::<Bn256Fr, 1>::random();
+        let zkprover = ZkProverCircuit::<Bn256Fr, TX_NUM>::fixture_circuit();
 
-        let pk = keygen(&params, zkprover).unwrap();
-        let deployment_code = gen_evm_verifier(&params, pk.get_vk(), vec![1]);
+        let pk = keygen(&params, zkprover).map_err(VerifierError::Keygen)?;
+        let deployment_code = gen_evm_verifier(
+            &params,
+            pk.get_vk(),
+            vec![IntergrateCircuit::PUBLIC_INPUT_SIZE],
+        )?;
         let rpc_client = BundlerRpcClient::new(rpc_url);
-        Manager {
+        Ok(Manager {
             params,
             proving_key: pk,
             verifier_code: deployment_code,
             bundler_rpc_client: rpc_client,
-        }
+            store,
+            mpt_root,
+        })
     }
 
-    pub async fn execute_mission(&self) -> Result<(), Error> {
-        let mission = self.bundler_rpc_client.pull_mission().await;
-        let bundler_rpc_data = match mission {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("rpc bundler Error : {}", e);
-                return Err(e);
-            }
-        };
-        let bundler_rpc_data_result = bundler_rpc_data.json::<BundlerRpcData>().await;
-        let bundler_rpc_data = match bundler_rpc_data_result {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("rpc bundler Error : {}", e);
-                return Err(e);
-            }
-        };
-        // let bundler_rpc_data = MOCK_RPC_TXS.clone();
-        let result = bundler_rpc_data.result;
+    /// the last committed state root
+    pub fn mpt_root(&self) -> H256 {
+        self.mpt_root
+    }
 
-        let task_id = bundler_rpc_data.id;
-        let result_data = match result {
+    /// persist a new state root after its batch has been accepted
+    pub fn commit_root(&mut self, root: H256) -> Result<(), StoreError> {
+        self.store.commit_root(root)?;
+        self.mpt_root = root;
+        Ok(())
+    }
+
+    fn prover_circuit(
+        &self,
+        tx_list: &[BundlerRpcTxData],
+    ) -> Result<ZkProverCircuit<Fp, TX_NUM>, ManagerError> {
+        batch_circuit(tx_list, self.mpt_root)
+    }
+
+    /// pull the next batch from the bundler, None if there is no mission
+    pub async fn fetch_batch(&self) -> Result<Option<BundlerRpcResult>, ManagerError> {
+        let bundler_rpc_data = self
+            .bundler_rpc_client
+            .pull_mission()
+            .await?
+            .json::<BundlerRpcData>()
+            .await?;
+        batch_of(bundler_rpc_data)
+    }
+
+    /// pull the batch of `block_number` from the bundler, None if there is no
+    /// such batch
+    pub async fn fetch_block_batch(
+        &self,
+        block_number: u64,
+    ) -> Result<Option<BundlerRpcResult>, ManagerError> {
+        let bundler_rpc_data = self
+            .bundler_rpc_client
+            .pull_block_mission(block_number)
+            .await?
+            .json::<BundlerRpcData>()
+            .await?;
+        batch_of(bundler_rpc_data)
+    }
+
+    pub async fn execute_mission(&mut self) -> Result<(), ManagerError> {
+        // let bundler_rpc_data = MOCK_RPC_TXS.clone();
+        let result_data = match self.fetch_batch().await? {
             Some(result) => result,
             _ => {
-                println!("no mission");
+                log::debug!("no mission");
                 return Ok(());
             }
         };
 
-        println!(
+        log::debug!(
             "mission result {}",
             serde_json::to_string(&result_data).unwrap()
         );
+        if !result_data.verify_batch_hash() {
+            log::error!(
+                "batch hash {:?} unmatch the tx list, reject the mission",
+                result_data.batch_hash
+            );
+            return Err(ManagerError::BatchHash(result_data.batch_hash));
+        }
         let batch_hash = result_data.batch_hash;
         let tx_list = result_data.tx_list;
         let status = result_data.status;
 
-        let (proof, instances) = self.generate_proof(&tx_list).unwrap();
+        let (proof, instances, mpt_root_after) = self.prove_batch(&tx_list).map_err(|e| {
+            log::error!("generate proof error: {}", e);
+            e
+        })?;
 
         let instances_vec = instances.into_iter().flatten().collect::<Vec<Fp>>();
 
@@ -165,14 +306,13 @@ impl Manager {
         let push_result_data = match push_result {
             Ok(result) => result,
             Err(e) => {
-                println!("push result error: {}", e);
-                return Err(e);
+                log::error!("push result error: {}", e);
+                return Err(e.into());
             }
         };
-        println!(
-            "push task_id {:?} batch_hash{:?} result success",
-            task_id, batch_hash
-        );
+        log::info!("push batch_hash{:?} result success", batch_hash);
+
+        self.commit_root(mpt_root_after)?;
 
         // let push_result = hex::encode(push_result_data.bytes().await.unwrap());
         // println!("push_result_data {:?}", push_result);
@@ -182,19 +322,94 @@ impl Manager {
 
     pub fn generate_proof(
         &self,
-        tx_list: &Vec<BundlerRpcTxData>,
-    ) -> Result<(Vec<u8>, Vec<Vec<Fp>>), Error> {
-        let circuit = ZkProverCircuit::<Fp, 1>::default();
-        let instances = vec![vec![Fp::from(15)]];
+        tx_list: &[BundlerRpcTxData],
+    ) -> Result<(Vec<u8>, Vec<Vec<Fp>>), ManagerError> {
+        self.prove_batch(tx_list)
+            .map(|(proof, instances, _)| (proof, instances))
+    }
+
+    /// prove the batch, and return the proof, its instances and the state
+    /// root after the batch the proof is made for
+    fn prove_batch(
+        &self,
+        tx_list: &[BundlerRpcTxData],
+    ) -> Result<(Vec<u8>, Vec<Vec<Fp>>, H256), ManagerError> {
+        let circuit = self.prover_circuit(tx_list)?;
+        let mpt_root_after = circuit.mpt_root_after;
+        let instances = circuit.instances();
 
-        let proof_bytes = gen_proof(&self.params, &self.proving_key, circuit, instances.clone());
+        let proof_bytes = gen_proof(&self.params, &self.proving_key, circuit, instances.clone())?;
         // santiy check
         evm_verify(
             self.verifier_code.clone(),
             instances.clone(),
             proof_bytes.clone(),
+        )?;
+
+        Ok((proof_bytes, instances, mpt_root_after))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+    use halo2_proofs::dev::MockProver;
+
+    use super::{batch_circuit, batch_of, ManagerError, TX_NUM};
+    use crate::{test_utils::Fp, zkprover_circuit::MOCK_RPC_TXS, ERC4337::bundler::BundlerRpcData};
+
+    #[test]
+    fn test_batch_circuit() {
+        let tx_list = MOCK_RPC_TXS.clone().result.unwrap().tx_list;
+        let root = H256::repeat_byte(7);
+
+        let circuit = batch_circuit(&tx_list[..1], root).unwrap();
+        assert_eq!(circuit.txs.len(), TX_NUM);
+        assert_eq!(circuit.txs[0].r, tx_list[0].r);
+        assert_eq!(
+            (circuit.mpt_root_before, circuit.mpt_root_after),
+            (root, root)
         );
+        let prover = MockProver::<Fp>::run(7, &circuit, circuit.instances()).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let empty = batch_circuit(&[], root).unwrap();
+        assert!(empty.txs.iter().all(|tx| tx.is_padding()));
+
+        let over = vec![tx_list[0].clone(); TX_NUM + 1];
+        assert!(matches!(
+            batch_circuit(&over, root),
+            Err(ManagerError::Capacity(n)) if n == TX_NUM + 1
+        ));
+    }
+
+    #[test]
+    fn test_batch_of_rpc_error() {
+        let data = serde_json::from_str::<BundlerRpcData>(
+            r#"{
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": {
+                    "code": -32602,
+                    "message": "invalid params",
+                    "data": {"reason": "batch not found"}
+                }
+            }"#,
+        )
+        .unwrap();
+        let err = batch_of(data).unwrap_err();
+        assert_eq!(err.to_string(), "bundler rpc error -32602: invalid params");
+        match err {
+            ManagerError::Rpc(err) => {
+                assert_eq!(err.code, -32602);
+                assert_eq!(err.data.unwrap()["reason"], "batch not found");
+            }
+            other => panic!("expect rpc error, got {other:?}"),
+        }
 
-        Ok((proof_bytes, instances))
+        let data =
+            serde_json::from_str::<BundlerRpcData>(r#"{"jsonrpc":"2.0","id":1,"result":null}"#)
+                .unwrap();
+        assert!(batch_of(data).unwrap().is_none());
     }
 }
@@ -1,28 +1,91 @@
-use std::{cmp::Ordering, hash::Hash};
+use std::{cmp::Ordering, collections::HashMap, hash::Hash};
 
-use crate::gadgets::mpt::{MPTPath, SingleOp};
+/// the storage key / value type shared by operations and gadgets
+pub use crate::gadgets::kv_util::KeyValue;
+use crate::gadgets::{
+    mpt::{empty_root, MPTPath, SingleOp},
+    u256_to_fp,
+};
 
 use super::serde;
-use halo2_proofs::arithmetic::FieldExt;
+use crate::state_trie::StateTrie;
+use ethers::types::U256;
 pub use halo2_proofs::halo2curves::bn256::Fr;
+use halo2_proofs::{arithmetic::FieldExt, halo2curves::group::ff::PrimeField};
 use hash_circuit::{hash, Hashable};
-use num_bigint::{BigInt, BigUint};
-use num_traits::Num;
+use lazy_static::lazy_static;
+use num_bigint::BigUint;
+
+lazy_static! {
+    /// the padding record of `AccountOp::<Fr>::padding_hash`, the hash of
+    /// `[0, 0]` is only calculated once
+    pub static ref PADDING_HASH: (Fr, Fr, Fr) = AccountOp::<Fr>::padding_hash();
+}
+
+/// rows reserved for the account trie part of an op without account path
+pub const ACCOUNT_ROWS_WITHOUT_PATH: usize = 10;
 
 /// Represent an account operation in MPT
 #[derive(Clone, Debug, Default)]
 pub struct AccountOp<Fp: FieldExt> {
-    /// the operation on the account trie (first layer)
-    // pub acc_trie: SingleOp<Fp>,
-    // /// the operation on the state trie (second layer)
-    // pub state_trie: Option<SingleOp<Fp>>,
+    /// the operation on the account trie (first layer), absent when the
+    /// trace carries no account path
+    pub acc_trie: Option<SingleOp<Fp>>,
+    /// the operation on the state trie (second layer)
+    pub state_trie: Option<SingleOp<Fp>>,
     /// the state before updating in account
     pub account_before: Account<Fp>,
     /// the state after updating in account
     pub account_after: Account<Fp>,
+    /// the key of storage slot, only for storage op
+    pub store_key: Option<KeyValue<Fp>>,
+    /// the value of storage slot before updating
+    pub store_before: Option<KeyValue<Fp>>,
+    /// the value of storage slot after updating
+    pub store_after: Option<KeyValue<Fp>>,
+    /// the storage root shared by both states when the op has no state path,
+    /// which is taken as the state root of the accounts
+    pub common_state_root: Option<Fp>,
+}
+
+/// ops are compared by what they commit to: the account hashes and roots of
+/// both states, the account and state trie roots and the storage slots; hash
+/// traces are expected to be completed
+impl<Fp: FieldExt> PartialEq for AccountOp<Fp> {
+    fn eq(&self, other: &Self) -> bool {
+        let trie_roots = |trie: &Option<SingleOp<Fp>>| {
+            trie.as_ref()
+                .map(|op| (op.key, op.start_root(), op.new_root()))
+        };
+        self.account_before.account_hash() == other.account_before.account_hash()
+            && self.account_after.account_hash() == other.account_after.account_hash()
+            && self.account_root_before() == other.account_root_before()
+            && self.account_root_after() == other.account_root_after()
+            && trie_roots(&self.acc_trie) == trie_roots(&other.acc_trie)
+            && trie_roots(&self.state_trie) == trie_roots(&other.state_trie)
+            && self.store_key == other.store_key
+            && self.store_before == other.store_before
+            && self.store_after == other.store_after
+    }
 }
 
+impl<Fp: FieldExt> Eq for AccountOp<Fp> {}
+
 impl<Fp: FieldExt> AccountOp<Fp> {
+    /// the op changes nothing: both account states hash the same, and the
+    /// account trie, state trie and storage slot (if any) are not updated
+    pub fn is_noop(&self) -> bool {
+        let unchanged = |trie: &Option<SingleOp<Fp>>| {
+            trie.as_ref()
+                .map_or(true, |op| op.start_root() == op.new_root())
+        };
+        self.account_before.account_hash() == self.account_after.account_hash()
+            && self.account_root_before() == self.account_root_after()
+            && unchanged(&self.acc_trie)
+            && unchanged(&self.state_trie)
+            && self.store_before == self.store_after
+    }
+
     /// the root of account trie before operation
     pub fn account_root_before(&self) -> Fp {
         self.account_before.state_root
@@ -32,15 +95,127 @@ impl<Fp: FieldExt> AccountOp<Fp> {
         self.account_after.state_root
     }
 
-    /// indicate rows would take in the account trie part
+    /// indicate rows would take in the account trie part, which follows the
+    /// depth of the account path, or `ACCOUNT_ROWS_WITHOUT_PATH` if the op has
+    /// no account path
     pub fn use_rows_trie_account(&self) -> usize {
-        // TODO: should update SingleOp
-        10
+        self.acc_trie
+            .as_ref()
+            .map_or(ACCOUNT_ROWS_WITHOUT_PATH, |s| s.use_rows())
+    }
+
+    /// indicate rows would take in the state trie part
+    pub fn use_rows_trie_state(&self) -> usize {
+        self.state_trie.as_ref().map_or(0, |s| s.use_rows())
+    }
+
+    /// indicate rows would take for the storage key and values, one row for
+    /// the value before and one for after
+    pub fn use_rows_trie_kv(&self) -> usize {
+        if self.store_key.is_some() {
+            2
+        } else {
+            0
+        }
+    }
+
+    /// indicate rows would take for the whole op
+    pub fn use_rows(&self) -> usize {
+        self.use_rows_trie_account() + self.use_rows_trie_state() + self.use_rows_trie_kv()
+    }
+
+    /// canonical binary encoding of the op, field elements are encoded as
+    /// 32-byte little-endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.account_before.write_bytes(&mut buf);
+        self.account_after.write_bytes(&mut buf);
+        match &self.state_trie {
+            Some(op) => {
+                buf.push(1);
+                op.write_bytes(&mut buf);
+            }
+            None => buf.push(0),
+        }
+        for kv in [&self.store_key, &self.store_before, &self.store_after] {
+            match kv {
+                Some(kv) => {
+                    buf.push(1);
+                    kv.write_bytes(&mut buf);
+                }
+                None => buf.push(0),
+            }
+        }
+        match &self.common_state_root {
+            Some(root) => {
+                buf.push(1);
+                write_fp(&mut buf, root);
+            }
+            None => buf.push(0),
+        }
+        match &self.acc_trie {
+            Some(op) => {
+                buf.push(1);
+                op.write_bytes(&mut buf);
+            }
+            None => buf.push(0),
+        }
+        buf
+    }
+
+    /// decode the op from the encoding of `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TraceError> {
+        let mut cursor = bytes;
+        let account_before = Account::read_bytes(&mut cursor)?;
+        let account_after = Account::read_bytes(&mut cursor)?;
+        let state_trie = read_opt(&mut cursor, SingleOp::read_bytes)?;
+        let store_key = read_opt(&mut cursor, KeyValue::read_bytes)?;
+        let store_before = read_opt(&mut cursor, KeyValue::read_bytes)?;
+        let store_after = read_opt(&mut cursor, KeyValue::read_bytes)?;
+        let common_state_root = read_opt(&mut cursor, read_fp)?;
+        let acc_trie = read_opt(&mut cursor, SingleOp::read_bytes)?;
+        expect_consumed(cursor)?;
+        Ok(Self {
+            acc_trie,
+            state_trie,
+            account_before,
+            account_after,
+            store_key,
+            store_before,
+            store_after,
+            common_state_root,
+        })
     }
 }
 
 impl<Fp: Hashable> AccountOp<Fp> {
-    /// providing the padding record for hash table
+    /// recompute the hashes of both account states natively and check they
+    /// are consistent with the cached traces and the stored roots, so a
+    /// batch can be sanity-checked before spending proving time
+    pub fn verify_transition(&self) -> Result<(), TraceError> {
+        self.account_before
+            .verify_traces()
+            .map_err(|e| e.context("account before"))?;
+        self.account_after
+            .verify_traces()
+            .map_err(|e| e.context("account after"))?;
+
+        if self.account_before.address != self.account_after.address {
+            return Err(TraceError::DataErr(
+                "address changed in account op".to_string(),
+            ));
+        }
+        if self.account_before.account_key != self.account_after.account_key {
+            return Err(TraceError::DataErr(
+                "account key changed in account op".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// providing the padding record for hash table, calculate it once for
+    /// all padding rows, or use `PADDING_HASH` for `Fr`
     pub fn padding_hash() -> (Fp, Fp, Fp) {
         (
             Fp::zero(),
@@ -49,21 +224,25 @@ impl<Fp: Hashable> AccountOp<Fp> {
         )
     }
 
-    // pub fn hash_traces(&self) -> impl Iterator<Item = &(Fp, Fp, Fp)> + Clone {
-    //     self.acc_trie
-    //         .hash_traces()
-    //         .chain(self.state_trie.iter().flat_map(|i| i.hash_traces()))
-    //         .chain(
-    //             self.account_before
-    //                 .iter()
-    //                 .flat_map(|i| i.hash_traces.iter()),
-    //         )
-    //         .chain(self.account_after.iter().flat_map(|i| i.hash_traces.iter()))
-    //         .chain(Some(self.address_rep.hash_traces()))
-    //         .chain(self.store_key.as_ref().map(|v| v.hash_traces()))
-    //         .chain(self.store_before.as_ref().map(|v| v.hash_traces()))
-    //         .chain(self.store_after.as_ref().map(|v| v.hash_traces()))
-    // }
+    /// all the hash traces of the op: the account trie, the state trie, both
+    /// account states and the storage slots, duplicates are not removed
+    pub fn hash_traces(&self) -> impl Iterator<Item = &(Fp, Fp, Fp)> + Clone {
+        self.acc_trie
+            .iter()
+            .flat_map(|t| t.hash_traces())
+            .chain(self.state_trie.iter().flat_map(|i| i.hash_traces()))
+            .chain(self.account_before.hash_traces.iter())
+            .chain(self.account_after.hash_traces.iter())
+            .chain(self.store_key.as_ref().map(|v| v.hash_traces()))
+            .chain(self.store_before.as_ref().map(|v| v.hash_traces()))
+            .chain(self.store_after.as_ref().map(|v| v.hash_traces()))
+    }
+
+    /// the distinct poseidon invocations of the op, deduplicated as
+    /// `HashTracesSrc` does, which is the calcs its hash circuit needs
+    pub fn num_hash_calcs(&self) -> usize {
+        HashTracesSrc::from(self.hash_traces()).count()
+    }
 }
 /// Represent for a zkProver account
 #[derive(Clone, Debug, Default)]
@@ -122,7 +301,31 @@ impl<Fp: FieldExt> Account<Fp> {
 
         self
     }
-    /// complete
+
+    /// split a 256-bit code hash into the (hi, lo) limbs, which hold its first
+    /// and last 16 bytes in big-endian
+    pub fn code_hash_limbs(code_hash: U256) -> (Fp, Fp) {
+        (
+            Fp::from_u128((code_hash >> 128).low_u128()),
+            Fp::from_u128(code_hash.low_u128()),
+        )
+    }
+
+    /// recover the code hash from its (hi, lo) limbs, the inverse of
+    /// `code_hash_limbs`, none if any limb exceeds 128 bits
+    pub fn code_hash_from_limbs((hi, lo): (Fp, Fp)) -> Option<U256> {
+        let to_u128 = |limb: Fp| {
+            let repr = limb.to_repr();
+            let (low, high) = repr.as_ref().split_at(16);
+            high.iter()
+                .all(|b| *b == 0)
+                .then(|| u128::from_le_bytes(low.try_into().expect("16 bytes")))
+        };
+        Some((U256::from(to_u128(hi)?) << 128) | U256::from(to_u128(lo)?))
+    }
+
+    /// complete, the traces are calculated if there is no cached one, cached
+    /// traces are kept as is, see `complete_verified` for checking them
     pub(crate) fn complete(self, hasher: impl FnMut(&Fp, &Fp) -> Fp) -> Self {
         if self.hash_traces.is_empty() {
             self.trace(hasher)
@@ -131,6 +334,87 @@ impl<Fp: FieldExt> Account<Fp> {
         }
     }
 
+    /// complete like `complete`, but the cached traces are recalculated by
+    /// hasher and must match, as well as the account key and the recursive
+    /// tx hash derived from them
+    pub fn complete_verified(self, hasher: impl FnMut(&Fp, &Fp) -> Fp) -> Result<Self, TraceError> {
+        if self.hash_traces.is_empty() {
+            return Ok(self.trace(hasher));
+        }
+
+        let expected = Self {
+            hash_traces: Vec::new(),
+            ..self.clone()
+        }
+        .trace(hasher);
+
+        if expected.hash_traces != self.hash_traces {
+            return Err(TraceError::DataErr(format!(
+                "unmatch account hash, expect {:?} but got {:?}",
+                expected.account_hash(),
+                self.account_hash()
+            )));
+        }
+        if expected.account_key != self.account_key
+            || expected.recrusive_tx_hash != self.recrusive_tx_hash
+        {
+            return Err(TraceError::DataErr(
+                "account fields unmatch the hash traces".to_string(),
+            ));
+        }
+
+        Ok(self)
+    }
+
+    /// build the account from its fields and the six traces calculated by
+    /// `trace` without hashing again, the account key and the recursive tx
+    /// hash are taken from the traces. only the layout is checked (see
+    /// `check_traces`): the final hash, whose second input is the hash of
+    /// the account key and pub key, is trusted, use `verify_traces` for it
+    pub fn from_traces(fields: Self, traces: Vec<(Fp, Fp, Fp)>) -> Result<Self, TraceError> {
+        fields.check_traces(&traces)?;
+        Ok(Self {
+            account_key: traces[0].2,
+            recrusive_tx_hash: traces[1].2,
+            hash_traces: traces,
+            ..fields
+        })
+    }
+
+    /// check the traces are in the layout of `trace`: the inputs are the
+    /// fields of account and each output feeds the next hash, the hashes
+    /// themselves are not recalculated (see `verify_traces`)
+    fn check_traces(&self, traces: &[(Fp, Fp, Fp)]) -> Result<(), TraceError> {
+        let [key, tx, h2, h3, h4, h_final]: &[_; 6] = traces.try_into().map_err(|_| {
+            TraceError::DataErr(format!(
+                "expect 6 account hash traces, got {}",
+                traces.len()
+            ))
+        })?;
+        let linked = [
+            (key.0, self.address),
+            (key.1, Fp::zero()),
+            (tx.0, self.pre_recrusive_tx_hash),
+            (tx.1, self.tx_hash),
+            (h2.0, self.nonce),
+            (h2.1, self.gas_balance),
+            (h3.0, tx.2),
+            (h3.1, self.state_root),
+            (h4.0, h3.2),
+            (h4.1, h2.2),
+            (h_final.0, h4.2),
+        ];
+        match linked
+            .iter()
+            .position(|(traced, expected)| traced != expected)
+        {
+            Some(i) => Err(TraceError::DataErr(format!(
+                "account hash traces inconsistent at input {i}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
     /// access the cached traces for calculated all hashes required in obtain
     /// the account hash there is totally 4 of them and the last one
     /// calculate the final hash
@@ -152,9 +436,80 @@ impl<Fp: FieldExt> Account<Fp> {
             self.hash_traces[len - 1].2
         }
     }
+
+    pub(crate) fn write_bytes(&self, buf: &mut Vec<u8>) {
+        for fp in [
+            &self.address,
+            &self.pub_key,
+            &self.account_key,
+            &self.gas_balance,
+            &self.nonce,
+            &self.recrusive_tx_hash,
+            &self.state_root,
+            &self.tx_hash,
+            &self.pre_recrusive_tx_hash,
+        ] {
+            write_fp(buf, fp);
+        }
+        write_traces(buf, &self.hash_traces);
+    }
+
+    pub(crate) fn read_bytes(bytes: &mut &[u8]) -> Result<Self, TraceError> {
+        Ok(Self {
+            address: read_fp(bytes)?,
+            pub_key: read_fp(bytes)?,
+            account_key: read_fp(bytes)?,
+            gas_balance: read_fp(bytes)?,
+            nonce: read_fp(bytes)?,
+            recrusive_tx_hash: read_fp(bytes)?,
+            state_root: read_fp(bytes)?,
+            tx_hash: read_fp(bytes)?,
+            pre_recrusive_tx_hash: read_fp(bytes)?,
+            hash_traces: read_traces(bytes)?,
+        })
+    }
 }
 
 impl<Fp: Hashable> Account<Fp> {
+    /// recompute all the hash traces of account and compare with the cached
+    /// ones, which also cover the account key and the state root
+    pub fn verify_traces(&self) -> Result<(), TraceError> {
+        if self.hash_traces.is_empty() {
+            return Err(TraceError::DataErr("account is not traced".to_string()));
+        }
+
+        self.clone()
+            .complete_verified(|a, b| <Fp as Hashable>::hash([*a, *b]))
+            .map(|_| ())
+    }
+
+    /// replace the state root and recalculate the traces if it is changed
+    pub(crate) fn with_state_root(self, state_root: Fp) -> Self {
+        if state_root == self.state_root && !self.hash_traces.is_empty() {
+            return self;
+        }
+        Self {
+            state_root,
+            hash_traces: Vec::new(),
+            ..self
+        }
+        .trace(|a, b| <Fp as Hashable>::hash([*a, *b]))
+    }
+
+    /// the key of account in trie, derived from the address in the same way
+    /// as `trace`
+    pub fn key_from_address(address: Fp) -> Fp {
+        <Fp as Hashable>::hash([address, Fp::zero()])
+    }
+
+    /// chain `hashes` onto `prev` in order, each step is the
+    /// `recrusive_tx_hash = hash(pre_recrusive_tx_hash, tx_hash)` of `trace`
+    pub fn fold_tx_hashes(prev: Fp, hashes: &[Fp]) -> Fp {
+        hashes
+            .iter()
+            .fold(prev, |acc, tx_hash| <Fp as Hashable>::hash([acc, *tx_hash]))
+    }
+
     pub fn create(
         nonce: Fp,
         gas_balance: Fp,
@@ -172,17 +527,126 @@ impl<Fp: Hashable> Account<Fp> {
         };
         init.trace(|a, b| <Fp as Hashable>::hash([*a, *b]))
     }
+
+    /// build a completed account from a snapshot (e.g. of `eth_getProof`),
+    /// a `gas_balance` not less than the modulus is rejected
+    pub fn from_snapshot(
+        address: Fp,
+        nonce: u64,
+        gas_balance: U256,
+        tx_hash: Fp,
+        pre_recrusive_tx_hash: Fp,
+        state_root: Fp,
+    ) -> Result<Self, TraceError> {
+        let mut bytes = [0u8; 32];
+        gas_balance.to_big_endian(&mut bytes);
+        let acc = Self {
+            address,
+            nonce: Fp::from(nonce),
+            gas_balance: biguint_to_fp(&BigUint::from_bytes_be(&bytes))?,
+            tx_hash,
+            pre_recrusive_tx_hash,
+            state_root,
+            ..Default::default()
+        };
+        Ok(acc.complete(|a, b| <Fp as Hashable>::hash([*a, *b])))
+    }
 }
 
 /// include error raised in deserialize or data verification
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum TraceError {
+    #[error("deserialize error: {0}")]
     /// error in deserialize
-    DeErr(std::io::Error),
+    DeErr(#[source] std::io::Error),
+    #[error("malformed data: {0}")]
     /// error for malform data
     DataErr(String),
 }
 
+/// whether a key can be decomposed into `layers` path bits
+pub fn can_decompose<Fp: FieldExt>(layers: usize) -> bool {
+    (layers as u32) * 8 < Fp::NUM_BITS
+}
+
+/// reject `layers` which can not be decomposed from a key, see
+/// `can_decompose`
+pub(crate) fn check_decompose<Fp: FieldExt>(layers: usize) -> Result<(), TraceError> {
+    if can_decompose::<Fp>(layers) {
+        Ok(())
+    } else {
+        Err(TraceError::DataErr(format!(
+            "not able to decompose key into {layers} layers, path is too deep"
+        )))
+    }
+}
+
+/// take the lowest bit of key as path bit and shift the rest right, without
+/// branching on the bit
+pub(crate) fn shift_key<Fp: FieldExt>(key: Fp) -> (bool, Fp) {
+    let invert_2 = Fp::one().double().invert().unwrap();
+    let odd = key.is_odd();
+    let low = Fp::conditional_select(&Fp::zero(), &Fp::one(), odd);
+    (bool::from(odd), (key - low) * invert_2)
+}
+
+/// decompose the lowest `layers` bits of key into path bits (from the root
+/// to the leaf), along with the residual part of key
+pub fn decompose_key<Fp: FieldExt>(key: Fp, layers: usize) -> (Vec<bool>, Fp) {
+    assert!(
+        can_decompose::<Fp>(layers),
+        "not able to decompose more than bits"
+    );
+    let mut path = Vec::with_capacity(layers);
+    let mut residual = key;
+    for _ in 0..layers {
+        let (bit, rest) = shift_key(residual);
+        path.push(bit);
+        residual = rest;
+    }
+    (path, residual)
+}
+
+/// obtain the account key in trie from the address
+pub fn address_to_key<Fp: Hashable>(address: Fp) -> Fp {
+    Account::key_from_address(address)
+}
+
+/// the account hash of the fields, as `Account::account_hash` of the traced
+/// account, without collecting the hash traces
+pub fn account_hash<Fp: Hashable>(
+    nonce: Fp,
+    gas_balance: Fp,
+    recrusive_tx_hash: Fp,
+    state_root: Fp,
+    account_key: Fp,
+    pub_key: Fp,
+) -> Fp {
+    let hash = |a: Fp, b: Fp| <Fp as Hashable>::hash([a, b]);
+    let h1 = hash(account_key, pub_key);
+    let h2 = hash(nonce, gas_balance);
+    let h3 = hash(recrusive_tx_hash, state_root);
+    let h4 = hash(h3, h2);
+    hash(h4, h1)
+}
+
+/// the inverse of `decompose_key`
+pub fn recover_key<Fp: FieldExt>(path: &[bool], residual: Fp) -> Fp {
+    path.iter().rev().fold(residual, |acc, bit| {
+        acc.double() + if *bit { Fp::one() } else { Fp::zero() }
+    })
+}
+
+impl TraceError {
+    /// prefix the message of a data error, deserialize errors are kept as is
+    pub(crate) fn context(self, ctx: impl std::fmt::Display) -> Self {
+        match self {
+            TraceError::DataErr(msg) => TraceError::DataErr(format!("{ctx}: {msg}")),
+            e => e,
+        }
+    }
+}
+
 fn bytes_to_fp<Fp: FieldExt>(mut bt: Vec<u8>) -> std::io::Result<Fp> {
     // let expected_size = Fp::NUM_BITS as usize / 8 + if Fp::NUM_BITS % 8 == 0 { 0
     // } else { 1 };
@@ -194,6 +658,93 @@ fn bytes_to_fp<Fp: FieldExt>(mut bt: Vec<u8>) -> std::io::Result<Fp> {
     Ok(Fp::from_bytes_wide(&arr))
 }
 
+fn take_bytes<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], TraceError> {
+    if bytes.len() < n {
+        return Err(TraceError::DataErr(format!(
+            "unexpected end of data, require {} bytes but only {} left",
+            n,
+            bytes.len()
+        )));
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+/// append a field element as its 32-byte little-endian repr
+pub(crate) fn write_fp<Fp: FieldExt>(buf: &mut Vec<u8>, fp: &Fp) {
+    buf.extend_from_slice(fp.to_repr().as_ref());
+}
+
+/// read a field element written by `write_fp` and advance the cursor
+pub(crate) fn read_fp<Fp: FieldExt>(bytes: &mut &[u8]) -> Result<Fp, TraceError> {
+    let mut repr = <Fp as PrimeField>::Repr::default();
+    let n = repr.as_ref().len();
+    repr.as_mut().copy_from_slice(take_bytes(bytes, n)?);
+    Option::from(Fp::from_repr(repr))
+        .ok_or_else(|| TraceError::DataErr("non-canonical field element".to_string()))
+}
+
+/// length prefix in 4-byte little-endian
+pub(crate) fn write_len(buf: &mut Vec<u8>, len: usize) {
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+pub(crate) fn read_len(bytes: &mut &[u8]) -> Result<usize, TraceError> {
+    let len: [u8; 4] = take_bytes(bytes, 4)?.try_into().expect("same size");
+    Ok(u32::from_le_bytes(len) as usize)
+}
+
+pub(crate) fn write_fps<Fp: FieldExt>(buf: &mut Vec<u8>, fps: &[Fp]) {
+    write_len(buf, fps.len());
+    fps.iter().for_each(|fp| write_fp(buf, fp));
+}
+
+pub(crate) fn read_fps<Fp: FieldExt>(bytes: &mut &[u8]) -> Result<Vec<Fp>, TraceError> {
+    (0..read_len(bytes)?).map(|_| read_fp(bytes)).collect()
+}
+
+pub(crate) fn write_traces<Fp: FieldExt>(buf: &mut Vec<u8>, traces: &[(Fp, Fp, Fp)]) {
+    write_len(buf, traces.len());
+    for (a, b, c) in traces {
+        write_fp(buf, a);
+        write_fp(buf, b);
+        write_fp(buf, c);
+    }
+}
+
+pub(crate) fn read_traces<Fp: FieldExt>(
+    bytes: &mut &[u8],
+) -> Result<Vec<(Fp, Fp, Fp)>, TraceError> {
+    (0..read_len(bytes)?)
+        .map(|_| Ok((read_fp(bytes)?, read_fp(bytes)?, read_fp(bytes)?)))
+        .collect()
+}
+
+/// read an optional item prefixed by tag 0 (none) or 1 (some)
+fn read_opt<T>(
+    bytes: &mut &[u8],
+    read: impl FnOnce(&mut &[u8]) -> Result<T, TraceError>,
+) -> Result<Option<T>, TraceError> {
+    match take_bytes(bytes, 1)?[0] {
+        0 => Ok(None),
+        1 => read(bytes).map(Some),
+        tag => Err(TraceError::DataErr(format!("invalid option tag {tag}"))),
+    }
+}
+
+/// fail if there is any trailing data after decoding
+pub(crate) fn expect_consumed(bytes: &[u8]) -> Result<(), TraceError> {
+    if bytes.is_empty() {
+        Ok(())
+    } else {
+        Err(TraceError::DataErr(format!(
+            "{} trailing bytes after decoding",
+            bytes.len()
+        )))
+    }
+}
+
 /// Represent an account operation in MPT
 #[derive(Debug, Default, Clone)]
 pub struct FpStruct<Fp> {
@@ -202,28 +753,45 @@ pub struct FpStruct<Fp> {
 
 impl<'d, Fp: Hashable> From<&'d serde::Hash> for (Fp,) {
     fn from(data: &'d serde::Hash) -> Self {
-        let hash_str = data.hex();
-        let hash_int = BigInt::from_str_radix(&hash_str.trim_start_matches("0x"), 16)
-            .unwrap()
-            .to_string();
-        // let hash_int = BigInt::from_str
-        let hash_fp: Fp = Fp::from_str_vartime(&hash_int).unwrap();
-
-        (hash_fp,)
+        (u256_to_fp(U256::from_big_endian(&data.0)).unwrap(),)
     }
 }
 
 impl<'d, Fp: Hashable> From<&'d serde::Address> for (Fp,) {
     fn from(data: &'d serde::Address) -> Self {
-        let hash_str = data.hex();
-        let hash_int = BigInt::from_str_radix(&hash_str.trim_start_matches("0x"), 16)
-            .unwrap()
-            .to_string();
-        // let hash_int = BigInt::from_str
-        let hash_fp: Fp = Fp::from_str_vartime(&hash_int).unwrap();
+        (pack_address(&data.0).expect("address has 20 bytes"),)
+    }
+}
+
+/// pack the 20-byte address in big-endian into field as
+/// `first_16 * 2^32 + last_4`, which is also how the address limbs are
+/// recombined in the account gadget; other lengths are rejected
+pub fn pack_address<Fp: FieldExt>(bytes: &[u8]) -> Result<Fp, TraceError> {
+    if bytes.len() != 20 {
+        return Err(TraceError::DataErr(format!(
+            "address should be 20 bytes, got {}",
+            bytes.len()
+        )));
+    }
+    let (first_16, last_4) = bytes.split_at(16);
+    Ok(
+        Fp::from_u128(u128::from_be_bytes(first_16.try_into().expect("16 bytes")))
+            * Fp::from(0x100000000u64)
+            + Fp::from(u32::from_be_bytes(last_4.try_into().expect("4 bytes")) as u64),
+    )
+}
 
-        (hash_fp,)
+/// convert the amount in trace into field, the ones not less than the modulus
+/// are rejected instead of being reduced, which would change the amount
+fn biguint_to_fp<Fp: FieldExt>(v: &BigUint) -> Result<Fp, TraceError> {
+    let out_of_field = || TraceError::DataErr(format!("amount {v} exceeds the field modulus"));
+    let bytes = v.to_bytes_le();
+    let mut repr = <Fp as PrimeField>::Repr::default();
+    if bytes.len() > repr.as_ref().len() {
+        return Err(out_of_field());
     }
+    repr.as_mut()[..bytes.len()].copy_from_slice(&bytes);
+    Option::from(Fp::from_repr(repr)).ok_or_else(out_of_field)
 }
 
 impl<'d, Fp: Hashable>
@@ -246,8 +814,7 @@ impl<'d, Fp: Hashable>
     ) -> Result<Self, Self::Error> {
         let (account_data, address, account_key, pub_key) = acc_trace;
         let nonce = Fp::from(account_data.nonce);
-        let gas_balance =
-            bytes_to_fp(account_data.gas_balance.to_bytes_le()).map_err(TraceError::DeErr)?;
+        let gas_balance = biguint_to_fp(&account_data.gas_balance)?;
         let (pre_recrusive_tx_hash,) = <(Fp,)>::from(&account_data.pre_recrusive_tx_hash);
         let (address,) = <(Fp,)>::from(address);
         let (pub_key,) = <(Fp,)>::from(pub_key);
@@ -268,7 +835,7 @@ impl<'d, Fp: Hashable>
             tx_hash,
             pre_recrusive_tx_hash,
             recrusive_tx_hash: Fp::zero(),
-            state_root: Fp::zero(),
+            state_root: empty_root(),
             hash_traces: vec![],
         };
         Ok(acc.complete(|a, b| <Fp as Hashable>::hash([*a, *b])))
@@ -282,6 +849,7 @@ struct SMTPathParse<Fp: FieldExt>(MPTPath<Fp>, Vec<Fp>, Vec<Fp>);
 impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTPath> for SMTPathParse<Fp> {
     type Error = TraceError;
     fn try_from(path_trace: &'d serde::SMTPath) -> Result<Self, Self::Error> {
+        check_decompose::<Fp>(path_trace.path.len())?;
         let mut siblings: Vec<Fp> = Vec::new();
         for n in &path_trace.path {
             let s = Fp::from_bytes_wide(&n.sibling.cast());
@@ -308,7 +876,11 @@ impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTPath> for SMTPathParse<Fp> {
         let mpt_path = MPTPath::create(&path_bits, &siblings, key, leaf);
         // sanity check
         let root = Fp::from_bytes_wide(&path_trace.root.cast());
-        assert_eq!(root, mpt_path.root());
+        if root != mpt_path.root() {
+            return Err(TraceError::DataErr(
+                "root of path trace mismatch with the calculated one".to_string(),
+            ));
+        }
 
         Ok(SMTPathParse(mpt_path, siblings, path))
     }
@@ -367,28 +939,18 @@ impl<'d, Fp: Hashable> TryFrom<(&'d serde::SMTPath, &'d serde::SMTPath, serde::H
 
                     if let Some(another_key) = old.key() {
                         // we need to make full path extension for both side, manually
-                        let invert_2 = Fp::one().double().invert().unwrap();
-                        let mut k1 = another_key;
-                        let mut k2 = key;
+                        let mut k1 = shift_key(another_key);
+                        let mut k2 = shift_key(key);
                         let mut common_prefix_depth: usize = 0;
-                        let shiftr = |fp: Fp| {
-                            if fp.is_odd().unwrap_u8() == 1 {
-                                fp * invert_2 - invert_2
-                            } else {
-                                fp * invert_2
-                            }
-                        };
-                        let mut k2_bit = k2.is_odd().unwrap_u8();
-                        while k1.is_odd().unwrap_u8() == k2_bit {
-                            k1 = shiftr(k1);
-                            k2 = shiftr(k2);
+                        while k1.0 == k2.0 {
                             common_prefix_depth += 1;
                             if common_prefix_depth > path.len() {
-                                path.push(Fp::from(k2_bit as u64));
+                                path.push(Fp::from(k2.0 as u64));
                                 siblings.push(Fp::zero());
                             }
-                            assert_ne!(k1, k2);
-                            k2_bit = k2.is_odd().unwrap_u8();
+                            assert_ne!(k1.1, k2.1);
+                            k1 = shift_key(k1.1);
+                            k2 = shift_key(k2.1);
                         }
 
                         assert!(common_prefix_depth >= old.depth());
@@ -397,7 +959,7 @@ impl<'d, Fp: Hashable> TryFrom<(&'d serde::SMTPath, &'d serde::SMTPath, serde::H
                         old = old.extend(ext_dist, key);
                         new = new.extend(ext_dist, key);
 
-                        path.push(Fp::from(k2_bit as u64));
+                        path.push(Fp::from(k2.0 as u64));
                         siblings.push(last_node_hash);
                     }
 
@@ -422,6 +984,7 @@ impl<'d, Fp: Hashable> TryFrom<(&'d serde::SMTPath, &'d serde::SMTPath, serde::H
             }
         };
 
+        check_decompose::<Fp>(siblings.len())?;
         let mut key_i = BigUint::from_bytes_le(ref_key.start_read());
         key_i >>= siblings.len();
 
@@ -437,55 +1000,255 @@ impl<'d, Fp: Hashable> TryFrom<(&'d serde::SMTPath, &'d serde::SMTPath, serde::H
     }
 }
 
+/// read a root in the trace as the roots of `SMTPath` are read, which all the
+/// trace formats share for the account trie and state trie roots
+fn trie_root<Fp: Hashable>(root: &serde::Hash) -> Fp {
+    Fp::from_bytes_wide(&root.cast())
+}
+
 impl<'d, Fp: Hashable> TryFrom<&'d serde::MPTTransTrace> for AccountOp<Fp> {
     type Error = TraceError;
 
     fn try_from(trace: &'d serde::MPTTransTrace) -> Result<Self, Self::Error> {
-        // let acc_trie: SingleOp<Fp> = (
-        //    &trace.account_path_update.unwrap().old_account_state_path.unwrap(),
-        //    &trace.account_path_update.unwrap().new_account_state_path.unwrap(),
-        //    trace.account_key
-        // ).try_into().unwrap();
-
-        // let state_trie: Option<SingleOp<Fp>> =
-        //     if trace.state_path[0].is_some() && trace.state_path[1].is_some() {
-        //         Some(
-        //             (
-        //                 trace.state_path[0].as_ref().unwrap(),
-        //                 trace.state_path[1].as_ref().unwrap(),
-        //                 trace.state_key.unwrap(),
-        //             )
-        //                 .try_into()?,
-        //         )
-        //     } else {
-        //         None
-        //     };
+        let acc_trie = match &trace.account_path_update {
+            Some(serde::AccountPathUpdate {
+                old_account_state_path: Some(path_before),
+                new_account_state_path: Some(path_after),
+            }) => Some((path_before, path_after, trace.account_key).try_into()?),
+            _ => None,
+        };
+
+        let (state_trie, [store_key, store_before, store_after]) =
+            parse_state_update(&trace.state_path, &trace.state_key, &trace.state_update)?;
 
         let account_update = trace.account_update.as_ref().expect("msg");
         let address = &trace.address;
         let account_key = &trace.account_key;
         let pub_key = &trace.pub_key;
+        let common_state_root = trace.common_state_root.as_ref().map(trie_root);
+        // the root of the state path, or the common one if the path is absent
+        let state_root = |path: &Option<serde::SMTPath>| {
+            path.as_ref()
+                .map(|path| trie_root(&path.root))
+                .or(common_state_root)
+                .unwrap_or_else(empty_root)
+        };
+        let [state_before, state_after] = &trace.state_path;
+
         let account_before = {
             let account_data = account_update.old_account_state.as_ref().expect("msg");
             let account: Account<Fp> = (account_data, address, account_key, pub_key).try_into()?;
-            account
+            account.with_state_root(state_root(state_before))
         };
 
         let account_after = {
             let account_data = account_update.new_account_state.as_ref().expect("");
             let account: Account<Fp> = (account_data, address, account_key, pub_key).try_into()?;
-            account
+            account.with_state_root(state_root(state_after))
         };
 
         Ok(Self {
+            acc_trie,
+            state_trie,
             account_before,
             account_after,
+            store_key,
+            store_before,
+            store_after,
+            common_state_root,
+        })
+    }
+}
+
+/// convert the traces into ops in order, the conversions are independent and
+/// run concurrently with the `parallel` feature
+fn ops_of_traces<Fp: Hashable>(
+    traces: &[serde::MPTTransTrace],
+) -> Result<Vec<AccountOp<Fp>>, TraceError> {
+    let convert = |(i, trace): (usize, &serde::MPTTransTrace)| {
+        AccountOp::<Fp>::try_from(trace).map_err(|e| e.context(format!("trace {i}")))
+    };
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        traces.par_iter().enumerate().map(convert).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        traces.iter().enumerate().map(convert).collect()
+    }
+}
+
+/// the (before, after) roots of the account trie an op updates, from the
+/// `mptRootUpdate` of its trace, or the account paths the op is built from
+fn account_trie_roots<Fp: Hashable>(
+    trace: &serde::MPTTransTrace,
+    op: &AccountOp<Fp>,
+) -> Option<(Fp, Fp)> {
+    trace
+        .mpt_root_update
+        .as_ref()
+        .map(|update| (trie_root(&update.old_root), trie_root(&update.new_root)))
+        .or_else(|| {
+            op.acc_trie
+                .as_ref()
+                .map(|acc_trie| (acc_trie.start_root(), acc_trie.new_root()))
+        })
+}
+
+impl serde::BlockResult {
+    /// convert the traces into ops and build the state trie of them, the
+    /// account trie roots must be continued from `start_mpt_root` through
+    /// every op to `end_mpt_root`
+    pub fn state_trie<Fp: Hashable>(&self) -> Result<StateTrie<Fp>, TraceError> {
+        let start_root = trie_root::<Fp>(&self.start_mpt_root);
+        let mut root = start_root;
+        let ops = ops_of_traces::<Fp>(&self.mpt_trans_trace)?;
+        for (i, (trace, op)) in self.mpt_trans_trace.iter().zip(&ops).enumerate() {
+            let (before, after) = account_trie_roots(trace, op).ok_or_else(|| {
+                TraceError::DataErr(format!("trace {i}: no root of the account trie"))
+            })?;
+            if before != root {
+                return Err(TraceError::DataErr(if i == 0 {
+                    format!(
+                        "start mpt root {start_root:?} unmatch the root before trace 0 {before:?}"
+                    )
+                } else {
+                    format!("trace {i}: root before {before:?} is not continued from {root:?}")
+                }));
+            }
+            root = after;
+        }
+
+        let end_root = trie_root::<Fp>(&self.end_mpt_root);
+        if root != end_root {
+            return Err(TraceError::DataErr(format!(
+                "end mpt root {end_root:?} unmatch the final root {root:?}"
+            )));
+        }
+
+        let mut trie = StateTrie::default();
+        trie.add_ops(ops);
+        Ok(trie)
+    }
+
+    /// check the start and end mpt root of the block against its ops
+    pub fn verify(&self) -> Result<(), TraceError> {
+        self.state_trie::<Fr>().map(|_| ())
+    }
+}
+
+/// the storage layer shared by the trace formats: the op on the state trie and
+/// the key, value before and value after of the storage slot
+fn parse_state_update<Fp: Hashable>(
+    state_path: &[Option<serde::SMTPath>; 2],
+    state_key: &Option<serde::Hash>,
+    state_update: &Option<[Option<serde::StateData>; 2]>,
+) -> Result<(Option<SingleOp<Fp>>, [Option<KeyValue<Fp>>; 3]), TraceError> {
+    let state_trie = match (state_path, state_key) {
+        ([Some(before), Some(after)], Some(state_key)) => {
+            Some((before, after, *state_key).try_into()?)
+        }
+        _ => None,
+    };
+
+    let store = match state_update {
+        Some([before, after]) => {
+            let key = before
+                .as_ref()
+                .or(after.as_ref())
+                .map(|st| KeyValue::from(hash_to_limbs(&st.key)));
+            let value = |st: &Option<serde::StateData>| {
+                st.as_ref()
+                    .map(|st| KeyValue::from(hash_to_limbs(&st.value)))
+            };
+            [key, value(before), value(after)]
+        }
+        None => [None, None, None],
+    };
+
+    Ok((state_trie, store))
+}
+
+#[cfg(feature = "scroll-trace")]
+impl<'d, Fp: Hashable> TryFrom<&'d serde::SMTTrace> for AccountOp<Fp> {
+    type Error = TraceError;
+
+    fn try_from(trace: &'d serde::SMTTrace) -> Result<Self, Self::Error> {
+        let [path_before, path_after] = &trace.account_path;
+        let acc_trie: SingleOp<Fp> = (path_before, path_after, trace.account_key).try_into()?;
+
+        let (state_trie, [store_key, store_before, store_after]) =
+            parse_state_update(&trace.state_path, &trace.state_key, &trace.state_update)?;
+
+        let (address,) = <(Fp,)>::from(&trace.address);
+        let account_key = Fp::from_bytes_wide(&trace.account_key.cast());
+        let [data_before, data_after] = &trace.account_update;
+        let [state_before, state_after] = &trace.state_path;
+        let state_root = |path: &Option<serde::SMTPath>| {
+            path.as_ref()
+                .map(|path| &path.root)
+                .or(trace.common_state_root.as_ref())
+        };
+
+        Ok(Self {
+            acc_trie: Some(acc_trie),
+            state_trie,
+            account_before: scroll_account(
+                data_before.as_ref(),
+                address,
+                account_key,
+                state_root(state_before),
+            )?,
+            account_after: scroll_account(
+                data_after.as_ref(),
+                address,
+                account_key,
+                state_root(state_after),
+            )?,
+            store_key,
+            store_before,
+            store_after,
+            common_state_root: trace.common_state_root.as_ref().map(trie_root),
         })
     }
 }
 
+/// the account of a scroll-style trace, an absent account is left without
+/// hash traces (i.e. the empty leaf); the code hash has no counterpart in
+/// `Account` and is dropped
+#[cfg(feature = "scroll-trace")]
+fn scroll_account<Fp: Hashable>(
+    data: Option<&serde::AccountData>,
+    address: Fp,
+    account_key: Fp,
+    state_root: Option<&serde::Hash>,
+) -> Result<Account<Fp>, TraceError> {
+    let account = Account {
+        address,
+        account_key,
+        ..Default::default()
+    };
+    let data = match data {
+        Some(data) => data,
+        None => return Ok(account),
+    };
+    let acc = Account {
+        nonce: Fp::from(data.nonce),
+        gas_balance: biguint_to_fp(&data.balance)?,
+        state_root: state_root.map(trie_root).unwrap_or_default(),
+        ..account
+    };
+    Ok(acc.complete(|a, b| <Fp as Hashable>::hash([*a, *b])))
+}
+
+/// split the 32-byte (be) word into the first and second 16 bytes
+fn hash_to_limbs<Fp: FieldExt>(data: &serde::Hash) -> (Fp, Fp) {
+    Account::code_hash_limbs(U256::from_big_endian(&data.0))
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
-struct HashableField<Fp: FieldExt>(Fp);
+pub(crate) struct HashableField<Fp: FieldExt>(pub(crate) Fp);
 
 impl<Fp: FieldExt> std::hash::Hash for HashableField<Fp> {
     fn hash<H>(&self, state: &mut H)
@@ -502,6 +1265,34 @@ impl<Fp: FieldExt> From<Fp> for HashableField<Fp> {
     }
 }
 
+/// a poseidon hasher memoizing the pairs it has hashed, it can be passed to
+/// the `*_with_hasher` and `trace` hooks as `|a, b| memo.hash(a, b)` so a pair
+/// repeated across a batch of ops is hashed once
+#[derive(Clone, Debug, Default)]
+pub struct MemoHasher<Fp: FieldExt> {
+    cache: HashMap<(HashableField<Fp>, HashableField<Fp>), Fp>,
+}
+
+impl<Fp: Hashable> MemoHasher<Fp> {
+    /// the hash of `[a, b]`, computed on the first query only
+    pub fn hash(&mut self, a: &Fp, b: &Fp) -> Fp {
+        *self
+            .cache
+            .entry((HashableField(*a), HashableField(*b)))
+            .or_insert_with(|| <Fp as Hashable>::hash([*a, *b]))
+    }
+
+    /// the number of distinct pairs hashed so far
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// whether nothing has been hashed yet
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct HashTracesSrc<T, Fp: FieldExt> {
     source: T,
@@ -539,17 +1330,26 @@ where
 #[cfg(test)]
 mod tests {
 
-    use halo2_proofs::halo2curves::group::ff::PrimeField;
+    use ethers::types::U256;
+    use halo2_proofs::{arithmetic::FieldExt, halo2curves::group::ff::PrimeField};
     use hash_circuit::{hash, poseidon::Hash, Hashable};
     use num_bigint::BigUint;
     use std::vec;
 
     use crate::{
-        serde::HexBytes,
+        serde::{BlockResult, Hash as Hash32, HexBytes},
         test_utils::{hash_str_to_fp, Fp},
     };
 
-    use super::{bytes_to_fp, Account, TraceError};
+    use super::{
+        account_hash, address_to_key, biguint_to_fp, bytes_to_fp, decompose_key, hash_to_limbs,
+        pack_address, recover_key, Account, AccountOp, KeyValue, MemoHasher, TraceError,
+        ACCOUNT_ROWS_WITHOUT_PATH,
+    };
+    use crate::{
+        gadgets::mpt::{empty_root, SingleOp},
+        test_utils::{rand_fp, rand_gen},
+    };
 
     #[test]
     fn atonomy_bytes_data() {
@@ -576,6 +1376,353 @@ mod tests {
         println!("hash_bytes_hex {hash_bytes_hex:?}");
     }
 
+    #[test]
+    fn trace_integer_to_fp() {
+        let hash = HexBytes::<32>::try_from(
+            "0x0000000000000000000000000000000000000000000000000000000000001234",
+        )
+        .unwrap();
+        assert_eq!(<(Fp,)>::from(&hash).0, Fp::from(0x1234u64));
+
+        // not less than the modulus, reduced instead of panicking
+        let hash = HexBytes::<32>([0xffu8; 32]);
+        let mut wide = [0u8; 64];
+        wide[..32].copy_from_slice(&[0xffu8; 32]);
+        assert_eq!(<(Fp,)>::from(&hash).0, Fp::from_bytes_wide(&wide));
+
+        let address =
+            HexBytes::<20>::try_from("0x00000000000000000000000000000000000000ff").unwrap();
+        assert_eq!(<(Fp,)>::from(&address).0, Fp::from(0xffu64));
+
+        assert_eq!(
+            biguint_to_fp::<Fp>(&BigUint::from(1000u64)).unwrap(),
+            Fp::from(1000u64)
+        );
+        assert!(biguint_to_fp::<Fp>(&(BigUint::from(1u64) << 256)).is_err());
+    }
+
+    #[test]
+    fn account_from_snapshot() {
+        let (address, tx_hash, pre_hash, root) = (rand_fp(), rand_fp(), rand_fp(), rand_fp());
+        let acc =
+            Account::<Fp>::from_snapshot(address, 3, U256::from(1000u64), tx_hash, pre_hash, root)
+                .unwrap();
+
+        let expected = Account::<Fp> {
+            address,
+            nonce: Fp::from(3u64),
+            gas_balance: Fp::from(1000u64),
+            tx_hash,
+            pre_recrusive_tx_hash: pre_hash,
+            state_root: root,
+            ..Default::default()
+        }
+        .complete(|a, b| <Fp as Hashable>::hash([*a, *b]));
+        assert_eq!(acc.hash_traces, expected.hash_traces);
+        assert_eq!(acc.account_key, Account::<Fp>::key_from_address(address));
+        acc.verify_traces().unwrap();
+
+        assert!(matches!(
+            Account::<Fp>::from_snapshot(address, 3, U256::MAX, tx_hash, pre_hash, root),
+            Err(TraceError::DataErr(_))
+        ));
+    }
+
+    #[test]
+    fn amount_at_field_modulus() {
+        let modulus =
+            BigUint::parse_bytes(Fp::MODULUS.trim_start_matches("0x").as_bytes(), 16).unwrap();
+        let one = BigUint::from(1u64);
+
+        assert_eq!(biguint_to_fp::<Fp>(&(&modulus - &one)).unwrap(), -Fp::one());
+        assert!(biguint_to_fp::<Fp>(&modulus).is_err());
+        assert!(biguint_to_fp::<Fp>(&(&modulus + &one)).is_err());
+        assert!(biguint_to_fp::<Fp>(&((one << 256) - 1u64)).is_err());
+    }
+
+    #[test]
+    fn block_result_verify() {
+        let block = |start: &Hash32, end: &Hash32| BlockResult {
+            start_mpt_root: start.clone(),
+            end_mpt_root: end.clone(),
+            mpt_trans_trace: Vec::new(),
+        };
+        let (root_a, root_b) = (HexBytes::<32>([1u8; 32]), HexBytes::<32>([2u8; 32]));
+
+        assert!(block(&root_a, &root_a).verify().is_ok());
+        assert!(matches!(
+            block(&root_a, &root_b).verify(),
+            Err(TraceError::DataErr(msg)) if msg.starts_with("end mpt root")
+        ));
+
+        let traced: BlockResult =
+            serde_json::from_str(include_str!("../integration-test/trace_no_storage.json"))
+                .unwrap();
+        assert!(!traced.mpt_trans_trace.is_empty());
+        assert!(traced.verify().is_ok());
+
+        let mut tampered = traced.clone();
+        tampered.start_mpt_root = root_a.clone();
+        assert!(matches!(
+            tampered.verify(),
+            Err(TraceError::DataErr(msg)) if msg.starts_with("start mpt root")
+        ));
+
+        let mut tampered = traced;
+        tampered.end_mpt_root = root_b;
+        assert!(matches!(
+            tampered.verify(),
+            Err(TraceError::DataErr(msg)) if msg.starts_with("end mpt root")
+        ));
+    }
+
+    #[test]
+    fn block_result_account_trie_roots() {
+        let traced: BlockResult =
+            serde_json::from_str(include_str!("../integration-test/trace.json")).unwrap();
+        let update = traced.mpt_trans_trace[0].mpt_root_update.clone().unwrap();
+        assert_eq!(update.old_root, traced.start_mpt_root);
+        // the state root of the accounts is not the account trie root
+        let trie = traced.state_trie::<Fp>().unwrap();
+        assert_eq!(trie.get_ops().len(), 1);
+        assert_ne!(
+            trie.get_ops()[0].account_root_before(),
+            trie_root::<Fp>(&traced.start_mpt_root)
+        );
+
+        // the root update is not continued from the start root
+        let mut tampered = traced.clone();
+        tampered.mpt_trans_trace[0]
+            .mpt_root_update
+            .as_mut()
+            .unwrap()
+            .old_root = HexBytes::<32>([1u8; 32]);
+        assert!(matches!(
+            tampered.verify(),
+            Err(TraceError::DataErr(msg)) if msg.starts_with("start mpt root")
+        ));
+
+        // nothing is left to check the account trie against
+        let mut bare = traced;
+        bare.mpt_trans_trace[0].mpt_root_update = None;
+        assert!(matches!(
+            bare.verify(),
+            Err(TraceError::DataErr(msg)) if msg.contains("no root of the account trie")
+        ));
+    }
+
+    #[test]
+    fn memo_hasher_dedup() {
+        let plain = |a: &Fp, b: &Fp| <Fp as Hashable>::hash([*a, *b]);
+        let account = |nonce: u64| Account::<Fp> {
+            address: Fp::from(0x1234u64),
+            nonce: Fp::from(nonce),
+            gas_balance: Fp::from(1000u64),
+            ..Default::default()
+        };
+
+        let mut memo = MemoHasher::<Fp>::default();
+        assert!(memo.is_empty());
+        let acc = account(1).trace(|a, b| memo.hash(a, b));
+        assert_eq!(acc.hash_traces, account(1).trace(plain).hash_traces);
+        let hashed = memo.len();
+
+        // only the nonce dependent hashes are new for the same address
+        account(2).trace(|a, b| memo.hash(a, b));
+        assert_eq!(memo.len(), hashed + 3);
+
+        let memo = std::cell::RefCell::new(memo);
+        let siblings = [Fp::from(5u64), Fp::from(6u64)];
+        let key = Fp::from(3u64);
+        let leafs = (Fp::from(10u64), Fp::from(11u64));
+        let op = SingleOp::create_update_op_with_hasher(2, &siblings, key, leafs, |a, b| {
+            memo.borrow_mut().hash(a, b)
+        });
+        let expected = SingleOp::create_update_op(2, &siblings, key, leafs);
+        assert_eq!(op.start_root(), expected.start_root());
+        assert_eq!(op.new_root(), expected.new_root());
+    }
+
+    #[test]
+    fn trace_error_display() {
+        use std::error::Error;
+
+        let err = TraceError::DeErr(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "short input",
+        ));
+        assert_eq!(err.to_string(), "deserialize error: short input");
+        assert_eq!(err.source().unwrap().to_string(), "short input");
+
+        let err = TraceError::DataErr("bad root".into()).context("trace 1");
+        assert_eq!(err.to_string(), "malformed data: trace 1: bad root");
+        assert!(err.source().is_none());
+
+        let boxed: Box<dyn Error> = err.into();
+        assert_eq!(boxed.to_string(), "malformed data: trace 1: bad root");
+    }
+
+    #[test]
+    fn padding_hash_cached() {
+        let expected = <Fp as Hashable>::hash([Fp::zero(), Fp::zero()]);
+        assert_eq!(
+            AccountOp::<Fp>::padding_hash(),
+            (Fp::zero(), Fp::zero(), expected)
+        );
+        assert_eq!(*super::PADDING_HASH, (Fp::zero(), Fp::zero(), expected));
+    }
+
+    #[test]
+    fn account_op_eq() {
+        let mut rng = rand_gen([7u8; 32]);
+        let op = AccountOp::<Fp>::random(&mut rng);
+        assert_eq!(op, op.clone());
+        assert!(!op.is_noop());
+        assert_ne!(op, AccountOp::random(&mut rng));
+
+        let mut changed = op.clone();
+        changed.account_after = Account {
+            gas_balance: op.account_after.gas_balance + Fp::one(),
+            hash_traces: Vec::new(),
+            ..op.account_after.clone()
+        }
+        .complete(|a, b| <Fp as Hashable>::hash([*a, *b]));
+        assert_ne!(op, changed);
+
+        let noop = AccountOp {
+            account_after: op.account_before.clone(),
+            ..op.clone()
+        };
+        assert!(noop.is_noop());
+        assert_ne!(noop, op);
+
+        // the account trie is committed to as well
+        let acc_trie = |leafs: (u64, u64)| {
+            Some(SingleOp::<Fp>::create_update_op(
+                3,
+                &[Fp::from(5u64); 3],
+                Fp::from(7u64),
+                (Fp::from(leafs.0), Fp::from(leafs.1)),
+            ))
+        };
+        let with_trie = |leafs| AccountOp {
+            acc_trie: acc_trie(leafs),
+            ..noop.clone()
+        };
+        assert!(with_trie((8, 8)).is_noop());
+        assert!(!with_trie((8, 9)).is_noop());
+        assert_eq!(with_trie((8, 9)), with_trie((8, 9)));
+        assert_ne!(with_trie((8, 9)), with_trie((8, 10)));
+        assert_ne!(with_trie((8, 8)), noop);
+    }
+
+    #[cfg(feature = "scroll-trace")]
+    #[test]
+    fn scroll_trace_account_op() {
+        use crate::{gadgets::mpt::MPTPath, serde};
+
+        let to_hash = |fp: Fp| HexBytes(fp.to_repr());
+        let key = Fp::from(0x1234u64);
+        let leafs = [Fp::from(1u64), Fp::from(2u64)];
+        let account_path = leafs.map(|leaf| serde::SMTPath {
+            root: to_hash(MPTPath::create(&[], &[], key, Some(leaf)).root()),
+            leaf: Some(serde::SMTNode {
+                value: to_hash(leaf),
+                sibling: to_hash(key),
+            }),
+            path: Vec::new(),
+            path_part: BigUint::from(0u64),
+        });
+        let account_data = |nonce: u64, balance: u64| serde::AccountData {
+            nonce,
+            balance: BigUint::from(balance),
+            code_hash: HexBytes([0u8; 32]),
+        };
+        let state_root = to_hash(rand_fp());
+        let trace = serde::SMTTrace {
+            address: HexBytes([0x11; 20]),
+            account_key: to_hash(key),
+            account_path,
+            account_update: [Some(account_data(1, 100)), Some(account_data(2, 90))],
+            state_path: [None, None],
+            common_state_root: Some(state_root),
+            state_key: None,
+            state_update: None,
+        };
+
+        let op = AccountOp::<Fp>::try_from(&trace).unwrap();
+        assert!(op.state_trie.is_none());
+        assert_eq!(
+            op.use_rows_trie_account(),
+            op.acc_trie.as_ref().unwrap().use_rows()
+        );
+        assert_eq!(op.account_before.nonce, Fp::from(1u64));
+        assert_eq!(op.account_after.gas_balance, Fp::from(90u64));
+        assert_eq!(op.account_after.account_key, key);
+        assert_eq!(
+            op.account_root_after(),
+            Fp::from_bytes_wide(&state_root.cast())
+        );
+        assert_ne!(op.account_before.account_hash(), Fp::zero());
+        assert!(!op.is_noop());
+
+        // the sibling set of the account proofs must agree
+        let mut broken = trace;
+        broken.account_path[1].path = vec![serde::SMTNode {
+            value: to_hash(Fp::zero()),
+            sibling: to_hash(Fp::one()),
+        }];
+        broken.account_path[1].root =
+            to_hash(MPTPath::create(&[false], &[Fp::one()], key, Some(leafs[1])).root());
+        broken.account_path[0].path = vec![serde::SMTNode {
+            value: to_hash(Fp::zero()),
+            sibling: to_hash(Fp::from(3u64)),
+        }];
+        broken.account_path[0].root =
+            to_hash(MPTPath::create(&[false], &[Fp::from(3u64)], key, Some(leafs[0])).root());
+        assert!(AccountOp::<Fp>::try_from(&broken).is_err());
+    }
+
+    #[test]
+    fn pack_address_length() {
+        let address: Vec<u8> = (1..=20).collect();
+        let packed = pack_address::<Fp>(&address).unwrap();
+        let expected = crate::gadgets::u256_to_fp(U256::from_big_endian(&address)).unwrap();
+        assert_eq!(packed, expected);
+        assert_eq!(
+            <(Fp,)>::from(&HexBytes::<20>(address.try_into().unwrap())).0,
+            packed
+        );
+
+        for len in [0, 19, 21, 32] {
+            assert!(matches!(
+                pack_address::<Fp>(&vec![0xff; len]),
+                Err(TraceError::DataErr(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn code_hash_limbs_round_trip() {
+        let code_hash = U256::from_str_radix(
+            "26af0428e16c2e77b72b7ff2bfee86292f61c02dce0f25f8a8651a662245b818",
+            16,
+        )
+        .unwrap();
+        let (hi, lo) = Account::<Fp>::code_hash_limbs(code_hash);
+        assert_eq!(hi, Fp::from_u128(0x26af0428e16c2e77b72b7ff2bfee8629));
+        assert_eq!(lo, Fp::from_u128(0x2f61c02dce0f25f8a8651a662245b818));
+        assert_eq!(Account::code_hash_from_limbs((hi, lo)), Some(code_hash));
+
+        let mut bytes = [0u8; 32];
+        code_hash.to_big_endian(&mut bytes);
+        assert_eq!(hash_to_limbs::<Fp>(&HexBytes(bytes)), (hi, lo));
+
+        let wide = Fp::from_u128(u128::MAX) + Fp::one();
+        assert_eq!(Account::code_hash_from_limbs((wide, lo)), None);
+        assert_eq!(Account::code_hash_from_limbs((hi, wide)), None);
+    }
+
     /// test
     #[test]
     fn trace_account_data() {
@@ -588,6 +1735,15 @@ mod tests {
         for i in 0..tx_hash_vec.len() {
             final_tx_hash = Fp::hash([final_tx_hash, tx_hash_vec[i]]);
         }
+        assert_eq!(
+            Account::fold_tx_hashes(Fp::zero(), &tx_hash_vec),
+            final_tx_hash
+        );
+        let (head, tail) = tx_hash_vec.split_at(1);
+        assert_eq!(
+            Account::fold_tx_hashes(Account::fold_tx_hashes(Fp::zero(), head), tail),
+            final_tx_hash
+        );
 
         let account: Account<Fp> = Account {
             address: hash_str_to_fp("0xb364e75b1189dcbbf7f0c856456c1ba8e4d6481b"),
@@ -610,10 +1766,292 @@ mod tests {
 
         let data = account.complete(|a, b| <Fp as Hashable>::hash([*a, *b]));
         println!("data hash {:?}", data.account_hash());
+        assert_eq!(
+            Account::fold_tx_hashes(data.pre_recrusive_tx_hash, &[data.tx_hash]),
+            data.recrusive_tx_hash
+        );
+        assert_eq!(Account::key_from_address(data.address), data.account_key);
+        assert_eq!(address_to_key(data.address), data.account_key);
 
         assert_eq!(
             data.account_hash(),
             hash_str_to_fp("0x10bfe617037389f321b8a42581d8366a9cc8ae353d8b7d54195c28016c6054e8")
         );
+        assert_eq!(
+            account_hash(
+                data.nonce,
+                data.gas_balance,
+                data.recrusive_tx_hash,
+                data.state_root,
+                data.account_key,
+                data.pub_key,
+            ),
+            data.account_hash()
+        );
+    }
+
+    #[test]
+    fn account_from_traces() {
+        let hasher = |a: &Fp, b: &Fp| <Fp as Hashable>::hash([*a, *b]);
+        let fields = Account::<Fp>::create(
+            Fp::from(1u64),
+            Fp::from(1000u64),
+            Fp::from(11u64),
+            Fp::zero(),
+            Fp::from(7u64),
+        );
+        let traced = fields.clone().trace(hasher);
+
+        let account = Account::from_traces(fields.clone(), traced.hash_traces.clone()).unwrap();
+        assert_eq!(account.account_hash(), traced.account_hash());
+        assert_eq!(account.account_key, traced.account_key);
+        assert_eq!(account.recrusive_tx_hash, traced.recrusive_tx_hash);
+        account.verify_traces().unwrap();
+
+        let mut broken = traced.hash_traces.clone();
+        broken[3].2 += Fp::one();
+        assert!(Account::from_traces(fields.clone(), broken.clone()).is_err());
+        assert!(Account::from_traces(fields.clone(), traced.hash_traces[..5].to_vec()).is_err());
+
+        // the final hash is trusted
+        let mut forged = traced.hash_traces.clone();
+        forged[5].1 += Fp::one();
+        forged[5].2 += Fp::one();
+        let account = Account::from_traces(fields.clone(), forged.clone()).unwrap();
+        assert_ne!(account.account_hash(), traced.account_hash());
+        assert!(account.verify_traces().is_err());
+
+        // cached traces are kept by `complete`, and rejected when verified
+        let cached = Account {
+            hash_traces: broken,
+            ..fields
+        };
+        assert_eq!(
+            cached.clone().complete(hasher).hash_traces,
+            cached.hash_traces
+        );
+        assert!(cached.complete_verified(hasher).is_err());
+    }
+
+    #[test]
+    fn account_complete_verified() {
+        let hasher = |a: &Fp, b: &Fp| <Fp as Hashable>::hash([*a, *b]);
+        let traced = Account::<Fp>::create(
+            Fp::from(1u64),
+            Fp::from(1000u64),
+            Fp::from(11u64),
+            Fp::zero(),
+            Fp::from(7u64),
+        );
+
+        let untraced = Account {
+            hash_traces: Vec::new(),
+            ..traced.clone()
+        };
+        let completed = untraced.clone().complete_verified(hasher).unwrap();
+        assert_eq!(completed.hash_traces, traced.hash_traces);
+        let verified = traced.clone().complete_verified(hasher).unwrap();
+        assert_eq!(verified.account_hash(), traced.account_hash());
+
+        // stale traces of an account whose balance has changed
+        let stale = Account {
+            gas_balance: Fp::from(900u64),
+            ..traced.clone()
+        };
+        assert!(stale.clone().complete_verified(hasher).is_err());
+        assert!(stale.verify_traces().is_err());
+
+        let mut forged = traced.clone();
+        forged.hash_traces[5].2 = Fp::one();
+        assert!(forged.complete_verified(hasher).is_err());
+    }
+
+    #[test]
+    fn trace_common_state_root() {
+        let block: BlockResult =
+            serde_json::from_str(include_str!("../integration-test/trace_no_storage.json"))
+                .unwrap();
+        let common = trie_root::<Fp>(block.mpt_trans_trace[0].common_state_root.as_ref().unwrap());
+        // the storage is untouched while the account trie is updated
+        assert_ne!(block.start_mpt_root, block.end_mpt_root);
+        assert_ne!(common, trie_root(&block.start_mpt_root));
+
+        let trie = block.state_trie::<Fp>().unwrap();
+        assert_eq!(trie.get_ops().len(), 2);
+        for op in trie.get_ops() {
+            assert!(op.state_trie.is_none());
+            assert_eq!(op.common_state_root, Some(common));
+            assert_eq!(op.account_root_before(), common);
+            assert_eq!(op.account_root_after(), common);
+            op.verify_transition().unwrap();
+        }
+        let op = &trie.get_ops()[0];
+        assert_eq!(
+            AccountOp::<Fp>::from_bytes(&op.to_bytes())
+                .unwrap()
+                .common_state_root,
+            Some(common)
+        );
+
+        // the accounts fall back to the empty root without the common root
+        let mut bare = block.mpt_trans_trace[0].clone();
+        bare.common_state_root = None;
+        let op = AccountOp::<Fp>::try_from(&bare).unwrap();
+        assert_eq!(op.common_state_root, None);
+        assert_eq!(op.account_root_before(), empty_root());
+    }
+
+    #[test]
+    fn account_op_bytes_round_trip() {
+        let before = Account::<Fp>::create(
+            Fp::from(1u64),
+            Fp::from(1000u64),
+            Fp::from(11u64),
+            Fp::zero(),
+            Fp::from(7u64),
+        );
+        let after = Account::<Fp>::create(
+            Fp::from(2u64),
+            Fp::from(900u64),
+            Fp::from(12u64),
+            before.recrusive_tx_hash,
+            Fp::from(8u64),
+        );
+        let op = AccountOp {
+            account_before: before,
+            account_after: after,
+            ..Default::default()
+        };
+
+        let bytes = op.to_bytes();
+        let decoded = AccountOp::<Fp>::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            decoded.account_before.account_hash(),
+            op.account_before.account_hash()
+        );
+        assert_eq!(
+            decoded.account_after.account_hash(),
+            op.account_after.account_hash()
+        );
+        assert_eq!(
+            decoded.account_after.hash_traces,
+            op.account_after.hash_traces
+        );
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        assert!(AccountOp::<Fp>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(AccountOp::<Fp>::from_bytes(&trailing).is_err());
+    }
+
+    #[test]
+    fn account_op_with_storage() {
+        let siblings: Vec<Fp> = (0..3).map(|_| rand_fp()).collect();
+        let state_trie =
+            SingleOp::<Fp>::create_update_op(3, &siblings, Fp::from(5u64), (rand_fp(), rand_fp()));
+        let op = AccountOp {
+            state_trie: Some(state_trie),
+            store_key: Some(KeyValue::create((Fp::from(1u64), Fp::from(2u64)))),
+            store_before: Some(KeyValue::create((Fp::zero(), Fp::zero()))),
+            store_after: Some(KeyValue::create((Fp::zero(), Fp::from(3u64)))),
+            ..Default::default()
+        };
+        assert_eq!(op.use_rows_trie_state(), 5);
+        assert_eq!(op.use_rows_trie_kv(), 2);
+        assert_eq!(op.use_rows(), op.use_rows_trie_account() + 7);
+
+        let bytes = op.to_bytes();
+        let decoded = AccountOp::<Fp>::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            decoded.store_after.as_ref().map(|kv| kv.hash()),
+            op.store_after.as_ref().map(|kv| kv.hash())
+        );
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn account_op_rows_follow_path_depth() {
+        let op = AccountOp::<Fp>::default();
+        assert_eq!(op.use_rows_trie_account(), ACCOUNT_ROWS_WITHOUT_PATH);
+
+        for depth in [2, 20] {
+            let siblings: Vec<Fp> = (0..depth).map(|_| rand_fp()).collect();
+            let acc_trie = SingleOp::<Fp>::create_update_op(
+                depth,
+                &siblings,
+                Fp::from(5u64),
+                (rand_fp(), rand_fp()),
+            );
+            let op = AccountOp {
+                acc_trie: Some(acc_trie),
+                ..Default::default()
+            };
+            assert_eq!(op.use_rows_trie_account(), depth + 2);
+
+            let decoded = AccountOp::<Fp>::from_bytes(&op.to_bytes()).unwrap();
+            assert_eq!(decoded.use_rows_trie_account(), depth + 2);
+        }
+    }
+
+    #[test]
+    fn account_op_verify_transition() {
+        let before = Account::<Fp>::create(
+            Fp::from(1u64),
+            Fp::from(1000u64),
+            Fp::from(11u64),
+            Fp::zero(),
+            Fp::from(7u64),
+        );
+        let after = Account::<Fp>::create(
+            Fp::from(2u64),
+            Fp::from(900u64),
+            Fp::from(12u64),
+            before.recrusive_tx_hash,
+            Fp::from(8u64),
+        );
+        let op = AccountOp {
+            account_before: before,
+            account_after: after,
+            ..Default::default()
+        };
+        assert!(op.verify_transition().is_ok());
+
+        let mut tampered = op.clone();
+        tampered.account_after.state_root = Fp::from(9u64);
+        assert!(tampered.verify_transition().is_err());
+
+        let mut untraced = op;
+        untraced.account_before.hash_traces.clear();
+        assert!(untraced.verify_transition().is_err());
+    }
+
+    #[test]
+    fn key_decomposing() {
+        let (path, residual) = decompose_key(Fp::from(0b1101u64), 3);
+        assert_eq!(path, vec![true, false, true]);
+        assert_eq!(residual, Fp::one());
+        assert_eq!(recover_key(&path, residual), Fp::from(0b1101u64));
+
+        let key = rand_fp();
+        let (path, residual) = decompose_key(key, 20);
+        assert_eq!(recover_key(&path, residual), key);
+
+        let siblings: Vec<Fp> = (0..4).map(|_| rand_fp()).collect();
+        let op = SingleOp::<Fp>::create_update_op(4, &siblings, key, (rand_fp(), rand_fp()));
+        let (path, residual) = decompose_key(key, 4);
+        assert_eq!(op.key_residual, residual);
+        assert_eq!(
+            op.path,
+            path.iter()
+                .map(|b| if *b { Fp::one() } else { Fp::zero() })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn key_decomposing_overflow() {
+        decompose_key(rand_fp(), 32);
     }
 }
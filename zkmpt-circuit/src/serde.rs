@@ -84,6 +84,17 @@ pub enum RowDeError {
     #[error("cannot parse bigInt repr")]
     /// bigInt decode error
     BigInt,
+    #[error("hex string has odd number of digits ({0})")]
+    /// the hex string can not be decoded into whole bytes
+    OddLength(usize),
+    #[error("expect {expected} bytes but hex string has {actual}")]
+    /// the hex string decodes into a wrong number of bytes
+    Length {
+        /// bytes of the target
+        expected: usize,
+        /// bytes in the hex string
+        actual: usize,
+    },
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -143,16 +154,24 @@ impl<const LEN: usize> AsMut<[u8; LEN]> for HexBytes<LEN> {
 }
 
 impl<const LEN: usize> TryFrom<&str> for HexBytes<LEN> {
-    type Error = hex::FromHexError;
+    type Error = RowDeError;
 
+    /// the hex string (with or without "0x" prefix) must encode exactly `LEN`
+    /// bytes
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let mut bytes = Self::default();
         // handling "0x" prefix
-        if value.starts_with("0x") {
-            hex::decode_to_slice(value.get(2..).unwrap(), &mut bytes.0)?;
-        } else {
-            hex::decode_to_slice(value, &mut bytes.0)?;
+        let digits = value.strip_prefix("0x").unwrap_or(value);
+        if digits.len() % 2 != 0 {
+            return Err(RowDeError::OddLength(digits.len()));
         }
+        if digits.len() != LEN * 2 {
+            return Err(RowDeError::Length {
+                expected: LEN,
+                actual: digits.len() / 2,
+            });
+        }
+        hex::decode_to_slice(digits, &mut bytes.0)?;
 
         Ok(bytes)
     }
@@ -194,6 +213,15 @@ pub struct AccountUpdate {
     pub new_account_state: Option<AccountStateData>,
 }
 
+/// storage slot and value
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StateData {
+    /// the slot
+    pub key: Hash,
+    /// the value
+    pub value: Hash,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all(deserialize = "camelCase", serialize = "camelCase"))]
 pub struct AccountPathUpdate {
@@ -260,8 +288,61 @@ pub struct MPTTransTrace {
     pub account_update: Option<AccountUpdate>,
 
     pub account_path_update: Option<AccountPathUpdate>,
-    // SMTPath for storage,
-    // pub state_path: [Option<SMTPath>; 2],
+
+    /// key of the storage slot (hash of the slot), only for storage op
+    #[serde(default)]
+    pub state_key: Option<Hash>,
+    /// SMTPath for storage, before and after
+    #[serde(default)]
+    pub state_path: [Option<SMTPath>; 2],
+    /// the storage slot and its value, before and after
+    #[serde(default)]
+    pub state_update: Option<[Option<StateData>; 2]>,
+    /// the storage root when the op does not touch the storage
+    #[serde(default)]
+    pub common_state_root: Option<Hash>,
+}
+
+/// account data in the scroll-style SMTTrace
+#[cfg(feature = "scroll-trace")]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all(deserialize = "camelCase", serialize = "camelCase"))]
+pub struct AccountData {
+    /// nonce
+    pub nonce: u64,
+    /// balance
+    #[serde(deserialize_with = "de_uint_hex", serialize_with = "se_uint_hex")]
+    pub balance: BigUint,
+    /// code hash
+    pub code_hash: Hash,
+}
+
+/// the scroll-style trace of an account op, with the account trie proof and
+/// an optional storage proof
+#[cfg(feature = "scroll-trace")]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all(deserialize = "camelCase", serialize = "camelCase"))]
+pub struct SMTTrace {
+    /// Address for the Account
+    pub address: Address,
+    /// key of account (hash of address)
+    pub account_key: Hash,
+    /// SMTPath for account, before and after
+    pub account_path: [SMTPath; 2],
+    /// the account data, before and after (none for an absent account)
+    pub account_update: [Option<AccountData>; 2],
+    /// SMTPath for storage, before and after
+    #[serde(default)]
+    pub state_path: [Option<SMTPath>; 2],
+    /// the storage root when the op does not touch the storage
+    #[serde(default)]
+    pub common_state_root: Option<Hash>,
+    /// key of the storage slot (hash of the slot)
+    #[serde(default)]
+    pub state_key: Option<Hash>,
+    /// the storage slot and its value, before and after
+    #[serde(default)]
+    pub state_update: Option<[Option<StateData>; 2]>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -272,3 +353,35 @@ pub struct BlockResult {
     // #[serde(rename = "mptwitness", default)]
     pub mpt_trans_trace: Vec<MPTTransTrace>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Hash, HexBytes, RowDeError};
+
+    #[test]
+    fn hex_bytes_length() {
+        let full = format!("0x{}", "ab".repeat(32));
+        assert_eq!(Hash::try_from(full.as_str()).unwrap().0, [0xab; 32]);
+        assert_eq!(Hash::try_from(&full[2..]).unwrap().0, [0xab; 32]);
+
+        let short = format!("0x{}", "ab".repeat(31));
+        assert!(matches!(
+            HexBytes::<32>::try_from(short.as_str()),
+            Err(RowDeError::Length {
+                expected: 32,
+                actual: 31
+            })
+        ));
+        assert!(matches!(
+            HexBytes::<32>::try_from(&full[..full.len() - 1]),
+            Err(RowDeError::OddLength(63))
+        ));
+        assert!(matches!(
+            HexBytes::<20>::try_from(full.as_str()),
+            Err(RowDeError::Length { expected: 20, .. })
+        ));
+
+        let err = serde_json::from_str::<Hash>(&format!("\"{short}\"")).unwrap_err();
+        assert!(err.to_string().contains("expect 32 bytes"));
+    }
+}
@@ -1,4 +1,13 @@
+#[cfg(feature = "rocksdb")]
 pub mod cf_store;
+#[cfg(feature = "rocksdb")]
 pub mod default_store;
+#[cfg(feature = "rocksdb")]
 pub mod smt_kv;
+#[cfg(feature = "rocksdb")]
 pub mod smt_serde;
+pub mod trie_store;
+
+#[cfg(feature = "rocksdb")]
+pub use trie_store::RocksDbTrieStore;
+pub use trie_store::{MemoryTrieStore, StoreError, TrieStore};
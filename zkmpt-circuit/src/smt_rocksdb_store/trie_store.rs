@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use ethers::types::H256;
+
+/// the reserved key under which the last committed root is kept
+pub const ROOT_KEY: &[u8] = b"__zkprover_state_root";
+
+#[derive(Debug, thiserror::Error)]
+/// trie store errors
+pub enum StoreError {
+    #[error("store backend error: {0}")]
+    /// error raised by the backend
+    Backend(String),
+    #[error("stored root has {0} bytes, expect 32")]
+    /// the value under `ROOT_KEY` is not a 32-byte hash
+    MalformedRoot(usize),
+}
+
+/// key-value backend persisting the state trie between runs
+pub trait TrieStore {
+    /// read the value under key
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError>;
+
+    /// write the value under key
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError>;
+
+    /// the last committed root, None for a fresh store
+    fn root(&self) -> Result<Option<H256>, StoreError> {
+        match self.get(ROOT_KEY)? {
+            None => Ok(None),
+            Some(v) if v.len() == 32 => Ok(Some(H256::from_slice(&v))),
+            Some(v) => Err(StoreError::MalformedRoot(v.len())),
+        }
+    }
+
+    /// commit a new root, which would be returned by `root` afterwards
+    fn commit_root(&mut self, root: H256) -> Result<(), StoreError> {
+        self.put(ROOT_KEY, root.as_bytes())
+    }
+}
+
+/// in-memory store, the default backend (and the one used in tests)
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTrieStore(HashMap<Vec<u8>, Vec<u8>>);
+
+impl TrieStore for MemoryTrieStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.0.get(key).cloned())
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        self.0.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+}
+
+/// store backed by a RocksDB database
+#[cfg(feature = "rocksdb")]
+pub struct RocksDbTrieStore(rocksdb::DB);
+
+#[cfg(feature = "rocksdb")]
+impl RocksDbTrieStore {
+    /// open (or create) the database under path
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, StoreError> {
+        use rocksdb::prelude::Open;
+        rocksdb::DB::open_default(path)
+            .map(Self)
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+impl TrieStore for RocksDbTrieStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        use rocksdb::prelude::Get;
+        self.0
+            .get(key)
+            .map(|v| v.map(|v| v.to_vec()))
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), StoreError> {
+        use rocksdb::prelude::Put;
+        self.0
+            .put(key, value)
+            .map_err(|e| StoreError::Backend(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryTrieStore, StoreError, TrieStore, ROOT_KEY};
+    use ethers::types::H256;
+
+    #[test]
+    fn test_memory_store_root() {
+        let mut store = MemoryTrieStore::default();
+        assert_eq!(store.root().unwrap(), None);
+
+        store.put(b"key", b"value").unwrap();
+        assert_eq!(store.get(b"key").unwrap(), Some(b"value".to_vec()));
+
+        let root = H256::repeat_byte(7);
+        store.commit_root(root).unwrap();
+        assert_eq!(store.root().unwrap(), Some(root));
+
+        store.put(ROOT_KEY, &[1, 2, 3]).unwrap();
+        assert!(matches!(store.root(), Err(StoreError::MalformedRoot(3))));
+    }
+}
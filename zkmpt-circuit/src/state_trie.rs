@@ -13,9 +13,12 @@ use crate::{
         account::AccountGadget,
         hash_util,
         layer::LayerGadget,
+        mpt::empty_root,
+        padding::PaddingGadget,
+        sign_util::Challenges,
         table_util::{self, MPTProofType},
     },
-    operation::{Account, AccountOp, HashTracesSrc},
+    operation::{Account, AccountOp, HashTracesSrc, HashableField, TraceError},
 };
 use hash_circuit::{
     hash::Hashable, hash::PoseidonHashChip, hash::PoseidonHashConfig, hash::PoseidonHashTable,
@@ -35,18 +38,94 @@ fn get_rand_base() -> u64 {
         .expect("always has init element")
 }
 
-#[derive(Clone, Default)]
+/// randomness used for rlc: a challenge which is only available after the
+/// first phase witness has been committed; under `dev` feature the constant
+/// rand base is used instead, for cheap testing. it is for the gadget looking
+/// up an rlc to configure, no gadget of the account only layout does so
+/// `StateTrieConfig` constructs no challenge
+fn configure_randomness<Fp: FieldExt>(
+    meta: &mut ConstraintSystem<Fp>,
+) -> (Option<Challenges>, Expression<Fp>) {
+    if cfg!(feature = "dev") {
+        (None, Expression::Constant(Fp::from(get_rand_base())))
+    } else {
+        let challenges = Challenges::construct(meta);
+        let randomness = challenges.exprs(meta).evm_word();
+        (Some(challenges), randomness)
+    }
+}
+
+#[derive(Clone)]
 pub struct StateTrie<Fp: FieldExt> {
     start_root: Fp,
     final_root: Fp,
     ops: Vec<AccountOp<Fp>>,
+    mode: StateTrieMode,
+    /// the start root is kept once the first op has been added, even if all
+    /// ops are reverted
+    start_fixed: bool,
 }
 
+/// a trie without any op, both roots are the empty root
+impl<Fp: FieldExt> Default for StateTrie<Fp> {
+    fn default() -> Self {
+        Self {
+            start_root: empty_root(),
+            final_root: empty_root(),
+            ops: Vec::new(),
+            mode: StateTrieMode::default(),
+            start_fixed: false,
+        }
+    }
+}
+
+const OP_PADDING: u32 = 0;
 const OP_TRIE_ACCOUNT: u32 = 1;
 const OP_TRIE_STATE: u32 = 2;
 const OP_ACCOUNT: u32 = 3;
 const OP_STORAGE: u32 = 4;
 
+/// which blocks of an op the state trie circuit lays out, kept in the crate
+/// until the storage blocks of `Full` are constrained
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StateTrieMode {
+    /// only the account trie block of each op: the account trie and account
+    /// data are constrained while the storage of the account is not proven
+    AccountOnly,
+    /// the account trie block followed by the state trie (`OP_TRIE_STATE`)
+    /// and storage kv (`OP_STORAGE`) blocks; the layer paces the storage
+    /// blocks but there is no gadget constraining them yet
+    Full,
+}
+
+impl Default for StateTrieMode {
+    fn default() -> Self {
+        Self::AccountOnly
+    }
+}
+
+impl StateTrieMode {
+    /// the (op code, rows) of the blocks `op` takes in this mode, in order
+    fn blocks<Fp: FieldExt>(&self, op: &AccountOp<Fp>) -> Vec<(u32, usize)> {
+        let mut blocks = vec![(OP_TRIE_ACCOUNT, op.use_rows_trie_account())];
+        if *self == Self::Full {
+            // the storage sub-blocks follow the account block
+            blocks.push((OP_TRIE_STATE, op.use_rows_trie_state()));
+            blocks.push((OP_STORAGE, op.use_rows_trie_kv()));
+        }
+        blocks
+    }
+
+    /// the rows `op` takes in this mode
+    pub fn op_rows<Fp: FieldExt>(&self, op: &AccountOp<Fp>) -> usize {
+        self.blocks(op).iter().map(|(_, rows)| rows).sum()
+    }
+}
+
+/// rows taken by the layer besides ops: the heading row and the last flushed
+/// row
+const LAYER_OVERHEAD_ROWS: usize = 2;
+
 impl<Fp: FieldExt> StateTrie<Fp> {
     /// Obtain the wrapped operation sequence
     pub fn get_ops(&self) -> &[AccountOp<Fp>] {
@@ -55,8 +134,9 @@ impl<Fp: FieldExt> StateTrie<Fp> {
 
     /// Add an op into the circuit data
     pub fn add_op(&mut self, op: AccountOp<Fp>) {
-        if self.ops.is_empty() {
-            self.start_root = op.account_root_before()
+        if !self.start_fixed {
+            self.start_root = op.account_root_before();
+            self.start_fixed = true;
         } else {
             assert_eq!(self.final_root, op.account_root_before());
         }
@@ -64,11 +144,57 @@ impl<Fp: FieldExt> StateTrie<Fp> {
         self.ops.push(op)
     }
 
-    /// Add an op array
-    pub fn add_ops(&mut self, ops: impl IntoIterator<Item = AccountOp<Fp>>) {
-        for op in ops {
-            self.add_op(op)
+    /// append an op and return the new final root, the op must start from
+    /// the final root or the trie is left unchanged
+    pub fn apply(&mut self, op: AccountOp<Fp>) -> Result<Fp, TraceError> {
+        if self.start_fixed && self.final_root != op.account_root_before() {
+            return Err(TraceError::DataErr(format!(
+                "root before {:?} of the applied op unmatch the final root {:?}",
+                op.account_root_before(),
+                self.final_root
+            )));
         }
+        self.add_op(op);
+        Ok(self.final_root)
+    }
+
+    /// pop the most recent op and restore the final root before it, the start
+    /// root is kept
+    pub fn revert_last(&mut self) -> Option<AccountOp<Fp>> {
+        let op = self.ops.pop()?;
+        self.final_root = op.account_root_before();
+        Some(op)
+    }
+
+    /// concatenate the ops of `other`, which must start from the final root
+    /// of this trie; a trie without any op takes the ops of `other` as is
+    pub fn append(&mut self, other: StateTrie<Fp>) -> Result<(), TraceError> {
+        if !other.start_fixed {
+            return Ok(());
+        }
+        if !self.start_fixed {
+            self.start_root = other.start_root;
+            self.start_fixed = true;
+        } else if self.final_root != other.start_root {
+            return Err(TraceError::DataErr(format!(
+                "start root {:?} of the appended trie unmatch the final root {:?}",
+                other.start_root, self.final_root
+            )));
+        }
+        self.final_root = other.final_root;
+        self.ops.extend(other.ops);
+        Ok(())
+    }
+
+    /// the distinct addresses of the accounts before and after each op, in
+    /// the order they are first seen
+    pub fn touched_addresses(&self) -> Vec<Fp> {
+        let mut seen = std::collections::HashSet::new();
+        self.ops
+            .iter()
+            .flat_map(|op| [op.account_before.address, op.account_after.address])
+            .filter(|address| seen.insert(HashableField(*address)))
+            .collect()
     }
 
     /// Obtain the final root
@@ -76,27 +202,119 @@ impl<Fp: FieldExt> StateTrie<Fp> {
         self.final_root
     }
 
+    /// pick the mode of the circuits built from this trie, `AccountOnly` by
+    /// default
+    pub(crate) fn with_mode(mut self, mode: StateTrieMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// the rows required for assigning all ops in the picked mode, including
+    /// the overhead of layer
+    pub fn required_rows(&self) -> usize {
+        self.ops
+            .iter()
+            .map(|op| self.mode.op_rows(op))
+            .sum::<usize>()
+            + LAYER_OVERHEAD_ROWS
+    }
+
+    /// build the circuit in the picked mode
     pub fn circuits(self, rows: usize, tips: &[MPTProofType]) -> StateTrieCircuit<Fp> {
-        StateTrieCircuit::new(rows, self.ops, Vec::from(tips))
+        StateTrieCircuit {
+            mode: self.mode,
+            ..StateTrieCircuit::new(rows, self.ops, Vec::from(tips))
+        }
     }
 }
 
 impl<Fp: Hashable> StateTrie<Fp> {
-    // pub fn hash_traces(&self) -> impl Iteratro<Item = &(Fp, Fp, Fp) + Clone {
-    //     HashTracesSrc::from(self.ops.iter().flat_map(|op| op.hash_traces()))
-    // }
+    /// the minimal k whose 2^k rows can contain `required_rows` besides the
+    /// blinding rows and the row reserved after them, i.e.
+    /// `cs.blinding_factors() + 1` rows
+    pub fn min_k(&self) -> u32 {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        StateTrieCircuit::<Fp>::configure(&mut cs);
+        (self.required_rows() + cs.blinding_factors() + 1)
+            .next_power_of_two()
+            .trailing_zeros()
+    }
+
+    /// Add an op array, the hash traces of both accounts of each op are
+    /// completed first (cached traces are kept, see `Account::complete`),
+    /// which is independent per op and runs concurrently with the `parallel`
+    /// feature; then the ops are added in order
+    pub fn add_ops(&mut self, ops: impl IntoIterator<Item = AccountOp<Fp>>) {
+        let ops: Vec<_> = ops.into_iter().collect();
+        #[cfg(feature = "parallel")]
+        let ops: Vec<_> = {
+            use rayon::prelude::*;
+            ops.into_par_iter().map(complete_op).collect()
+        };
+        for op in ops {
+            #[cfg(not(feature = "parallel"))]
+            let op = complete_op(op);
+            self.add_op(op)
+        }
+    }
+
+    /// walk every op natively, checking the root continuity and the validity
+    /// of each op, without building a circuit
+    pub fn verify_all(&self) -> Result<(), TraceError> {
+        let mut root = self.start_root;
+        for (i, op) in self.ops.iter().enumerate() {
+            if op.account_root_before() != root {
+                return Err(TraceError::DataErr(format!(
+                    "op {i}: root before {:?} is not continued from {:?}",
+                    op.account_root_before(),
+                    root
+                )));
+            }
+            op.verify_transition()
+                .map_err(|e| e.context(format!("op {i}")))?;
+            root = op.account_root_after();
+        }
+
+        if root != self.final_root {
+            return Err(TraceError::DataErr(format!(
+                "final root {:?} unmatch the last op {:?}",
+                self.final_root, root
+            )));
+        }
+        Ok(())
+    }
+
+    /// the distinct poseidon invocations of all ops, deduplicated across ops
+    /// as `HashTracesSrc` does, which is the calcs `HashCircuit::from_ops`
+    /// needs for them
+    pub fn num_hash_calcs(&self) -> usize {
+        HashTracesSrc::from(self.ops.iter().flat_map(|op| op.hash_traces())).count()
+    }
+}
+
+/// complete the hash traces of both accounts of `op`, see `Account::complete`
+fn complete_op<Fp: Hashable>(op: AccountOp<Fp>) -> AccountOp<Fp> {
+    let hasher = |a: &Fp, b: &Fp| <Fp as Hashable>::hash([*a, *b]);
+    AccountOp {
+        account_before: op.account_before.complete(hasher),
+        account_after: op.account_after.complete(hasher),
+        ..op
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct StateTrieConfig {
     layer: LayerGadget,
     account: AccountGadget,
+    padding: PaddingGadget,
     tables: table_util::MPTOpTables,
     hash_tbl: hash_util::HashTable,
+    mode: StateTrieMode,
 }
 
 impl StateTrieConfig {
-    /// configure for lite circuit (no mpt table included, for fast testing)
+    /// configure for lite circuit (no mpt table included, for fast testing),
+    /// in `AccountOnly` mode
     pub fn configure_base<Fp: FieldExt>(
         meta: &mut ConstraintSystem<Fp>,
         hash_tbl: [Column<Advice>; 5],
@@ -106,6 +324,7 @@ impl StateTrieConfig {
 
         let layer =
             LayerGadget::configure(meta, 5, std::cmp::max(0, AccountGadget::min_free_cols()), 4);
+        layer.constrain_root_continuity(meta);
 
         let account = AccountGadget::configure(
             meta,
@@ -117,54 +336,168 @@ impl StateTrieConfig {
             tables.clone(),
             hash_tbl.clone(),
         );
+        let padding = PaddingGadget::configure(
+            meta,
+            layer.public_sel(),
+            layer.exported_cols(OP_PADDING).as_slice(),
+            layer.get_ctrl_type_flags(),
+        );
         Self {
             layer,
             account,
+            padding,
             tables,
             hash_tbl,
+            mode: StateTrieMode::AccountOnly,
         }
     }
 
+    /// same as `account_only`
     pub fn configure_sub<Fp: FieldExt>(
         meta: &mut ConstraintSystem<Fp>,
         mpt_tbl: [Column<Advice>; 7],
         hash_tbl: [Column<Advice>; 5],
-        randomness: Expression<Fp>,
+    ) -> Self {
+        Self::account_only(meta, mpt_tbl, hash_tbl)
+    }
+
+    /// configure in `AccountOnly` mode, the only mode whose blocks are all
+    /// constrained
+    pub fn account_only<Fp: FieldExt>(
+        meta: &mut ConstraintSystem<Fp>,
+        mpt_tbl: [Column<Advice>; 7],
+        hash_tbl: [Column<Advice>; 5],
+    ) -> Self {
+        Self::configure_mode(meta, mpt_tbl, hash_tbl, StateTrieMode::AccountOnly)
+    }
+
+    /// configure with the blocks of `mode` laid out, see `StateTrieMode`
+    pub(crate) fn configure_mode<Fp: FieldExt>(
+        meta: &mut ConstraintSystem<Fp>,
+        _mpt_tbl: [Column<Advice>; 7],
+        hash_tbl: [Column<Advice>; 5],
+        mode: StateTrieMode,
     ) -> Self {
         let mut lite_cfg = Self::configure_base(meta, hash_tbl);
+        lite_cfg.mode = mode;
         lite_cfg
     }
 
+    /// the mode this config lays out the ops in
+    pub(crate) fn mode(&self) -> StateTrieMode {
+        self.mode
+    }
+
+    /// fill the transition table shared by the gadgets
+    pub fn load_tables<Fp: FieldExt>(&self, layouter: &mut impl Layouter<Fp>) -> Result<(), Error> {
+        self.tables
+            .fill_mpt_transitions(layouter, AccountGadget::transition_rules())
+    }
+
+    /// walk the blocks of `ops` in the configured mode from offset `start`,
+    /// `pace` is called with the start offset, the series of the op (from 1),
+    /// the op transition, the rows and the (old, new) account root of each
+    /// block, where only the first block of an op moves the root; return the
+    /// offset after the last block and the last op code
+    fn walk_ops<'d, Fp: Hashable>(
+        &self,
+        ops: impl Iterator<Item = &'d AccountOp<Fp>>,
+        mut start: usize,
+        mut pace: impl FnMut(usize, u64, (u32, u32), usize, (Fp, Fp)) -> Result<(), Error>,
+    ) -> Result<(usize, u32), Error> {
+        let mut last_op_code = self.layer.start_op_code();
+        for (series, op) in (1..).zip(ops) {
+            let mut old_root = op.account_root_before();
+            for (op_code, rows) in self.mode.blocks(op) {
+                if rows == 0 {
+                    continue;
+                }
+                let new_root = op.account_root_after();
+                pace(
+                    start,
+                    series,
+                    (last_op_code, op_code),
+                    rows,
+                    (old_root, new_root),
+                )?;
+                old_root = new_root;
+                start += rows;
+                last_op_code = op_code;
+            }
+        }
+        Ok((start, last_op_code))
+    }
+
+    /// dry run of the layout in `synthesize_core` without assigning anything,
+    /// return the offset the layout ends at: `rows` if the ops fit in, or
+    /// beyond `rows` if they overflow
+    pub fn measure_core<'d, Fp: Hashable>(
+        &self,
+        ops: impl Iterator<Item = &'d AccountOp<Fp>>,
+        rows: usize,
+    ) -> usize {
+        let (end, _) = self
+            .walk_ops(ops, self.layer.start_offset(), |_, _, _, _, _| Ok(()))
+            .expect("dry run never fails");
+        std::cmp::max(end, rows)
+    }
+
+    /// assign all ops within the single "main" region, each op block is
+    /// placed at the running offset `walk_ops` threads from the one
+    /// `LayerGadget::assign` returns, so gadgets assigned per op must use that
+    /// offset instead of entering a region of their own
     pub fn synthesize_core<'d, Fp: Hashable>(
         &self,
         layouter: &mut impl Layouter<Fp>,
         ops: impl Iterator<Item = &'d AccountOp<Fp>> + Clone,
         rows: usize,
     ) -> Result<(), Error> {
+        self.load_tables(layouter)?;
         let start_root = ops
             .clone()
             .next()
             .map(|op| op.account_root_before())
-            .unwrap_or_else(Fp::zero);
+            .unwrap_or_else(empty_root);
+        let final_root = ops
+            .clone()
+            .last()
+            .map(|op| op.account_root_after())
+            .unwrap_or(start_root);
+        // the padding continues the series of the last op, or the first series
+        // the layer has fixed
+        let last_series = std::cmp::max(ops.clone().count(), 1) as u64;
         layouter.assign_region(
             || "main",
             |mut region| {
-                let mut series: usize = 1;
-                let mut last_op_code = self.layer.start_op_code();
-                let mut start = self.layer.assign(&mut region, rows, start_root).unwrap();
-
-                // let empty_account = Default::default();
-                for op in ops.clone() {
-                    let block_start = start;
+                let start = self.layer.assign(&mut region, rows, start_root)?;
+                let (start, last_op_code) = self.walk_ops(
+                    ops.clone(),
+                    start,
+                    |offset, series, op_type, op_rows, roots| {
+                        self.layer.pace_op(&mut region, offset, op_type, op_rows)?;
+                        self.layer
+                            .pace_series(&mut region, offset, series, op_rows)?;
+                        self.layer.pace_roots(&mut region, offset, roots, op_rows)
+                    },
+                )?;
+
+                // pad the rest rows so the circuit shape is constant
+                if start < rows {
+                    self.layer.pace_op(
+                        &mut region,
+                        start,
+                        (last_op_code, OP_PADDING),
+                        rows - start,
+                    )?;
                     self.layer
-                        .pace_op(
-                            &mut region,
-                            start,
-                            (last_op_code, OP_TRIE_ACCOUNT),
-                            op.use_rows_trie_account(),
-                        )
-                        .unwrap();
-                    // start = self.account
+                        .pace_series(&mut region, start, last_series, rows - start)?;
+                    self.layer.pace_roots(
+                        &mut region,
+                        start,
+                        (final_root, final_root),
+                        rows - start,
+                    )?;
+                    self.padding.assign(&mut region, start, rows)?;
                 }
                 Ok(())
             },
@@ -172,6 +505,52 @@ impl StateTrieConfig {
     }
 }
 
+impl StateTrieConfig {
+    /// the layout before `synthesize_core` took a single region: the layer
+    /// and every block of the ops enter a region of their own, which is not
+    /// a satisfied layout and only kept for benchmarking the region overhead
+    #[cfg(feature = "bench")]
+    #[doc(hidden)]
+    pub fn synthesize_per_op<'d, Fp: Hashable>(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        ops: impl Iterator<Item = &'d AccountOp<Fp>> + Clone,
+        rows: usize,
+    ) -> Result<(), Error> {
+        self.load_tables(layouter)?;
+        let start_root = ops
+            .clone()
+            .next()
+            .map(|op| op.account_root_before())
+            .unwrap_or_else(empty_root);
+        layouter.assign_region(
+            || "layer",
+            |mut region| self.layer.assign(&mut region, rows, start_root).map(|_| ()),
+        )?;
+
+        let mut blocks = Vec::new();
+        self.walk_ops(
+            ops,
+            self.layer.start_offset(),
+            |_, series, op_type, op_rows, roots| {
+                blocks.push((series, op_type, op_rows, roots));
+                Ok(())
+            },
+        )?;
+        for (series, op_type, op_rows, roots) in blocks {
+            layouter.assign_region(
+                || "op block",
+                |mut region| {
+                    self.layer.pace_op(&mut region, 0, op_type, op_rows)?;
+                    self.layer.pace_series(&mut region, 0, series, op_rows)?;
+                    self.layer.pace_roots(&mut region, 0, roots, op_rows)
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
 /// StateTrie
 #[derive(Clone, Default, Debug)]
 pub struct StateTrieCircuit<F: FieldExt> {
@@ -185,6 +564,9 @@ pub struct StateTrieCircuit<F: FieldExt> {
     /// no run-time checking for the consistents between ops and generated mpt
     /// table
     pub mpt_table: Vec<MPTProofType>,
+
+    /// the mode the ops are laid out in
+    pub(crate) mode: StateTrieMode,
 }
 
 impl<Fp: Hashable> StateTrieCircuit<Fp> {
@@ -205,6 +587,7 @@ impl<Fp: FieldExt> StateTrieCircuit<Fp> {
             calcs,
             ops,
             mpt_table,
+            mode: StateTrieMode::AccountOnly,
         }
     }
 }
@@ -218,18 +601,30 @@ impl<Fp: Hashable> Circuit<Fp> for StateTrieCircuit<Fp> {
             calcs: self.calcs,
             ops: Vec::new(),
             mpt_table: Vec::new(),
+            mode: self.mode,
         }
     }
 
     fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
         let hash_tbl = [0; 5].map(|_| meta.advice_column());
         let mpt_tbl = [0; 7].map(|_| meta.advice_column());
-        let randomness = Expression::Constant(Fp::from(get_rand_base()));
-        StateTrieConfig::configure_sub(meta, mpt_tbl, hash_tbl, randomness)
+        StateTrieConfig::configure_sub(meta, mpt_tbl, hash_tbl)
     }
 
-    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
-        todo!()
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        // the blocks are laid out by the mode of config
+        if self.mode != config.mode() {
+            return Err(Error::Synthesis);
+        }
+        config.hash_tbl.dev_fill(
+            &mut layouter,
+            self.ops.iter().flat_map(|op| op.hash_traces()),
+        )?;
+        config.synthesize_core(&mut layouter, self.ops.iter(), self.calcs)
     }
 }
 
@@ -239,11 +634,20 @@ mod tests {
     #![allow(unused_imports)]
     use std::hash::Hash;
 
+    use super::{StateTrie, StateTrieCircuit, StateTrieConfig, StateTrieMode};
     use crate::{
-        gadgets::hash_util::HashCircuit,
-        test_utils::{hash_str_to_fp, Fp},
+        gadgets::{
+            hash_util::HashCircuit,
+            mpt::{empty_root, MPTPath, SingleOp},
+        },
+        operation::{Account, AccountOp, TraceError},
+        test_utils::{hash_str_to_fp, rand_account_ops, rand_gen, Fp},
+    };
+    use halo2_proofs::{
+        dev::MockProver,
+        halo2curves::group::ff::PrimeField,
+        plonk::{Circuit, ConstraintSystem},
     };
-    use halo2_proofs::{dev::MockProver, halo2curves::group::ff::PrimeField};
     use hash_circuit::{
         hash::{MessageHashable, PoseidonHashTable},
         Hashable,
@@ -283,4 +687,331 @@ mod tests {
 
         // assert_eq!(prover_hash.verify(), Ok(()));
     }
+
+    fn account_op(nonce: u64, root_before: u64, root_after: u64) -> AccountOp<Fp> {
+        let account_before = Account::<Fp>::create(
+            Fp::from(nonce),
+            Fp::from(1000u64),
+            Fp::from(nonce),
+            Fp::zero(),
+            Fp::from(root_before),
+        );
+        let account_after = Account::<Fp>::create(
+            Fp::from(nonce + 1),
+            Fp::from(1000u64),
+            Fp::from(nonce + 1),
+            account_before.recrusive_tx_hash,
+            Fp::from(root_after),
+        );
+        AccountOp {
+            account_before,
+            account_after,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_required_rows() {
+        let mut trie = StateTrie::<Fp>::default();
+        let mut cs = ConstraintSystem::<Fp>::default();
+        StateTrieCircuit::<Fp>::configure(&mut cs);
+        let reserved = cs.blinding_factors() + 1;
+        assert_eq!(trie.required_rows(), 2);
+        assert_eq!(
+            trie.min_k(),
+            (2 + reserved).next_power_of_two().trailing_zeros()
+        );
+
+        trie.add_ops([account_op(0, 1, 2), account_op(1, 2, 3)]);
+        let op_rows = trie.ops[0].use_rows();
+        assert_eq!(trie.required_rows(), op_rows * 2 + 2);
+        let rows = trie.required_rows() + reserved;
+        assert!(1usize << trie.min_k() >= rows);
+        assert!(1usize << (trie.min_k() - 1) < rows);
+    }
+
+    #[test]
+    fn test_measure_core() {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let hash_tbl = [0; 5].map(|_| cs.advice_column());
+        let config = StateTrieConfig::configure_base(&mut cs, hash_tbl);
+
+        let ops = [account_op(0, 1, 2), account_op(1, 2, 3)];
+        let used = 1 + ops.iter().map(|op| op.use_rows()).sum::<usize>();
+        assert_eq!(config.measure_core(ops.iter(), used + 10), used + 10);
+        assert_eq!(config.measure_core(ops.iter(), used), used);
+        // overflow
+        assert_eq!(config.measure_core(ops.iter(), used - 1), used);
+        assert_eq!(
+            config.measure_core(std::iter::empty::<&AccountOp<Fp>>(), 8),
+            8
+        );
+    }
+
+    #[test]
+    fn test_walk_ops_offsets() {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let hash_tbl = [0; 5].map(|_| cs.advice_column());
+        let config = StateTrieConfig::configure_base(&mut cs, hash_tbl);
+
+        let ops: Vec<_> = (0..100).map(|i| account_op(i, i + 1, i + 2)).collect();
+        let mut offsets = Vec::new();
+        let (end, _) = config
+            .walk_ops(
+                ops.iter(),
+                config.layer.start_offset(),
+                |offset, _, _, rows, _| {
+                    offsets.push((offset, rows));
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        // blocks are laid out back to back from the layer offset
+        let mut next = config.layer.start_offset();
+        for (offset, rows) in offsets {
+            assert_eq!(offset, next);
+            next += rows;
+        }
+        assert_eq!(end, next);
+        assert_eq!(
+            end,
+            1 + ops
+                .iter()
+                .map(|op| config.mode().op_rows(op))
+                .sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn test_walk_ops_root_continuity() {
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let hash_tbl = [0; 5].map(|_| cs.advice_column());
+        let config = StateTrieConfig::configure_base(&mut cs, hash_tbl);
+        assert!(cs
+            .gates()
+            .iter()
+            .any(|gate| gate.name() == "root continuity"));
+
+        let ops: Vec<_> = (0..10).map(|i| account_op(i, i + 1, i + 2)).collect();
+        let mut roots = Vec::new();
+        config
+            .walk_ops(
+                ops.iter(),
+                config.layer.start_offset(),
+                |_, _, _, _, block_roots| {
+                    roots.push(block_roots);
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        assert_eq!(roots.len(), ops.len());
+        for (op, root) in ops.iter().zip(&roots) {
+            assert_eq!(*root, (op.account_root_before(), op.account_root_after()));
+        }
+        for pair in roots.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn test_mode_rows() {
+        let mut op = account_op(0, 1, 2);
+        op.store_key = Some(Default::default());
+        let account_rows = op.use_rows_trie_account();
+        assert_eq!(StateTrieMode::AccountOnly.op_rows(&op), account_rows);
+        assert_eq!(StateTrieMode::Full.op_rows(&op), op.use_rows());
+        assert!(op.use_rows() > account_rows);
+
+        let mut trie = StateTrie::<Fp>::default();
+        trie.add_op(op.clone());
+        assert_eq!(trie.required_rows(), account_rows + 2);
+        let trie = trie.with_mode(StateTrieMode::Full);
+        assert_eq!(trie.required_rows(), op.use_rows() + 2);
+        assert_eq!(trie.circuits(16, &[]).mode, StateTrieMode::Full);
+
+        let mut cs = ConstraintSystem::<Fp>::default();
+        let hash_tbl = [0; 5].map(|_| cs.advice_column());
+        let mpt_tbl = [0; 7].map(|_| cs.advice_column());
+        let config = StateTrieConfig::account_only(&mut cs, mpt_tbl, hash_tbl);
+        assert_eq!(config.mode(), StateTrieMode::AccountOnly);
+        assert_eq!(config.measure_core([&op].into_iter(), 0), 1 + account_rows);
+    }
+
+    #[test]
+    fn test_apply_and_revert() {
+        let mut trie = StateTrie::<Fp>::default();
+        assert!(trie.revert_last().is_none());
+
+        let first = account_op(0, 1, 2);
+        let root = first.account_root_after();
+        assert_eq!(trie.apply(first).unwrap(), root);
+        let speculative = account_op(1, 2, 3);
+        assert_eq!(
+            trie.apply(speculative.clone()).unwrap(),
+            speculative.account_root_after()
+        );
+
+        assert_eq!(trie.revert_last(), Some(speculative));
+        assert_eq!(trie.final_root(), root);
+        assert!(trie.verify_all().is_ok());
+
+        // the start root is kept after reverting every op
+        let start_root = trie.start_root;
+        trie.revert_last().unwrap();
+        assert_eq!(trie.final_root(), start_root);
+        trie.apply(account_op(0, 1, 2)).unwrap();
+        assert_eq!(trie.start_root, start_root);
+        assert!(trie.verify_all().is_ok());
+
+        // an op not continued from the final root is rejected
+        let final_root = trie.final_root();
+        assert!(trie.apply(account_op(5, 6, 7)).is_err());
+        assert_eq!(trie.final_root(), final_root);
+        assert_eq!(trie.get_ops().len(), 1);
+    }
+
+    #[test]
+    fn test_verify_random_ops() {
+        let mut rng = rand_gen([7u8; 32]);
+        let mut trie = StateTrie::<Fp>::default();
+        trie.add_ops(rand_account_ops(&mut rng, 4));
+        assert!(trie.verify_all().is_ok());
+    }
+
+    #[test]
+    fn test_add_ops_complete_traces() {
+        let mut rng = rand_gen([7u8; 32]);
+        let ops = rand_account_ops::<Fp>(&mut rng, 8);
+        let untraced = ops.iter().cloned().map(|mut op| {
+            op.account_before.hash_traces.clear();
+            op.account_after.hash_traces.clear();
+            op
+        });
+
+        let mut trie = StateTrie::<Fp>::default();
+        trie.add_ops(untraced);
+        assert_eq!(trie.get_ops().len(), ops.len());
+        for (added, op) in trie.get_ops().iter().zip(&ops) {
+            assert_eq!(
+                added.account_before.hash_traces,
+                op.account_before.hash_traces
+            );
+            assert_eq!(
+                added.account_after.hash_traces,
+                op.account_after.hash_traces
+            );
+        }
+        assert!(trie.verify_all().is_ok());
+    }
+
+    #[test]
+    fn test_state_trie_circuit() {
+        let mut trie = StateTrie::<Fp>::default();
+        trie.add_ops(rand_account_ops(&mut rand_gen([13u8; 32]), 4));
+        let rows = trie.required_rows();
+        let k = trie.min_k();
+        let circuit = trie.circuits(rows, &[]);
+        let prover = MockProver::<Fp>::run(k, &circuit, vec![]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_num_hash_calcs() {
+        let mut trie = StateTrie::<Fp>::default();
+        assert_eq!(trie.num_hash_calcs(), 0);
+
+        let (op_a, op_b) = (account_op(0, 1, 2), account_op(1, 2, 3));
+        trie.add_ops([op_a.clone(), op_b.clone()]);
+        // the account key is hashed by both states of both ops
+        assert!(op_a.num_hash_calcs() < op_a.hash_traces().count());
+        assert!(trie.num_hash_calcs() < op_a.num_hash_calcs() + op_b.num_hash_calcs());
+        assert!(trie.num_hash_calcs() >= op_a.num_hash_calcs());
+
+        // the hashes of the account trie paths are counted too
+        let acc_trie = SingleOp::<Fp>::create_update_op(
+            3,
+            &[Fp::from(5u64); 3],
+            Fp::from(7u64),
+            (Fp::from(8u64), Fp::from(9u64)),
+        );
+        let op_c = AccountOp {
+            acc_trie: Some(acc_trie.clone()),
+            ..account_op(2, 3, 4)
+        };
+        assert_eq!(
+            op_c.hash_traces().count(),
+            account_op(2, 3, 4).hash_traces().count() + acc_trie.hash_traces().count()
+        );
+        assert!(op_c.num_hash_calcs() > account_op(2, 3, 4).num_hash_calcs());
+
+        let mut without = trie.clone();
+        without.add_ops([account_op(2, 3, 4)]);
+        trie.add_ops([op_c]);
+        assert!(trie.num_hash_calcs() > without.num_hash_calcs());
+    }
+
+    #[test]
+    fn test_touched_addresses() {
+        let mut ops = rand_account_ops::<Fp>(&mut rand_gen([23u8; 32]), 3);
+        let first = ops[0].account_before.address;
+        let second = ops[1].account_before.address;
+        ops[1].account_after.address = Fp::from(7u64);
+        ops[2].account_before.address = first;
+        ops[2].account_after.address = first;
+
+        let mut trie = StateTrie::default();
+        trie.add_ops(ops);
+        assert_eq!(
+            trie.touched_addresses(),
+            vec![first, second, Fp::from(7u64)]
+        );
+        assert!(StateTrie::<Fp>::default().touched_addresses().is_empty());
+    }
+
+    #[test]
+    fn test_empty_root() {
+        let trie = StateTrie::<Fp>::default();
+        assert_eq!(trie.start_root, empty_root());
+        assert_eq!(trie.final_root(), empty_root());
+        assert_eq!(MPTPath::<Fp>::default().root(), empty_root());
+    }
+
+    #[test]
+    fn test_append() {
+        let chunk = |ops: Vec<AccountOp<Fp>>| {
+            let mut trie = StateTrie::<Fp>::default();
+            trie.add_ops(ops);
+            trie
+        };
+        let mut trie = StateTrie::<Fp>::default();
+        trie.append(chunk(vec![account_op(0, 1, 2)])).unwrap();
+        trie.append(StateTrie::default()).unwrap();
+        trie.append(chunk(vec![account_op(1, 2, 3), account_op(2, 3, 4)]))
+            .unwrap();
+        assert_eq!(trie.get_ops().len(), 3);
+        assert_eq!(trie.start_root, Fp::from(1u64));
+        assert_eq!(trie.final_root(), Fp::from(4u64));
+        assert!(trie.verify_all().is_ok());
+
+        let err = trie.append(chunk(vec![account_op(3, 5, 6)])).unwrap_err();
+        assert!(matches!(err, TraceError::DataErr(msg) if msg.contains("unmatch the final root")));
+        assert_eq!(trie.get_ops().len(), 3);
+    }
+
+    #[test]
+    fn test_verify_all() {
+        let mut trie = StateTrie::<Fp>::default();
+        trie.add_ops([account_op(0, 1, 2), account_op(1, 2, 3)]);
+        assert!(trie.verify_all().is_ok());
+
+        let mut broken = trie.clone();
+        broken.ops.push(account_op(2, 5, 6));
+        assert!(broken.verify_all().is_err());
+
+        let mut tampered = trie;
+        tampered.ops[1].account_after.nonce = Fp::from(9u64);
+        assert!(tampered.verify_all().is_err());
+    }
 }
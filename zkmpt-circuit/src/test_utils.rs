@@ -1,11 +1,14 @@
 pub use halo2_proofs::halo2curves::bn256::Fr as Fp;
 use halo2_proofs::{
     arithmetic::Field,
+    dev::{MockProver, VerifyFailure},
     halo2curves::{group::ff::PrimeField, FieldExt},
+    plonk::Circuit,
 };
+use hash_circuit::Hashable;
 use lazy_static::lazy_static;
 use num_traits::Num;
-use rand::{random, SeedableRng};
+use rand::{random, RngCore, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 use std::{i64, str::FromStr};
 
@@ -16,6 +19,8 @@ lazy_static! {
 
 use num_bigint::BigInt;
 
+use crate::operation::{Account, AccountOp};
+
 pub fn hash_str_to_fp(hash_str: &str) -> Fp {
     let hash_int = BigInt::from_str_radix(&hash_str.trim_start_matches("0x"), 16)
         .unwrap()
@@ -42,3 +47,95 @@ pub fn rand_fp() -> Fp {
 pub fn mock_hash(a: &Fp, b: &Fp) -> Fp {
     (a + *GAMMA) * (b + *GAMMA)
 }
+
+/// run the mock prover over the circuit and panic if it is not satisfied,
+/// with one line for each failure naming the gate, lookup or column and
+/// where it fails, instead of the debug dump of all failures
+pub fn assert_circuit_satisfied<C: Circuit<Fp>>(k: u32, circuit: &C, instances: Vec<Vec<Fp>>) {
+    let prover = MockProver::<Fp>::run(k, circuit, instances).expect("circuit is synthesized");
+    if let Err(failures) = prover.verify() {
+        let lines: Vec<_> = failures.iter().map(describe_failure).collect();
+        panic!(
+            "circuit is not satisfied, {} failures:\n{}",
+            failures.len(),
+            lines.join("\n")
+        );
+    }
+}
+
+fn describe_failure(failure: &VerifyFailure) -> String {
+    match failure {
+        VerifyFailure::ConstraintNotSatisfied {
+            constraint,
+            location,
+            ..
+        } => format!("{constraint} is not satisfied {location}"),
+        VerifyFailure::Lookup { name, location, .. } => {
+            format!("lookup '{name}' is not satisfied {location}")
+        }
+        VerifyFailure::Permutation { column, location } => {
+            format!("copy of column {column:?} is not satisfied {location}")
+        }
+        other => other.to_string(),
+    }
+}
+
+impl<Fp: Hashable> AccountOp<Fp> {
+    /// a random but self-consistent transition of one account, see
+    /// `random_from_root`
+    pub fn random(rng: &mut impl RngCore) -> Self {
+        let root_before = Fp::random(&mut *rng);
+        Self::random_from_root(rng, root_before)
+    }
+
+    /// a random transition starting from `root_before`: the nonce is increased
+    /// by one, some gas is deducted, the tx hash is chained and the root is
+    /// moved to a random one, both accounts carry valid hash traces
+    pub fn random_from_root(rng: &mut impl RngCore, root_before: Fp) -> Self {
+        let hasher = |a: &Fp, b: &Fp| <Fp as Hashable>::hash([*a, *b]);
+        let nonce = rng.next_u32() as u64;
+        let gas_balance = rng.next_u32() as u64 + 1;
+        let gas_used = rng.next_u64() % gas_balance;
+
+        let account_before = Account {
+            address: Fp::from(rng.next_u64()),
+            pub_key: Fp::random(&mut *rng),
+            nonce: Fp::from(nonce),
+            gas_balance: Fp::from(gas_balance),
+            tx_hash: Fp::random(&mut *rng),
+            pre_recrusive_tx_hash: Fp::random(&mut *rng),
+            state_root: root_before,
+            ..Default::default()
+        }
+        .trace(hasher);
+
+        let account_after = Account {
+            nonce: Fp::from(nonce + 1),
+            gas_balance: Fp::from(gas_balance - gas_used),
+            tx_hash: Fp::random(&mut *rng),
+            pre_recrusive_tx_hash: account_before.recrusive_tx_hash,
+            state_root: Fp::random(&mut *rng),
+            hash_traces: Vec::new(),
+            ..account_before.clone()
+        }
+        .trace(hasher);
+
+        Self {
+            account_before,
+            account_after,
+            ..Default::default()
+        }
+    }
+}
+
+/// a sequence of random ops with continuous roots
+pub fn rand_account_ops<Fp: Hashable>(rng: &mut impl RngCore, n: usize) -> Vec<AccountOp<Fp>> {
+    let mut root = Fp::random(&mut *rng);
+    (0..n)
+        .map(|_| {
+            let op = AccountOp::random_from_root(rng, root);
+            root = op.account_root_after();
+            op
+        })
+        .collect()
+}
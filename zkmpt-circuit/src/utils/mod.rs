@@ -1 +1,196 @@
+use std::path::Path;
+
+use halo2_proofs::{
+    halo2curves::bn256::Fr,
+    plonk::{Circuit, ConstraintSystem},
+};
+
+use crate::{operation::TraceError, serde::BlockResult, state_trie::StateTrie};
+
 pub mod spec256k1;
+
+/// the proof of a batch along with the instances it is verified against
+#[cfg(feature = "live-rpc")]
+#[derive(Clone, Debug)]
+pub struct Proof {
+    /// the proof bytes
+    pub proof: Vec<u8>,
+    /// the public inputs of the proof
+    pub instances: Vec<Vec<Fr>>,
+}
+
+/// fetch the batch of `block_number` from the bundler at `rpc_url`, build the
+/// circuit and prove it with the setup under `setup_path`
+#[cfg(feature = "live-rpc")]
+pub async fn prove_block(
+    setup_path: &str,
+    rpc_url: &str,
+    block_number: u64,
+) -> Result<Proof, crate::manager::ManagerError> {
+    use crate::manager::{Manager, ManagerError};
+
+    let manager = Manager::new(setup_path.to_string(), rpc_url.to_string())?;
+    let batch = manager
+        .fetch_block_batch(block_number)
+        .await?
+        .ok_or(ManagerError::NoBatch(block_number))?;
+    if !batch.verify_batch_hash() {
+        return Err(ManagerError::BatchHash(batch.batch_hash));
+    }
+    let (proof, instances) = manager.generate_proof(&batch.tx_list)?;
+    Ok(Proof { proof, instances })
+}
+
+/// the size figures of a circuit, collected from its constraint system
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CircuitStats {
+    /// degree of the constraint system
+    pub degree: usize,
+    /// number of advice columns
+    pub advice_columns: usize,
+    /// number of fixed columns
+    pub fixed_columns: usize,
+    /// number of instance columns
+    pub instance_columns: usize,
+    /// number of selectors (before they are compressed into fixed columns)
+    pub selectors: usize,
+    /// number of lookup arguments
+    pub lookups: usize,
+    /// rows can be assigned under `k`, excluding the blinding rows
+    pub usable_rows: usize,
+}
+
+/// configure the circuit in a throwaway constraint system and report its size
+/// figures under `k`
+pub fn report_circuit<C: Circuit<Fr>>(_circuit: &C, k: u32) -> CircuitStats {
+    let mut cs = ConstraintSystem::<Fr>::default();
+    C::configure(&mut cs);
+
+    CircuitStats {
+        degree: cs.degree(),
+        advice_columns: cs.num_advice_columns(),
+        fixed_columns: cs.num_fixed_columns(),
+        instance_columns: cs.num_instance_columns(),
+        selectors: cs.num_selectors(),
+        lookups: cs.lookups().len(),
+        usable_rows: (1usize << k).saturating_sub(cs.minimum_rows()),
+    }
+}
+
+/// render the layout of the circuit under `k` into the png at `path`, the
+/// parent directories are created if missing
+#[cfg(feature = "print_layout")]
+pub fn plot_layout<C: Circuit<Fr>>(
+    circuit: &C,
+    k: u32,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use plotters::prelude::{BitMapBackend, IntoDrawingArea, WHITE};
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let root = BitMapBackend::new(path, (1024, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let root = root.titled(&title, ("sans-serif", 60))?;
+    halo2_proofs::dev::CircuitLayout::default().render(k, circuit, &root)?;
+    root.present()?;
+    Ok(())
+}
+
+/// read the `BlockResult` in the trace file at `path` and build the state trie
+/// of its ops, see `BlockResult::state_trie`
+pub fn validate_trace_file(path: &Path) -> Result<StateTrie<Fr>, TraceError> {
+    let buffer = std::fs::read(path).map_err(TraceError::DeErr)?;
+    let block = serde_json::from_slice::<BlockResult>(&buffer)
+        .map_err(|e| TraceError::DataErr(format!("{}: {e}", path.display())))?;
+    block.state_trie()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gadgets::hash_util::HashCircuit;
+
+    #[test]
+    fn report_hash_circuit() {
+        let stats = report_circuit(&HashCircuit::<Fr>::new(3, &[]), 7);
+        assert!(stats.advice_columns >= 5);
+        assert_eq!(stats.instance_columns, 0);
+        assert!(stats.degree > 1);
+        assert!(stats.usable_rows > 0 && stats.usable_rows < 1 << 7);
+    }
+
+    #[cfg(feature = "print_layout")]
+    #[test]
+    fn plot_hash_circuit_layout() {
+        let path = std::env::temp_dir().join("layouts/hash_circuit_layout.png");
+        plot_layout(&HashCircuit::<Fr>::new(3, &[]), 7, &path).unwrap();
+        assert!(path.exists());
+    }
+
+    // require a bundler serving the batch, e.g.
+    // LIVE_RPC_URL=<url> LIVE_BLOCK=<number> cargo test --features live-rpc -- --ignored
+    #[cfg(feature = "live-rpc")]
+    #[tokio::test]
+    #[ignore]
+    async fn prove_live_block() {
+        let rpc_url = std::env::var("LIVE_RPC_URL").expect("LIVE_RPC_URL is set");
+        let block_number = std::env::var("LIVE_BLOCK")
+            .expect("LIVE_BLOCK is set")
+            .parse()
+            .unwrap();
+        let setup_path = std::env::var("SETUP_PATH").unwrap_or(String::from("/data/setup/"));
+
+        let proof = prove_block(&setup_path, &rpc_url, block_number)
+            .await
+            .unwrap();
+        assert!(!proof.proof.is_empty());
+        assert_eq!(proof.instances.len(), 1);
+    }
+
+    #[test]
+    fn validate_trace_file_errors() {
+        // a directory of its own, so parallel runs do not share the files
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let root = |byte: u8| format!("0x{}", format!("{byte:02x}").repeat(32));
+
+        assert!(matches!(
+            validate_trace_file(&dir.join("no_such_trace.json")),
+            Err(TraceError::DeErr(_))
+        ));
+
+        let malformed = write("malformed_trace.json", "{\"startMptRoot\": 1}");
+        assert!(matches!(
+            validate_trace_file(&malformed),
+            Err(TraceError::DataErr(_))
+        ));
+
+        let block = |start: u8, end: u8| {
+            format!(
+                r#"{{"startMptRoot": "{}", "endMptRoot": "{}", "mptTransTrace": []}}"#,
+                root(start),
+                root(end)
+            )
+        };
+        let unmatched = write("unmatched_trace.json", &block(1, 2));
+        assert!(matches!(
+            validate_trace_file(&unmatched),
+            Err(TraceError::DataErr(_))
+        ));
+
+        let empty = write("empty_trace.json", &block(1, 1));
+        assert!(validate_trace_file(&empty).unwrap().get_ops().is_empty());
+    }
+}
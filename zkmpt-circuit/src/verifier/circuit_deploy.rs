@@ -1,6 +1,6 @@
 use halo2_proofs::{
     halo2curves::pairing::{Engine, MultiMillerLoop},
-    halo2curves::{serde::SerdeObject, bn256::Bn256},
+    halo2curves::{serde::SerdeObject, bn256::{Bn256, G1Affine}},
     plonk::{keygen_pk, keygen_vk, Circuit, Error, ProvingKey, VerifyingKey},
     poly::{commitment::Params, kzg::commitment::ParamsKZG},
     SerdeFormat,
@@ -13,9 +13,10 @@ use std::{
     fmt::Debug,
     io::{Cursor, Read},
     path::PathBuf,
+    time::Instant,
 };
 
-use crate::zkprover_circuit::ZkProverCircuit;
+use crate::{manager::TX_NUM, zkprover_circuit::ZkProverCircuit};
 
 pub fn init_trusted_setup(
     circuit_k: u32, 
@@ -24,7 +25,7 @@ pub fn init_trusted_setup(
 ) 
 {
     let params = ParamsKZG::<Bn256>::setup(circuit_k, OsRng);
-    let zkprover_circuit_instance = ZkProverCircuit::<Bn256Fr, 128>::random();
+    let zkprover_circuit_instance = ZkProverCircuit::<Bn256Fr, TX_NUM>::fixture_circuit();
 
     let vk = keygen_vk(&params, &zkprover_circuit_instance).expect("keygen_vk should not fail");
 
@@ -37,12 +38,37 @@ pub fn init_trusted_setup(
 
     {
         folder.push(format!("sample_circuit_{}.vkey", &circuit_name));
-        let mut fd = std::fs::File::create(folder.as_path()).unwrap();
+        std::fs::write(folder.as_path(), vk_to_bytes(&vk)).unwrap();
         folder.pop();
-        vk.write(&mut fd, SerdeFormat::Processed).unwrap();
     }
 }
 
+/// the format of the vk files, shared by `export_vk` and `import_vk`
+const VK_FORMAT: SerdeFormat = SerdeFormat::Processed;
+
+fn vk_to_bytes(vk: &VerifyingKey<G1Affine>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    vk.write(&mut buf, VK_FORMAT)
+        .expect("write to vec should not fail");
+    buf
+}
+
+/// run keygen for `circuit` and serialize its verifying key, which can be
+/// shipped as the `.vkey` file of a target circuit
+pub fn export_vk<C: Circuit<Bn256Fr>>(
+    params: &ParamsKZG<Bn256>,
+    circuit: &C,
+) -> Result<Vec<u8>, Error> {
+    let vk = keygen_vk(params, circuit)?;
+    Ok(vk_to_bytes(&vk))
+}
+
+/// load a verifying key from the bytes of `export_vk` without keygen, `C`
+/// must be the circuit type the vk was exported from
+pub fn import_vk<C: Circuit<Bn256Fr>>(bytes: &[u8]) -> std::io::Result<VerifyingKey<G1Affine>> {
+    VerifyingKey::read::<_, C>(&mut Cursor::new(bytes), VK_FORMAT)
+}
+
 /// Ported from https://github.com/scroll-tech/halo2-snark-aggregator/blob/main/halo2-snark-aggregator-circuit/src/sample_circuit.rs
 
 pub trait TargetCircuit {
@@ -128,17 +154,16 @@ pub fn load_target_circuit_vk< Circuit: TargetCircuit>(
 ) -> VerifyingKey<<Bn256 as Engine>::G1Affine>
 {
 
-    let zkprover_circuit_instance = ZkProverCircuit::<Bn256Fr, 128>::random();
+    let zkprover_circuit_instance = ZkProverCircuit::<Bn256Fr, TX_NUM>::fixture_circuit();
     
     if Circuit::READABLE_VKEY {
-        VerifyingKey::<<Bn256 as Engine>::G1Affine>::read::<_, ZkProverCircuit::<Bn256Fr, 128>>(
-            &mut Cursor::new(&read_target_circuit_vk::<Bn256, Circuit>(&mut folder.clone())),
-            SerdeFormat::Processed,
-        )
+        import_vk::<ZkProverCircuit<Bn256Fr, TX_NUM>>(&read_target_circuit_vk::<Bn256, Circuit>(
+            &mut folder.clone(),
+        ))
         .unwrap()
     } else {
 
-        keygen_vk::<<Bn256 as Engine>::G1Affine, _,ZkProverCircuit::<Bn256Fr, 128>>(params, &zkprover_circuit_instance)
+        keygen_vk::<<Bn256 as Engine>::G1Affine, _,ZkProverCircuit::<Bn256Fr, TX_NUM>>(params, &zkprover_circuit_instance)
             .expect("keygen_vk should not fail")
     }
 }
@@ -152,8 +177,65 @@ where
     E::G1Affine: SerdeObject,
     E::G2Affine: SerdeObject,
 {
+    let _span = tracing::info_span!("keygen", k = params.k()).entered();
+
+    let start = Instant::now();
     let vk = keygen_vk::<<E as Engine>::G1Affine, ParamsKZG<E>, _>(params, &circuit)?;
+    tracing::info!(elapsed = ?start.elapsed(), "vk generated");
     let pk = keygen_pk::<<E as Engine>::G1Affine, ParamsKZG<E>, _>(params, vk, &circuit)?;
+    tracing::info!(elapsed = ?start.elapsed(), "pk generated");
 
     Ok(pk)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{export_vk, import_vk, keygen};
+    use crate::{
+        test_utils::Fp,
+        verifier::{
+            evm_verify, evm_verify_with_gas_limit, gen_evm_verifier, gen_proof, num_instance,
+            VerifierError,
+        },
+        zkprover_circuit::ZkProverCircuit,
+    };
+    use halo2_proofs::{halo2curves::bn256::Bn256, poly::kzg::commitment::ParamsKZG};
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_vk_round_trip() {
+        let params = ParamsKZG::<Bn256>::setup(7, OsRng);
+        let circuit = ZkProverCircuit::<Fp, 1>::default();
+
+        let vk_bytes = export_vk(&params, &circuit).unwrap();
+        let vk = import_vk::<ZkProverCircuit<Fp, 1>>(&vk_bytes).unwrap();
+
+        let pk = keygen(&params, circuit.clone()).unwrap();
+        assert_eq!(vk.transcript_repr(), pk.get_vk().transcript_repr());
+        let instances = circuit.instances();
+        let proof = gen_proof(&params, &pk, circuit, instances.clone()).unwrap();
+        let deployment_code = gen_evm_verifier(&params, &vk, num_instance(&instances)).unwrap();
+        let verification =
+            evm_verify(deployment_code.clone(), instances.clone(), proof.clone()).unwrap();
+        assert!(verification.gas_used > 0);
+
+        // the limit only applies to the verifying call, not the deployment
+        let gas_used = verification.gas_used;
+        assert_eq!(
+            evm_verify_with_gas_limit(
+                deployment_code.clone(),
+                instances.clone(),
+                proof.clone(),
+                gas_used
+            )
+            .unwrap()
+            .gas_used,
+            gas_used
+        );
+        // not enough gas for the verifier
+        assert!(matches!(
+            evm_verify_with_gas_limit(deployment_code, instances, proof, gas_used - 1),
+            Err(VerifierError::Revert(_))
+        ));
+    }
+}
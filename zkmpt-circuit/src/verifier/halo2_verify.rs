@@ -6,7 +6,7 @@ use halo2_proofs::plonk::{
 use halo2_proofs::poly::commitment::{Params, ParamsProver};
 use halo2_proofs::poly::kzg::commitment::KZGCommitmentScheme;
 use halo2_proofs::poly::kzg::multiopen::{ProverGWC, VerifierGWC};
-use halo2_proofs::poly::kzg::strategy::AccumulatorStrategy;
+use halo2_proofs::poly::kzg::strategy::{AccumulatorStrategy, SingleStrategy};
 use halo2_proofs::poly::VerificationStrategy;
 use halo2_proofs::transcript::{TranscriptReadBuffer, TranscriptWriterBuffer};
 use halo2_proofs::{
@@ -35,6 +35,8 @@ use hex::encode;
 
 use serde_json::json;
 
+use super::VerifierError;
+
 #[derive(Clone)]
 pub(crate) struct Number<F: FieldExt>(AssignedCell<F, F>);
 
@@ -193,6 +195,19 @@ impl<F: FieldExt> Circuit<F> for FiboCircuit<F> {
         mut layouter: impl Layouter<F>,
     ) -> Result<(), Error> {
         let chip = FiboChip::construct(config);
+        let (_, c) = self.assign_seq(&chip, &mut layouter)?;
+        chip.expose_public(layouter.namespace(|| "expose c"), c, 0)?;
+        Ok(())
+    }
+}
+
+impl<F: FieldExt> FiboCircuit<F> {
+    // assign the sequence and return its last two numbers
+    fn assign_seq(
+        &self,
+        chip: &FiboChip<F>,
+        layouter: &mut impl Layouter<F>,
+    ) -> Result<(Number<F>, Number<F>), Error> {
         let (_, mut b, mut c) =
             chip.load_first_row(layouter.namespace(|| "first row"), self.a, self.b)?;
         for _ in 3..self.num {
@@ -200,8 +215,39 @@ impl<F: FieldExt> Circuit<F> for FiboCircuit<F> {
             b = c;
             c = new_c;
         }
+        Ok((b, c))
+    }
+}
+
+/// `FiboCircuit` exposing the last number in the first instance column and the
+/// one before in a second column
+#[derive(Default, Clone)]
+struct FiboPairCircuit<F>(FiboCircuit<F>);
+
+impl<F: FieldExt> Circuit<F> for FiboPairCircuit<F> {
+    type Config = (FiboConfig, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let config = FiboChip::configure(meta);
+        let prev = meta.instance_column();
+        meta.enable_equality(prev);
+        (config, prev)
+    }
+
+    fn synthesize(
+        &self,
+        (config, prev): Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let chip = FiboChip::construct(config);
+        let (b, c) = self.0.assign_seq(&chip, &mut layouter)?;
         chip.expose_public(layouter.namespace(|| "expose c"), c, 0)?;
-        Ok(())
+        layouter.constrain_instance(b.0.cell(), prev, 0)
     }
 }
 
@@ -215,6 +261,30 @@ fn get_fibo_seq(a: u64, b: u64, num: usize) -> Vec<u64> {
     seq
 }
 
+/// verify a proof of `gen_proof` with the native halo2 verifier, the off-chain
+/// counterpart of `evm_verify` which needs no yul deployment
+pub fn verify(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    instances: &[Vec<Fp>],
+    proof: &[u8],
+) -> Result<(), VerifierError> {
+    let instances = instances
+        .iter()
+        .map(|instances| instances.as_slice())
+        .collect_vec();
+
+    let mut transcript = TranscriptReadBuffer::<_, G1Affine, _>::init(proof);
+    verify_proof::<KZGCommitmentScheme<Bn256>, VerifierGWC<_>, _, EvmTranscript<_, _, _, _>, _>(
+        params,
+        vk,
+        SingleStrategy::new(params),
+        &[instances.as_slice()],
+        &mut transcript,
+    )
+    .map_err(VerifierError::Verify)
+}
+
 pub fn encode_calldata_json<F>(instances: &[Vec<F>], proof: &[u8]) -> Vec<u8>
 where
     F: PrimeField<Repr = [u8; 32]>,
@@ -270,8 +340,9 @@ mod tests {
     use halo2_proofs::dev::MockProver;
 
     use crate::verifier::{
-        evm_verify, gen_evm_verifier, gen_pk, gen_proof, gen_srs,
-        halo2_verify::{get_fibo_seq, FiboCircuit},
+        build_calldata, decode_calldata, evm_verify, gen_evm_verifier, gen_pk, gen_proof, gen_srs,
+        halo2_verify::{get_fibo_seq, verify, FiboCircuit, FiboPairCircuit},
+        num_instance, VerifierError,
     };
     use crate::{
         test_utils::{hash_str_to_fp, Fp},
@@ -281,6 +352,69 @@ mod tests {
     #[test]
     fn gen_params() {}
 
+    #[test]
+    fn test_native_verify() {
+        let params = gen_srs(10);
+
+        let num = 14;
+        let seq = get_fibo_seq(1, 1, num);
+        let res = Fp::from(seq[num - 1]);
+        let fibo_circuit = FiboCircuit {
+            a: Fp::from(seq[0]),
+            b: Fp::from(seq[1]),
+            num,
+        };
+        let pk = gen_pk(&params, &fibo_circuit).unwrap();
+        let proof = gen_proof(&params, &pk, fibo_circuit, vec![vec![res]]).unwrap();
+
+        verify(&params, pk.get_vk(), &[vec![res]], &proof).unwrap();
+        assert!(matches!(
+            verify(&params, pk.get_vk(), &[vec![res + Fp::one()]], &proof),
+            Err(VerifierError::Verify(_))
+        ));
+    }
+
+    #[test]
+    fn test_two_instance_columns() {
+        let params = gen_srs(10);
+
+        let num = 14;
+        let seq = get_fibo_seq(1, 1, num);
+        let circuit = FiboPairCircuit(FiboCircuit {
+            a: Fp::from(seq[0]),
+            b: Fp::from(seq[1]),
+            num,
+        });
+        let instances = vec![vec![Fp::from(seq[num - 1])], vec![Fp::from(seq[num - 2])]];
+        assert_eq!(num_instance(&instances), vec![1, 1]);
+
+        let pk = gen_pk(&params, &circuit).unwrap();
+        let proof = gen_proof(&params, &pk, circuit, instances.clone()).unwrap();
+        verify(&params, pk.get_vk(), &instances, &proof).unwrap();
+
+        let (decoded, _) = decode_calldata(
+            &build_calldata(&instances, &proof),
+            &num_instance(&instances),
+        )
+        .unwrap();
+        assert_eq!(decoded, instances);
+
+        let deployment_code =
+            gen_evm_verifier(&params, pk.get_vk(), num_instance(&instances)).unwrap();
+        evm_verify(deployment_code.clone(), instances.clone(), proof.clone()).unwrap();
+
+        // the columns are bound in order
+        let swapped = vec![instances[1].clone(), instances[0].clone()];
+        assert!(matches!(
+            verify(&params, pk.get_vk(), &swapped, &proof),
+            Err(VerifierError::Verify(_))
+        ));
+        assert!(matches!(
+            evm_verify(deployment_code, swapped, proof),
+            Err(VerifierError::Revert(_))
+        ));
+    }
+
     #[test]
     fn test_verifier() {
         let params = gen_srs(10);
@@ -296,22 +430,22 @@ mod tests {
             b: Fp::from(seq[1]),
             num,
         };
-        let pk = gen_pk(&params, &fibo_circuit);
+        let pk = gen_pk(&params, &fibo_circuit).unwrap();
 
         // Arrange the public input. We expose the multiplication result in row 0
         // of the instance column, so we position it there in our public inputs.
         let mut public_inputs = vec![res];
-        let deployment_code = gen_evm_verifier(&params, pk.get_vk(), vec![1]);
+        let deployment_code = gen_evm_verifier(&params, pk.get_vk(), vec![1]).unwrap();
 
         let mut instances = vec![vec![res]];
 
-        let proof = gen_proof(&params, &pk, fibo_circuit.clone(), vec![vec![res]]);
+        let proof = gen_proof(&params, &pk, fibo_circuit.clone(), vec![vec![res]]).unwrap();
 
         // println!("final proof {:?}", &proof);
 
         encode_calldata_json(&instances, &proof);
 
-        evm_verify(deployment_code, vec![vec![res]], proof);
+        evm_verify(deployment_code, vec![vec![res]], proof).unwrap();
 
         // Set circuit size
         let k = 10;
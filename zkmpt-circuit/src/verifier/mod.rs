@@ -1,7 +1,11 @@
+use ethers::types::H256;
 use halo2_proofs::{
     dev::MockProver,
-    halo2curves::bn256::{Bn256, Fq, Fr, G1Affine},
-    plonk::{create_proof, keygen_pk, keygen_vk, Circuit, ProvingKey, VerifyingKey},
+    halo2curves::{
+        bn256::{Bn256, Fq, Fr, G1Affine},
+        group::ff::PrimeField,
+    },
+    plonk::{create_proof, keygen_pk, keygen_vk, Circuit, Error, ProvingKey, VerifyingKey},
     poly::{
         commitment::{Params, ParamsProver},
         kzg::{
@@ -19,12 +23,14 @@ use snark_verifier::{
     system::halo2::{compile, transcript::evm::EvmTranscript, Config},
     verifier::{self, SnarkVerifier},
 };
-use std::rc::Rc;
+use std::{rc::Rc, time::Instant};
 
 use bytes::Bytes;
 use std::fs::File;
 use std::io::prelude::*;
 
+use crate::zkprover_circuit::public_inputs;
+
 pub mod circuit_deploy;
 pub mod halo2_verify;
 /// Halo2 loader
@@ -32,13 +38,39 @@ pub mod loader;
 
 type PlonkVerifier = verifier::plonk::PlonkVerifier<KzgAs<Bn256, Gwc19>>;
 
+#[derive(Debug, thiserror::Error)]
+/// errors raised in proving and verifying
+pub enum VerifierError {
+    #[error("keygen error: {0}")]
+    /// failed to generate the vk or pk
+    Keygen(Error),
+    #[error("proof generation error: {0}")]
+    /// the circuit is not satisfied or the proof can not be created
+    ProofGen(String),
+    #[error("verifier deployment error: {0}")]
+    /// the evm verifier can not be generated or deployed
+    Deploy(String),
+    #[error("proof verification reverted: {0}")]
+    /// the evm verifier rejects the proof
+    Revert(String),
+    #[error("proof verification failed: {0}")]
+    /// the native verifier rejects the proof
+    Verify(Error),
+    #[error("io error: {0}")]
+    /// error raised by file access
+    Io(#[from] std::io::Error),
+}
+
 fn gen_srs(k: u32) -> ParamsKZG<Bn256> {
     ParamsKZG::<Bn256>::setup(k, OsRng)
 }
 
-fn gen_pk<C: Circuit<Fr>>(params: &ParamsKZG<Bn256>, circuit: &C) -> ProvingKey<G1Affine> {
-    let vk = keygen_vk(params, circuit).unwrap();
-    keygen_pk(params, vk, circuit).unwrap()
+fn gen_pk<C: Circuit<Fr>>(
+    params: &ParamsKZG<Bn256>,
+    circuit: &C,
+) -> Result<ProvingKey<G1Affine>, VerifierError> {
+    let vk = keygen_vk(params, circuit).map_err(VerifierError::Keygen)?;
+    keygen_pk(params, vk, circuit).map_err(VerifierError::Keygen)
 }
 
 // Generate Proof
@@ -47,10 +79,22 @@ pub fn gen_proof<C: Circuit<Fr>>(
     pk: &ProvingKey<G1Affine>,
     circuit: C,
     instances: Vec<Vec<Fr>>,
-) -> Vec<u8> {
+) -> Result<Vec<u8>, VerifierError> {
+    let _span = tracing::info_span!(
+        "gen_proof",
+        k = params.k(),
+        num_instance = instances.iter().map(Vec::len).sum::<usize>()
+    )
+    .entered();
+
+    let start = Instant::now();
     MockProver::run(params.k(), &circuit, instances.clone())
-        .unwrap()
-        .assert_satisfied();
+        .map_err(|e| VerifierError::ProofGen(e.to_string()))?
+        .verify()
+        .map_err(|failures| {
+            VerifierError::ProofGen(format!("circuit is not satisfied: {failures:?}"))
+        })?;
+    tracing::info!(elapsed = ?start.elapsed(), "witness assigned");
 
     let instances = instances
         .iter()
@@ -66,18 +110,42 @@ pub fn gen_proof<C: Circuit<Fr>>(
             OsRng,
             &mut transcript,
         )
-        .unwrap();
+        .map_err(|e| VerifierError::ProofGen(e.to_string()))?;
         transcript.finalize()
     };
+    tracing::info!(elapsed = ?start.elapsed(), "proof created");
 
-    proof
+    Ok(proof)
+}
+
+/// verify a `ZkProverCircuit` proof with the native verifier against the
+/// expected roots and gas sum, a cheap check before submitting it on-chain;
+/// it only checks the proof was made for these public inputs, the circuit
+/// does not constrain the roots against the txs
+pub fn verify_state_transition(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    proof: &[u8],
+    root_before: H256,
+    root_after: H256,
+    gas_sum: Fr,
+) -> Result<(), VerifierError> {
+    let instances = public_inputs(&root_before, &root_after, gas_sum);
+    halo2_verify::verify(params, vk, &instances, proof)
+}
+
+/// the number of instances in each column of `instances`, i.e. the
+/// `num_instance` for `gen_evm_verifier` and `decode_calldata`, in which the
+/// columns are laid out in order
+pub fn num_instance(instances: &[Vec<Fr>]) -> Vec<usize> {
+    instances.iter().map(Vec::len).collect()
 }
 
 pub fn gen_evm_verifier(
     params: &ParamsKZG<Bn256>,
     vk: &VerifyingKey<G1Affine>,
     num_instance: Vec<usize>,
-) -> Vec<u8> {
+) -> Result<Vec<u8>, VerifierError> {
     let protocol = compile(
         params,
         vk,
@@ -91,20 +159,129 @@ pub fn gen_evm_verifier(
     let instances = transcript.load_instances(num_instance);
     let vk = (params.get_g()[0], params.g2(), params.s_g2()).into();
 
-    let proof = PlonkVerifier::read_proof(&vk, &protocol, &instances, &mut transcript).unwrap();
-    PlonkVerifier::verify(&vk, &protocol, &instances, &proof).unwrap();
+    let proof = PlonkVerifier::read_proof(&vk, &protocol, &instances, &mut transcript)
+        .map_err(|e| VerifierError::Deploy(format!("{e:?}")))?;
+    PlonkVerifier::verify(&vk, &protocol, &instances, &proof)
+        .map_err(|e| VerifierError::Deploy(format!("{e:?}")))?;
     // print!("yul code: {:}", &loader.yul_code());
 
-    let mut file = File::create("zkpVerifier.yul").unwrap();
-    file.write_all(&loader.yul_code().as_bytes()).unwrap();
+    let mut file = File::create("zkpVerifier.yul")?;
+    file.write_all(&loader.yul_code().as_bytes())?;
     // let file = File::create("t").unwrap();
     // file.write(file.to).unwrap();
 
-    evm::compile_yul(&loader.yul_code())
+    Ok(evm::compile_yul(&loader.yul_code()))
+}
+
+/// a Solidity contract deploying the verifier of `gen_evm_verifier` in its
+/// constructor, whose `verifyProof` takes the calldata of `build_calldata`
+/// with `num_instance` instances in each column
+pub fn gen_solidity_verifier(
+    params: &ParamsKZG<Bn256>,
+    vk: &VerifyingKey<G1Affine>,
+    num_instance: Vec<usize>,
+) -> Result<String, VerifierError> {
+    let deployment_code = gen_evm_verifier(params, vk, num_instance.clone())?;
+    Ok(solidity_wrapper(
+        &deployment_code,
+        num_instance.iter().sum::<usize>(),
+    ))
 }
 
-pub fn evm_verify(deployment_code: Vec<u8>, instances: Vec<Vec<Fr>>, proof: Vec<u8>) {
-    let calldata = encode_calldata(&instances, &proof);
+fn solidity_wrapper(deployment_code: &[u8], num_instances: usize) -> String {
+    format!(
+        r#"// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+contract ZkProverVerifier {{
+    uint256 public constant NUM_INSTANCES = {num_instances};
+    address public immutable verifier;
+
+    constructor() {{
+        bytes memory code = hex"{code}";
+        address deployed;
+        assembly {{
+            deployed := create(0, add(code, 0x20), mload(code))
+        }}
+        require(deployed != address(0), "verifier deployment failed");
+        verifier = deployed;
+    }}
+
+    /// `proof` is the instances as 32-byte big-endian words followed by the proof
+    function verifyProof(bytes calldata proof) external view returns (bool) {{
+        if (proof.length < NUM_INSTANCES * 32) {{
+            return false;
+        }}
+        (bool success, ) = verifier.staticcall(proof);
+        return success;
+    }}
+}}
+"#,
+        code = hex::encode(deployment_code),
+    )
+}
+
+/// the calldata to send to the deployed verifier: each instance as a 32-byte
+/// big-endian word, followed by the proof
+pub fn build_calldata(instances: &[Vec<Fr>], proof: &[u8]) -> Vec<u8> {
+    encode_calldata(instances, proof)
+}
+
+/// split the calldata of `build_calldata` back into the instances, with
+/// `num_instance` words in each column, and the proof; None if the calldata
+/// is too short or a word is not a field element
+pub fn decode_calldata(calldata: &[u8], num_instance: &[usize]) -> Option<(Vec<Vec<Fr>>, Vec<u8>)> {
+    let words = num_instance.iter().sum::<usize>();
+    if calldata.len() < words * 32 {
+        return None;
+    }
+    let (words_bytes, proof) = calldata.split_at(words * 32);
+    let mut words_bytes = words_bytes.chunks_exact(32);
+    let instances = num_instance
+        .iter()
+        .map(|&n| {
+            words_bytes
+                .by_ref()
+                .take(n)
+                .map(|word| {
+                    let mut repr = [0u8; 32];
+                    repr.copy_from_slice(word);
+                    repr.reverse();
+                    Option::from(Fr::from_repr(repr))
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((instances, proof.to_vec()))
+}
+
+/// the outcome of a verifier call which has not reverted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvmVerification {
+    /// the gas used by calling the deployed verifier
+    pub gas_used: u64,
+}
+
+/// deploy the verifier and call it with the proof, without any gas limit
+pub fn evm_verify(
+    deployment_code: Vec<u8>,
+    instances: Vec<Vec<Fr>>,
+    proof: Vec<u8>,
+) -> Result<EvmVerification, VerifierError> {
+    evm_verify_with_gas_limit(deployment_code, instances, proof, u64::MAX)
+}
+
+/// like `evm_verify` but the verifying call is limited to `gas_limit` (e.g.
+/// the block gas limit), running out of gas is a revert; the deployment is
+/// not limited. the executor takes one gas limit for all txs, so the call
+/// runs unlimited and is rejected if it has used more than `gas_limit`
+pub fn evm_verify_with_gas_limit(
+    deployment_code: Vec<u8>,
+    instances: Vec<Vec<Fr>>,
+    proof: Vec<u8>,
+    gas_limit: u64,
+) -> Result<EvmVerification, VerifierError> {
+    let calldata = build_calldata(&instances, &proof);
     // println!("proof calldata: {:?}", calldata);
     let mut evm = ExecutorBuilder::default()
         .with_gas_limit(u64::MAX.into())
@@ -115,9 +292,10 @@ pub fn evm_verify(deployment_code: Vec<u8>, instances: Vec<Vec<Fr>>, proof: Vec<
 
     let caller = Address::from_low_u64_be(0xfe);
     let deployment_result = evm.deploy(caller, deployment_code_bytes, 0.into());
-    dbg!(deployment_result.exit_reason);
 
-    let verifier_address = deployment_result.address.unwrap();
+    let verifier_address = deployment_result.address.ok_or_else(|| {
+        VerifierError::Deploy(format!("exit reason {:?}", deployment_result.exit_reason))
+    })?;
     // println!("calldata {:?}", &calldata);
     // println!("calldata string {:?}",
     // String::from_utf8(calldata.clone()).unwrap());
@@ -126,10 +304,145 @@ pub fn evm_verify(deployment_code: Vec<u8>, instances: Vec<Vec<Fr>>, proof: Vec<
 
     let result = evm.call_raw(caller, verifier_address, calldata_bytes, 0.into());
 
-    dbg!(result.gas_used);
-    dbg!(result.reverted);
-    dbg!(result.exit_reason);
+    if result.reverted {
+        return Err(VerifierError::Revert(format!(
+            "exit reason {:?}",
+            result.exit_reason
+        )));
+    }
+    if result.gas_used > gas_limit {
+        return Err(VerifierError::Revert(format!(
+            "out of gas: {} used, limit {gas_limit}",
+            result.gas_used
+        )));
+    }
+    Ok(EvmVerification {
+        gas_used: result.gas_used,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ethers::types::H256;
+    use halo2_proofs::{halo2curves::bn256::Bn256, poly::kzg::commitment::ParamsKZG};
+    use rand::rngs::OsRng;
+
+    use super::{
+        build_calldata, decode_calldata, gen_proof, gen_solidity_verifier, num_instance,
+        solidity_wrapper, verify_state_transition, VerifierError,
+    };
+    use crate::{
+        gadgets::gas_sum::GasSumChip, test_utils::Fp, verifier::circuit_deploy::keygen,
+        zkprover_circuit::ZkProverCircuit,
+    };
+
+    #[test]
+    fn test_calldata_round_trip() {
+        let instances = vec![vec![Fp::one(), -Fp::one()], vec![Fp::from(42u64)]];
+        let proof = vec![0xab; 7];
+
+        let calldata = build_calldata(&instances, &proof);
+        assert_eq!(calldata.len(), 3 * 32 + proof.len());
+        // words are big-endian
+        assert_eq!(calldata[31], 1);
+        assert_eq!(calldata[2 * 32 + 31], 42);
+
+        let (decoded, decoded_proof) = decode_calldata(&calldata, &[2, 1]).unwrap();
+        assert_eq!(decoded, instances);
+        assert_eq!(decoded_proof, proof);
+
+        assert!(decode_calldata(&calldata[..64], &[2, 1]).is_none());
+        // a word beyond the modulus is not an instance
+        let overflow = [0xff; 32];
+        assert!(decode_calldata(&overflow, &[1]).is_none());
+    }
+
+    #[test]
+    fn test_solidity_wrapper() {
+        let source = solidity_wrapper(&[0x60, 0x0a], 5);
+        assert!(source.contains("NUM_INSTANCES = 5;"));
+        assert!(source.contains(r#"hex"600a""#));
+        assert!(source.contains("function verifyProof(bytes calldata proof)"));
+    }
+
+    // require solc in PATH
+    #[test]
+    #[ignore]
+    fn test_solidity_verifier_verifies_proof() {
+        use ethers::{
+            abi::{self, Token},
+            solc::{CompilerInput, Solc},
+            utils::id,
+        };
+        use snark_verifier::loader::evm::{Address, ExecutorBuilder};
+
+        let params = ParamsKZG::<Bn256>::setup(7, OsRng);
+        let circuit = ZkProverCircuit::<Fp, 1>::fixture_circuit();
+        let pk = keygen(&params, circuit.clone()).unwrap();
+        let instances = circuit.instances();
+        let proof = gen_proof(&params, &pk, circuit, instances.clone()).unwrap();
+
+        let source = gen_solidity_verifier(&params, pk.get_vk(), num_instance(&instances)).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ZkProverVerifier.sol");
+        std::fs::write(&path, source).unwrap();
+        let input = CompilerInput::new(&path).unwrap().remove(0);
+        let output = Solc::default().compile_exact(&input).unwrap();
+        assert!(!output.has_error(), "{:?}", output.errors);
+        let bytecode = output
+            .get(path.to_str().unwrap(), "ZkProverVerifier")
+            .and_then(|contract| contract.bytecode().cloned())
+            .unwrap();
+
+        let mut evm = ExecutorBuilder::default()
+            .with_gas_limit(u64::MAX.into())
+            .build();
+        let caller = Address::from_low_u64_be(0xfe);
+        let wrapper = evm
+            .deploy(caller, bytecode.to_vec().into(), 0.into())
+            .address
+            .unwrap();
+
+        let mut tampered = instances.clone();
+        tampered[0][0] += Fp::one();
+        for (instances, expected) in [(instances, 1), (tampered, 0)] {
+            let call = [
+                &id("verifyProof(bytes)")[..],
+                &abi::encode(&[Token::Bytes(build_calldata(&instances, &proof))]),
+            ]
+            .concat();
+            let result = evm.call_raw(caller, wrapper, call.into(), 0.into());
+            assert!(!result.reverted);
+            assert_eq!(result.result[31], expected);
+        }
+    }
+
+    #[test]
+    fn test_verify_state_transition() {
+        let params = ParamsKZG::<Bn256>::setup(7, OsRng);
+        let mut circuit = ZkProverCircuit::<Fp, 1>::fixture_circuit();
+        circuit.mpt_root_after =
+            H256::from_str("9bf16634632e5e97feca7df7a6f8d3319d833fe101d63b6c081f5632b61a84a6")
+                .unwrap();
+        let (root_before, root_after) = (circuit.mpt_root_before, circuit.mpt_root_after);
+        let gas_sum = GasSumChip::gas_sum(&circuit.txs);
+
+        let pk = keygen(&params, circuit.clone()).unwrap();
+        let proof = gen_proof(&params, &pk, circuit.clone(), circuit.instances()).unwrap();
 
-    let success = !result.reverted;
-    assert!(success);
+        let verify = |before, after, gas| {
+            verify_state_transition(&params, pk.get_vk(), &proof, before, after, gas)
+        };
+        verify(root_before, root_after, gas_sum).unwrap();
+        assert!(matches!(
+            verify(root_after, root_before, gas_sum),
+            Err(VerifierError::Verify(_))
+        ));
+        assert!(matches!(
+            verify(root_before, root_after, gas_sum + Fp::one()),
+            Err(VerifierError::Verify(_))
+        ));
+    }
 }